@@ -0,0 +1,42 @@
+//! `bz2zstd index`: builds a block index file for a bzip2 file.
+//!
+//! The index lists every block of the input with its position in the compressed
+//! and decompressed data, so that parallel_bzip2 can decode the file without
+//! scanning it first and seek anywhere in it (see `parallel_bzip2::index`).
+
+use anyhow::{Context, Result};
+use clap::Args;
+use parallel_bzip2::{index_path, Bz2Decoder};
+use std::path::PathBuf;
+
+/// Arguments for the `index` subcommand.
+#[derive(Args, Debug)]
+pub struct IndexArgs {
+    /// Input bzip2 file
+    input: PathBuf,
+
+    /// Index file (optional, defaults to input file with .bz2 replaced by .bz2idx)
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Number of threads to use (default = number of logical cores)
+    #[arg(short = 'j', long)]
+    jobs: Option<usize>,
+}
+
+/// Decodes the input once and writes its block index.
+pub fn run(args: IndexArgs) -> Result<()> {
+    let mut builder = Bz2Decoder::builder();
+    if let Some(jobs) = args.jobs {
+        builder = builder.num_threads(jobs);
+    }
+    let index = builder
+        .build_index(&args.input)
+        .with_context(|| format!("Failed to index {}", args.input.display()))?;
+
+    let output = args.output.unwrap_or_else(|| index_path(&args.input));
+    index
+        .save(&output)
+        .with_context(|| format!("Failed to write index {}", output.display()))?;
+    Ok(())
+}
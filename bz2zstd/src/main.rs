@@ -12,13 +12,19 @@
 //! 2. **Worker pool**: Decompresses bzip2 blocks and compresses to zstd in parallel
 //! 3. **Writer thread**: Reorders and writes compressed blocks to output file
 //!
+//! When converting several files, their blocks go through the same scanner pool
+//! and worker pool one file after the other, each file with its own writer.
+//!
 //! # Performance
 //!
 //! - Memory-mapped I/O for efficient file access
-//! - Bounded channels prevent excessive memory usage
+//! - Bounded channels and a reorder window prevent excessive memory usage
 //! - Per-thread zstd compressors avoid lock contention
 //! - Scales linearly with CPU core count
 //!
+//! Input read from stdin cannot be mapped; it is decoded through a sliding window
+//! instead, with the same parallel decoding and compression (see [`stream`]).
+//!
 //! # Usage
 //!
 //! ```bash
@@ -30,35 +36,94 @@
 //!
 //! # Limit thread count
 //! bz2zstd input.bz2 -j 4
+//!
+//! # Convert many files, or every .bz2 file under a directory
+//! bz2zstd a.bz2 b.bz2 c.bz2
+//! bz2zstd -r dumps/
+//!
+//! # Read from stdin and write to stdout, e.g. in a pipeline
+//! cat input.bz2 | bz2zstd - -c > output.zst
+//!
+//! # Build a block index (input.bz2idx) for random access
+//! bz2zstd index input.bz2
+//!
+//! # Check the output against the input's block CRCs once written
+//! bz2zstd input.bz2 --verify
+//!
+//! # Append a seek table, for random access with parallel_bzip2::SeekableZstd
+//! bz2zstd input.bz2 --seekable
+//!
+//! # Salvage the intact blocks of a damaged file, with a JSON damage report
+//! bz2zstd recover damaged.bz2 -o salvaged.zst --report damage.json
 //! ```
 
 use anyhow::{Context, Result};
-use bzip2::read::BzDecoder;
-use clap::Parser;
-use crossbeam_channel::bounded;
-use memmap2::MmapOptions;
+use clap::{Parser, Subcommand};
+use crossbeam_channel::{bounded, unbounded, Sender};
+use memmap2::{Mmap, MmapOptions};
 use rayon::prelude::*;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
 
+mod index;
+mod progress;
+mod recover;
+mod stream;
+mod verify;
 mod writer;
-use parallel_bzip2::{extract_bits, MarkerType, Scanner};
+use parallel_bzip2::crc::stored_block_crc;
+use parallel_bzip2::{
+    decompress_block_merging, scan_blocks_shared_in, BlockSpan, CancellationToken, Error,
+    ReorderBuffer, ReorderWindow, Scanner, StreamCrc, DEFAULT_LEVEL,
+};
+use progress::{FileProgress, Progress, ProgressMode};
+use verify::FrameLog;
 use writer::OutputWriter;
 
+/// Input path that stands for stdin.
+const STDIN: &str = "-";
+
+/// Outcome of converting one candidate range: the bzip2 span it was decoded
+/// from and the compressed zstd data.
+type BlockResult = Result<(BlockSpan, Vec<u8>)>;
+
+/// Writer thread of one file, returning once the file is written or has failed.
+type Writer = thread::JoinHandle<Result<()>>;
+
 /// Command-line arguments for bz2zstd.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(
+    author,
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    /// Input bzip2 file
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Output file (optional, defaults to input file with .bz2 replaced by .zst)
+    /// Input bzip2 files, or `-` to read from stdin
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Convert the .bz2 files in directories given as inputs, recursively
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Output file (optional, defaults to input file with .bz2 replaced by .zst,
+    /// or to stdout when reading from stdin)
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// Write to stdout instead of a file
+    #[arg(short = 'c', long, conflicts_with = "output")]
+    stdout: bool,
+
     /// Zstd compression level (1-22, default = 3)
     /// Higher values provide better compression but are slower
     #[arg(short = 'z', long, default_value_t = 3)]
@@ -68,14 +133,43 @@ struct Args {
     #[arg(short = 'j', long)]
     jobs: Option<usize>,
 
+    /// Once each output is written, read it back and check every zstd frame
+    /// against the CRC of its bzip2 block; outputs that fail are removed
+    #[arg(long)]
+    verify: bool,
+
+    /// Write the zstd seekable format: a seek table listing every frame, with
+    /// its checksum, is appended to the output, so it can be read at any offset
+    #[arg(long)]
+    seekable: bool,
+
+    /// How to show progress on stderr: a bar if stderr is a terminal (auto), a
+    /// bar, a line every few seconds for logs (plain) or nothing (none)
+    #[arg(long, value_enum, default_value_t = ProgressMode::Auto)]
+    progress: ProgressMode,
+
     /// Benchmark mode: Only run the scanner and exit
     /// Useful for measuring scanner performance
     #[arg(long)]
     benchmark_scan: bool,
 }
 
+/// Commands other than the default conversion.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build a block index (.bz2idx) that lets parallel_bzip2 skip scanning the input
+    Index(index::IndexArgs),
+    /// Salvage the blocks of a damaged bzip2 file that pass their CRC
+    Recover(recover::RecoverArgs),
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
+    match args.command {
+        Some(Command::Index(index_args)) => return index::run(index_args),
+        Some(Command::Recover(recover_args)) => return recover::run(recover_args),
+        None => {}
+    }
 
     // Configure global thread pool if user specified thread count
     // This affects all Rayon parallel iterators in the application
@@ -86,226 +180,485 @@ fn main() -> Result<()> {
             .context("Failed to build global thread pool")?;
     }
 
-    // Memory-map the input file for efficient random access
-    // Benefits:
-    // - No need to load entire file into memory
-    // - OS handles paging and caching
-    // - Multiple threads can access without copying
-    let file = File::open(&args.input).context("Failed to open input file")?;
+    let from_stdin = args.inputs.iter().any(|input| input.as_os_str() == STDIN);
+    if args.inputs.len() > 1 && (from_stdin || args.benchmark_scan) {
+        anyhow::bail!("Reading from stdin and --benchmark-scan take a single input");
+    }
+
+    // Stdin cannot be mapped: decode it as it arrives
+    if from_stdin {
+        if args.benchmark_scan {
+            anyhow::bail!("--benchmark-scan needs an input file, not stdin");
+        }
+        let (out, output) = open_output(&args.inputs[0], args.output.as_deref(), true)?;
+        let verify = match (args.verify, &output) {
+            (false, _) => None,
+            (true, Some(output)) => Some(output.as_path()),
+            (true, None) => anyhow::bail!("--verify needs an output file to read back"),
+        };
+        let progress = Progress::start(args.progress, None);
+        let result = stream::convert(
+            io::stdin(),
+            out,
+            args.zstd_level,
+            progress.file(0),
+            verify,
+            args.seekable,
+        );
+        progress.finish();
+        if let (Err(_), Some(output)) = (&result, &output) {
+            let _ = fs::remove_file(output);
+        }
+        return result;
+    }
+
+    if args.benchmark_scan {
+        return benchmark_scan(&args.inputs[0]);
+    }
+
+    let inputs = expand_inputs(&args.inputs, args.recursive)?;
+    if inputs.len() != 1 && (args.output.is_some() || args.stdout) {
+        anyhow::bail!("-o and -c take a single input file");
+    }
+    if args.verify && args.stdout {
+        anyhow::bail!("--verify needs an output file to read back");
+    }
+    convert_files(
+        inputs,
+        args.output,
+        args.stdout,
+        args.zstd_level,
+        args.progress,
+        args.verify,
+        args.seekable,
+    )
+}
+
+/// Benchmark mode: measures scanner performance only.
+fn benchmark_scan(input: &Path) -> Result<()> {
+    let file = File::open(input).context("Failed to open input file")?;
     let mmap = unsafe {
         MmapOptions::new()
             .map(&file)
             .context("Failed to mmap input file")?
     };
 
-    // Benchmark mode: measure scanner performance only
-    if args.benchmark_scan {
-        let start = std::time::Instant::now();
-        let scanner = Scanner::new();
-
-        let (tx, rx) = bounded(1000); // Large buffer for benchmark
-        let mmap_ref = &mmap;
-
-        // Run scanner and count markers
-        thread::scope(|s| {
-            s.spawn(move || {
-                scanner.scan_stream(mmap_ref, 0, tx);
-            });
-
-            let mut count = 0;
-            // We don't need to reorder for benchmark, just count
-            for (_, markers) in rx {
-                count += markers.len();
-            }
+    let start = std::time::Instant::now();
+    let scanner = Scanner::new();
+
+    let (tx, rx) = bounded(1000); // Large buffer for benchmark
+    let mmap_ref = &mmap[..];
 
-            let elapsed = start.elapsed();
-            println!("Scanned {} markers in {:.2?}", count, elapsed);
-            let mb = mmap.len() as f64 / 1024.0 / 1024.0;
-            println!("Throughput: {:.2} MB/s", mb / elapsed.as_secs_f64());
+    // Run scanner and count markers
+    thread::scope(|s| {
+        s.spawn(move || {
+            scanner.scan_stream(mmap_ref, 0, DEFAULT_LEVEL, tx);
         });
-        return Ok(());
-    }
 
-    // === MAIN PIPELINE SETUP ===
-    //
-    // Three-stage pipeline:
-    // 1. Scanner thread: Finds block boundaries
-    // 2. Worker pool: Decompresses bzip2 → compresses zstd
-    // 3. Writer thread: Reorders and writes output
+        let mut count = 0;
+        // We don't need to reorder for benchmark, just count
+        for (_, markers) in rx {
+            count += markers.len();
+        }
 
-    // Channel for block boundaries (start_bit, end_bit)
-    // Bounded to prevent scanner from running too far ahead
-    // Small buffer maintains cache locality
-    let (task_sender, task_receiver) = bounded::<(u64, u64)>(100);
+        let elapsed = start.elapsed();
+        println!("Scanned {} markers in {:.2?}", count, elapsed);
+        let mb = mmap.len() as f64 / 1024.0 / 1024.0;
+        println!("Throughput: {:.2} MB/s", mb / elapsed.as_secs_f64());
+    });
+    Ok(())
+}
 
-    // Channel for compressed results (block_index, compressed_data)
-    // Sized at 2x thread count to allow buffering without excessive memory use
-    let (result_sender, result_receiver) =
-        bounded::<(usize, Vec<u8>)>(rayon::current_num_threads() * 2);
+/// A file whose blocks are being converted.
+///
+/// Shared by the blocks of the file in flight; once the last one is converted,
+/// dropping it closes the file's result channel and lets its writer finish.
+struct FileJob {
+    /// Mapped input, shared with the scanner and the writer
+    data: Arc<Mmap>,
+    /// Sends converted blocks to the file's writer
+    results: Sender<(usize, BlockResult)>,
+    /// Keeps the file's blocks within a fixed distance of its writer
+    window: Arc<ReorderWindow>,
+    /// Cancelled by the writer if it fails, so the file's other blocks are skipped
+    cancel: CancellationToken,
+}
 
-    // === STAGE 3: WRITER THREAD ===
-    //
-    // Receives compressed blocks from workers and writes them in order.
-    // Uses a HashMap to buffer out-of-order blocks.
-    let writer_handle = thread::spawn(move || -> Result<()> {
-        // Determine output file path
-        let output_path = if let Some(path) = args.output {
-            path
-        } else {
-            // Auto-generate output filename by replacing .bz2 with .zst
-            let input_str = args.input.to_string_lossy();
-            if input_str.ends_with("bz2") {
-                PathBuf::from(input_str.replace("bz2", "zst"))
-            } else {
-                let mut path = args.input.clone();
-                path.set_extension("zst");
-                path
-            }
-        };
+/// A file handed to the pipeline, reported in input order once its writer is done.
+struct Conversion {
+    input: PathBuf,
+    /// Output file, or `None` for stdout or if it was not created
+    output: Option<PathBuf>,
+    /// The file's writer thread, or why the file could not be started
+    writer: Result<Writer>,
+}
+
+/// Converts the given files, reporting each one and carrying on after failures.
+///
+/// All files go through one pipeline, so their blocks share the worker pool: the
+/// next file is scanned and dispatched as soon as the previous one's blocks are,
+/// rather than once it has been written, so small files do not leave cores idle.
+/// A single failing input is returned as is; with several inputs, each failure is
+/// reported and the result only says how many failed.
+fn convert_files(
+    inputs: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    to_stdout: bool,
+    zstd_level: i32,
+    progress_mode: ProgressMode,
+    verify: bool,
+    seekable: bool,
+) -> Result<()> {
+    let single = inputs.len() == 1;
+    let input_len = inputs
+        .iter()
+        .filter_map(|input| fs::metadata(input).ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum();
+    let progress = Progress::start(progress_mode, Some(input_len));
+    // Scanning gets a pool of its own: the workers below wait on the scanners,
+    // which could not make progress if they needed the workers' threads
+    let scan_pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(rayon::current_num_threads())
+            .build()
+            .context("Failed to build scanner thread pool")?,
+    );
+    let (report_sender, report_receiver) = unbounded::<Conversion>();
+
+    let result = thread::scope(|s| {
+        let progress = &progress;
+        // === STAGES 1 AND 2: SCANNING AND WORKER POOL ===
+        //
+        // Files are started in order: each gets its own scanner thread, reorder
+        // window and writer thread (stage 3), and its blocks are chained into one
+        // stream for the workers.
+        s.spawn(move || {
+            use zstd::bulk::Compressor;
+            inputs
+                .into_iter()
+                .filter_map(|input| {
+                    let started = start_file(
+                        &input,
+                        output.as_deref(),
+                        to_stdout,
+                        progress,
+                        verify,
+                        seekable,
+                    );
+                    let (job, conversion) = match started {
+                        Ok((job, output, writer)) => (
+                            Some(job),
+                            Conversion {
+                                input,
+                                output,
+                                writer: Ok(writer),
+                            },
+                        ),
+                        Err(e) => (
+                            None,
+                            Conversion {
+                                input,
+                                output: None,
+                                writer: Err(e),
+                            },
+                        ),
+                    };
+                    let _ = report_sender.send(conversion);
+                    job
+                })
+                .flat_map(|job| {
+                    let window_job = job.clone();
+                    scan_blocks_shared_in(scan_pool.clone(), job.data.clone())
+                        .into_iter()
+                        .enumerate() // Add block index for reordering
+                        // Hold back blocks that are too far ahead of the file's
+                        // writer; skip the rest of the file if it failed
+                        .take_while(move |(idx, _)| {
+                            window_job.window.acquire(*idx, &window_job.cancel)
+                        })
+                        .map(move |(idx, block)| (job.clone(), idx, block))
+                })
+                .par_bridge() // Convert to parallel iterator using Rayon
+                .for_each_init(
+                    // Per-thread initialization: create buffers and compressor once per thread
+                    // This avoids lock contention and repeated allocations
+                    || {
+                        let mut compressor = Compressor::new(zstd_level).unwrap();
+                        // Seek tables take their checksums from the frames
+                        compressor.include_checksum(seekable).unwrap();
+                        (Vec::new(), compressor)
+                    },
+                    |(decomp_buf, compressor), (job, idx, block)| {
+                        // Decompress the bzip2 block and check it against its stored CRC,
+                        // merging it with the next ranges if it was split by a false
+                        // positive. Decode failures are sent to the writer instead of
+                        // stopping the pool: the range may be the tail of a block that
+                        // another worker recovered by merging.
+                        let result = decompress_block_merging(&job.data, block, decomp_buf)
+                            .with_context(|| format!("Failed to decode block {}", idx))
+                            .and_then(|span| {
+                                // Compress to zstd using per-thread compressor
+                                let compressed = compressor
+                                    .compress(decomp_buf)
+                                    .context("Failed to compress chunk")?;
+                                Ok((span, compressed))
+                            });
+
+                        // A writer only hangs up once it has failed, which it reports
+                        let _ = job.results.send((idx, result));
+                    },
+                );
+        });
 
-        let raw_out: Box<dyn Write + Send> =
-            Box::new(File::create(output_path).context("Failed to create output file")?);
-
-        let mut out = OutputWriter::new(raw_out)?;
-        // Buffer for out-of-order blocks
-        let mut buffer: HashMap<usize, Vec<u8>> = HashMap::new();
-        let mut next_idx = 0;
-
-        // Reordering loop: ensure blocks are written in correct order
-        for (idx, data) in result_receiver {
-            if idx == next_idx {
-                // This is the next expected block, write it immediately
-                out.write_all(&data)?;
-                next_idx += 1;
-
-                // Check if we have subsequent blocks buffered
-                while let Some(next_data) = buffer.remove(&next_idx) {
-                    out.write_all(&next_data)?;
-                    next_idx += 1;
+        // Report each file once it is written, in input order
+        let mut total = 0;
+        let mut failed = 0;
+        let mut first_error = None;
+        for conversion in report_receiver {
+            total += 1;
+            let result = conversion.writer.and_then(|writer| writer.join().unwrap());
+            let input = conversion.input.display();
+            match (result, &conversion.output) {
+                (Ok(()), _) if single => {}
+                (Ok(()), Some(output)) => {
+                    progress.println(format!("{} -> {}", input, output.display()))
+                }
+                (Ok(()), None) => progress.println(format!("{} -> stdout", input)),
+                (Err(e), output) => {
+                    failed += 1;
+                    // Don't leave a partial output that looks like a converted file
+                    if let Some(output) = output {
+                        let _ = fs::remove_file(output);
+                    }
+                    if single {
+                        first_error = Some(e);
+                    } else {
+                        progress.println(format!("{}: {:#}", input, e));
+                    }
                 }
-            } else {
-                // Out-of-order block, buffer it for later
-                buffer.insert(idx, data);
             }
         }
-        out.finish()?;
-        Ok(())
+
+        match first_error {
+            Some(e) => Err(e),
+            None if failed > 0 => Err(anyhow::anyhow!("{} of {} files failed", failed, total)),
+            None => Ok(()),
+        }
     });
+    progress.finish();
+    result
+}
 
-    // === STAGE 1: SCANNER THREAD ===
-    //
-    // Scans the bzip2 file for block boundaries and converts markers to block ranges.
-    std::thread::scope(|s| {
-        let mmap_ref = &mmap;
+/// Maps an input file, creates its output and starts its writer thread.
+///
+/// Returns the job its blocks are dispatched with, the output file if one was
+/// created, and the writer thread.
+fn start_file(
+    input: &Path,
+    output: Option<&Path>,
+    to_stdout: bool,
+    progress: &Progress,
+    verify: bool,
+    seekable: bool,
+) -> Result<(Arc<FileJob>, Option<PathBuf>, Writer)> {
+    // Memory-map the input file for efficient random access
+    // Benefits:
+    // - No need to load entire file into memory
+    // - OS handles paging and caching
+    // - Multiple threads can access without copying
+    // - Shared with the writer thread, which needs it to verify stream CRCs
+    let file = File::open(input).context("Failed to open input file")?;
+    if file.metadata()?.is_dir() {
+        anyhow::bail!("Is a directory (use -r to convert the .bz2 files in it)");
+    }
+    let mmap = Arc::new(unsafe {
+        MmapOptions::new()
+            .map(&file)
+            .context("Failed to mmap input file")?
+    });
+    // Anything else would scan as no blocks at all and convert to an empty output
+    if !matches!(mmap.get(..4), Some([b'B', b'Z', b'h', b'1'..=b'9'])) {
+        return Err(Error::NotBzip2 { byte_offset: 0 }.into());
+    }
+    let (out, output) = open_output(input, output, to_stdout)?;
 
-        s.spawn(move || {
-            let scanner = Scanner::new();
-            // Small buffer for chunks to prevent scanning too far ahead
-            // This maintains cache locality
-            let (chunk_tx, chunk_rx) = bounded(4);
-
-            // Spawn the actual scanning in a background thread
-            s.spawn(move || {
-                scanner.scan_stream(mmap_ref, 0, chunk_tx);
-            });
-
-            // Convert markers to block boundaries
-            // Markers come as (position, type) where type is Block or Eos
-            // We convert these to (start_bit, end_bit) ranges
-            let mut chunk_buffer: HashMap<usize, Vec<(u64, MarkerType)>> = HashMap::new();
-            let mut next_chunk_idx = 0;
-            let mut current_block_start: Option<u64> = None;
-
-            for (idx, markers) in chunk_rx {
-                chunk_buffer.insert(idx, markers);
-
-                // Process chunks in order
-                while let Some(markers) = chunk_buffer.remove(&next_chunk_idx) {
-                    for (marker_pos, mtype) in markers {
-                        match mtype {
-                            MarkerType::Block => {
-                                // Block marker: end previous block (if any) and start new one
-                                if let Some(start) = current_block_start {
-                                    if task_sender.send((start, marker_pos)).is_err() {
-                                        return; // Workers stopped, exit
-                                    }
-                                }
-                                current_block_start = Some(marker_pos);
-                            }
-                            MarkerType::Eos => {
-                                // End-of-stream marker: end current block
-                                if let Some(start) = current_block_start {
-                                    if task_sender.send((start, marker_pos)).is_err() {
-                                        return;
-                                    }
-                                    current_block_start = None;
-                                }
-                            }
-                        }
-                    }
-                    next_chunk_idx += 1;
-                }
-            }
+    // Channel for compressed results (block_index, Result<(decoded_span, compressed_data)>)
+    // Sized at 2x thread count to allow buffering without excessive memory use
+    let threads = rayon::current_num_threads();
+    let (result_sender, result_receiver) = bounded::<(usize, BlockResult)>(threads * 2);
 
-            // Handle edge case: block without EOS marker (truncated file)
-            if let Some(start) = current_block_start {
-                let end = (mmap_ref.len() as u64) * 8;
-                let _ = task_sender.send((start, end));
-            }
-        });
+    // Workers only start a block within this many blocks of the next one to be
+    // written, which bounds the writer's reorder buffer even if one block is slow.
+    // Cancelled by the writer if it fails, so the workers skip the file's other blocks.
+    let window = Arc::new(ReorderWindow::new(threads * 2));
+    let cancel = CancellationToken::new();
 
-        // === STAGE 2: WORKER POOL ===
-        //
-        // Parallel workers that decompress bzip2 blocks and compress to zstd.
-        // Each worker has its own decompression buffer and zstd compressor to avoid contention.
-        use zstd::bulk::Compressor;
-        task_receiver
-            .into_iter()
-            .enumerate() // Add block index for reordering
-            .par_bridge() // Convert to parallel iterator using Rayon
-            .try_for_each_init(
-                // Per-thread initialization: create buffers and compressor once per thread
-                // This avoids lock contention and repeated allocations
-                || (Vec::new(), Compressor::new(args.zstd_level).unwrap()),
-                |(decomp_buf, compressor), (idx, (start_bit, end_bit))| -> Result<()> {
-                    // Extract the compressed bzip2 block bits
-                    let mut block_data = Vec::new();
-                    extract_bits(&mmap, start_bit, end_bit, &mut block_data);
-
-                    // Wrap with bzip2 header (BZh9 = highest compression level)
-                    let mut wrapped_data = Vec::with_capacity(4 + block_data.len());
-                    wrapped_data.extend_from_slice(b"BZh9");
-                    wrapped_data.append(&mut block_data);
-
-                    // Decompress the bzip2 block
-                    // Note: Last block may not have EOS marker, causing UnexpectedEof
-                    decomp_buf.clear();
-                    let mut decoder = BzDecoder::new(&wrapped_data[..]);
-                    match decoder.read_to_end(decomp_buf) {
-                        Ok(_) => {}
-                        // Expected for last block without EOS marker
-                        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
-                        Err(e) => return Err(e).context("Failed to decompress block"),
-                    }
+    // === STAGE 3: WRITER THREAD ===
+    //
+    // Receives compressed blocks from workers and writes them in order.
+    // Buffers out-of-order blocks in a ReorderBuffer, bounded by the reorder window.
+    let writer_mmap = mmap.clone();
+    let writer_window = window.clone();
+    let writer_cancel = cancel.clone();
+    let file_progress = progress.file(mmap.len() as u64);
+    let verify_path = output.clone().filter(|_| verify);
+    let writer = thread::spawn(move || {
+        let result = write_output(
+            out,
+            result_receiver,
+            &writer_mmap,
+            &writer_window,
+            file_progress,
+            verify_path.as_deref(),
+            seekable,
+        );
+        if result.is_err() {
+            writer_cancel.cancel();
+        }
+        result
+    });
 
-                    // Compress to zstd using per-thread compressor
-                    let compressed = compressor
-                        .compress(decomp_buf)
-                        .context("Failed to compress chunk")?;
+    let job = Arc::new(FileJob {
+        data: mmap,
+        results: result_sender,
+        window,
+        cancel,
+    });
+    Ok((job, output, writer))
+}
 
-                    // Send to writer thread with block index for reordering
-                    result_sender
-                        .send((idx, compressed))
-                        .context("Failed to send compressed data")?;
-                    Ok(())
-                },
-            )?;
+/// Lists the files to convert: the inputs, with directories replaced by the
+/// bzip2 files found under them if `recursive` is set.
+///
+/// Other directories are kept, to be reported as failures.
+fn expand_inputs(inputs: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if recursive && input.is_dir() {
+            collect_bz2_files(input, &mut files)?;
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
 
-        Ok::<(), anyhow::Error>(())
-    })?;
+/// Appends the .bz2 and .tbz2 files under `dir` to `files`, in path order.
+///
+/// Symbolic links to directories are not followed, so links cannot form a cycle.
+fn collect_bz2_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?;
+    entries.sort_by_key(|entry| entry.path());
+
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            collect_bz2_files(&path, files)?;
+        } else if path.is_file()
+            && path
+                .extension()
+                .is_some_and(|ext| ext == "bz2" || ext == "tbz2")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
 
-    drop(result_sender);
-    writer_handle.join().unwrap()?;
+/// Writer stage: reorders compressed blocks and writes them to the output file.
+///
+/// Blocks pass through here in file order, so this is also where the combined
+/// CRC of each bzip2 stream is checked and where the reorder window is advanced.
+/// With `verify`, the output written to that path is then read back and checked.
+/// With `seekable`, a seek table of the frames is appended to the output.
+fn write_output(
+    raw_out: Box<dyn Write + Send>,
+    result_receiver: crossbeam_channel::Receiver<(usize, BlockResult)>,
+    data: &[u8],
+    window: &ReorderWindow,
+    mut progress: FileProgress,
+    verify: Option<&Path>,
+    seekable: bool,
+) -> Result<()> {
+    let mut out = OutputWriter::new(raw_out)?;
+    if seekable {
+        out = out.seekable();
+    }
+    let mut frames = FrameLog::default();
+    // Buffer for out-of-order blocks
+    let mut buffer = ReorderBuffer::new();
+    let mut stream_crc = StreamCrc::new();
+
+    // Reordering loop: ensure blocks are written in correct order
+    for (idx, result) in result_receiver {
+        // Results handed back are for ranges absorbed by a merged block
+        let _ = buffer.insert(idx, result);
+
+        // Write every block that is now next in order
+        let mut block_idx = buffer.next_index();
+        while let Some(result) = buffer.pop() {
+            let (span, compressed) = result?;
+            stream_crc.push_block(data, block_idx, span.start_bit, span.end_bit)?;
+            out.write_frame(&compressed)?;
+            progress.block(span.end_bit / 8, compressed.len());
+            if verify.is_some() {
+                let block_crc = stored_block_crc(data, span.start_bit)
+                    .with_context(|| format!("Block {} ends before its stored CRC", block_idx))?;
+                frames.push(compressed.len(), block_crc);
+            }
 
+            // Skip the ranges that turned out to be part of this block
+            buffer.skip(span.merged);
+            block_idx = buffer.next_index();
+            window.advance(block_idx);
+        }
+    }
+    out.finish()?;
+    if let Some(path) = verify {
+        frames.verify(path, seekable)?;
+    }
     Ok(())
 }
+
+/// Opens where the zstd output goes: stdout, the given file or the default one.
+///
+/// Returns the output along with its path, unless it is stdout. Like `zstd`,
+/// refuses to write compressed data to a terminal.
+fn open_output(
+    input: &Path,
+    output: Option<&Path>,
+    to_stdout: bool,
+) -> Result<(Box<dyn Write + Send>, Option<PathBuf>)> {
+    let path = match output {
+        Some(path) => path.to_path_buf(),
+        None if to_stdout => {
+            let stdout = io::stdout();
+            if stdout.is_terminal() {
+                anyhow::bail!(
+                    "Refusing to write compressed data to a terminal; use -o or redirect stdout"
+                );
+            }
+            return Ok((Box::new(io::BufWriter::new(stdout)), None));
+        }
+        None => default_output_path(input),
+    };
+    let file = File::create(&path)
+        .with_context(|| format!("Failed to create output file {}", path.display()))?;
+    Ok((Box::new(file), Some(path)))
+}
+
+/// Output path used when none is given: the input with .bz2 replaced by .zst.
+///
+/// Only the suffix is replaced, so directories named after bz2 are left alone.
+fn default_output_path(input: &Path) -> PathBuf {
+    let input_str = input.to_string_lossy();
+    if let Some(stem) = input_str.strip_suffix("bz2") {
+        PathBuf::from(format!("{}zst", stem))
+    } else {
+        input.with_extension("zst")
+    }
+}
@@ -0,0 +1,250 @@
+//! Progress reporting for conversions.
+//!
+//! Writers count the compressed input behind each block they write, the zstd
+//! bytes they write and the blocks themselves. A display thread samples these
+//! counters and draws them either as an `indicatif` progress bar or, for logs,
+//! as a line every few seconds. Counters are shared by every file of a batch, so
+//! the progress covers the whole batch.
+
+use clap::ValueEnum;
+use crossbeam_channel::{bounded, RecvTimeoutError, Sender};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the progress bar is redrawn.
+const BAR_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How often a line is printed with `--progress=plain`.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How progress is shown.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// A progress bar if stderr is a terminal, nothing otherwise
+    Auto,
+    /// A progress bar
+    Bar,
+    /// A line every few seconds, for logs
+    Plain,
+    /// Nothing
+    None,
+}
+
+/// Counts shared by the writers and the display thread.
+#[derive(Default)]
+struct Counters {
+    /// Compressed input of the blocks written, or of files done with
+    input: AtomicU64,
+    /// Zstd bytes written
+    output: AtomicU64,
+    /// Blocks written
+    blocks: AtomicU64,
+}
+
+/// Progress of a conversion, shown on stderr until [`finish`](Self::finish).
+pub struct Progress {
+    counters: Arc<Counters>,
+    /// Drawn progress bar, if any; also used to print above it
+    bar: Option<ProgressBar>,
+    /// Stops the display thread
+    stop: Option<(Sender<()>, thread::JoinHandle<()>)>,
+}
+
+impl Progress {
+    /// Starts showing progress, towards `input_len` compressed bytes if known.
+    pub fn start(mode: ProgressMode, input_len: Option<u64>) -> Self {
+        let mode = match mode {
+            ProgressMode::Auto if io::stderr().is_terminal() => ProgressMode::Bar,
+            ProgressMode::Auto => ProgressMode::None,
+            mode => mode,
+        };
+        let counters = Arc::new(Counters::default());
+        let bar = (mode == ProgressMode::Bar).then(|| new_bar(input_len));
+        let interval = match mode {
+            ProgressMode::Bar => BAR_INTERVAL,
+            ProgressMode::Plain => PLAIN_INTERVAL,
+            _ => {
+                return Self {
+                    counters,
+                    bar: None,
+                    stop: None,
+                }
+            }
+        };
+
+        let (stop_sender, stop_receiver) = bounded(1);
+        let display = Display {
+            counters: counters.clone(),
+            bar: bar.clone(),
+            input_len,
+            start: Instant::now(),
+        };
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(interval) {
+                display.show();
+            }
+            display.show();
+        });
+
+        Self {
+            counters,
+            bar,
+            stop: Some((stop_sender, handle)),
+        }
+    }
+
+    /// Returns a handle for the writer of a file of `len` compressed bytes.
+    pub fn file(&self, len: u64) -> FileProgress {
+        FileProgress {
+            counters: self.counters.clone(),
+            len,
+            done: 0,
+        }
+    }
+
+    /// Prints a line to stderr without breaking the progress bar.
+    pub fn println(&self, line: impl AsRef<str>) {
+        match &self.bar {
+            Some(bar) if !bar.is_hidden() => bar.println(line),
+            _ => eprintln!("{}", line.as_ref()),
+        }
+    }
+
+    /// Shows the final progress and stops updating it.
+    pub fn finish(self) {
+        if let Some((stop, handle)) = self.stop {
+            let _ = stop.send(());
+            handle.join().unwrap();
+        }
+        if let Some(bar) = self.bar {
+            bar.finish();
+        }
+    }
+}
+
+/// Progress of one file, updated by its writer.
+///
+/// Dropping it counts the rest of the file as done, so that the progress of a
+/// batch still adds up when a file fails or ends with padding after its last block.
+pub struct FileProgress {
+    counters: Arc<Counters>,
+    /// Size of the compressed file, or 0 if unknown
+    len: u64,
+    /// Compressed bytes counted so far
+    done: u64,
+}
+
+impl FileProgress {
+    /// Records a written block, ending at byte `input_end` of the compressed file
+    /// and taking `output_len` bytes of zstd output.
+    pub fn block(&mut self, input_end: u64, output_len: usize) {
+        if input_end > self.done {
+            self.counters
+                .input
+                .fetch_add(input_end - self.done, Ordering::Relaxed);
+            self.done = input_end;
+        }
+        self.counters
+            .output
+            .fetch_add(output_len as u64, Ordering::Relaxed);
+        self.counters.blocks.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for FileProgress {
+    fn drop(&mut self) {
+        if self.len > self.done {
+            self.counters
+                .input
+                .fetch_add(self.len - self.done, Ordering::Relaxed);
+        }
+    }
+}
+
+/// State of the display thread.
+struct Display {
+    counters: Arc<Counters>,
+    bar: Option<ProgressBar>,
+    input_len: Option<u64>,
+    start: Instant,
+}
+
+impl Display {
+    /// Updates the progress bar, or prints a line if there is none.
+    fn show(&self) {
+        let input = self.counters.input.load(Ordering::Relaxed);
+        let output = self.counters.output.load(Ordering::Relaxed);
+        let blocks = self.counters.blocks.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64().max(1e-3);
+        let ratio = if input > 0 {
+            output as f64 / input as f64
+        } else {
+            0.0
+        };
+        let output_rate = HumanBytes((output as f64 / elapsed) as u64);
+
+        // The bar works out the input rate and ETA from its position itself
+        if let Some(bar) = &self.bar {
+            bar.set_position(input);
+            bar.set_message(format!(
+                "out {}/s, ratio {:.3}, {} blocks",
+                output_rate, ratio, blocks
+            ));
+            return;
+        }
+
+        let input_rate = input as f64 / elapsed;
+        let done = match self.input_len {
+            Some(len) if len > 0 => {
+                let eta = if input > 0 {
+                    let remaining = len.saturating_sub(input) as f64 / input_rate;
+                    HumanDuration(Duration::from_secs_f64(remaining)).to_string()
+                } else {
+                    "unknown".to_string()
+                };
+                format!(
+                    "{}/{} ({:.0}%), ETA {}",
+                    HumanBytes(input),
+                    HumanBytes(len),
+                    input as f64 * 100.0 / len as f64,
+                    eta
+                )
+            }
+            _ => HumanBytes(input).to_string(),
+        };
+        eprintln!(
+            "[{}] {}, in {}/s, out {}/s, ratio {:.3}, {} blocks",
+            HumanDuration(self.start.elapsed()),
+            done,
+            HumanBytes(input_rate as u64),
+            output_rate,
+            ratio,
+            blocks
+        );
+    }
+}
+
+/// Creates a progress bar on stderr, with a length if the input size is known.
+fn new_bar(input_len: Option<u64>) -> ProgressBar {
+    let (bar, template) = match input_len {
+        Some(len) => (
+            ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::stderr()),
+            // The message goes last, where it is cut short in narrow terminals
+            "[{elapsed_precise}] [{bar:20}] {binary_bytes}/{binary_total_bytes} \
+             in {binary_bytes_per_sec}, ETA {eta}, {wide_msg}",
+        ),
+        None => (
+            ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr()),
+            "{spinner} [{elapsed_precise}] {binary_bytes} in {binary_bytes_per_sec}, {wide_msg}",
+        ),
+    };
+    bar.with_style(
+        ProgressStyle::with_template(template)
+            .expect("valid progress template")
+            .progress_chars("=> "),
+    )
+}
@@ -0,0 +1,142 @@
+//! Conversion of bzip2 data read from a stream, such as stdin.
+//!
+//! Input that cannot be memory-mapped is decoded by `Bz2Decoder::from_reader`,
+//! which scans it through a sliding window and decodes its blocks in parallel.
+//! The decoded blocks are then compressed on the worker pool, one zstd frame per
+//! block as for mapped files, and written in order. Memory use stays bounded by
+//! the decoder's budget and the reorder window, however long the stream is.
+
+use anyhow::{Context, Result};
+use crossbeam_channel::unbounded;
+use parallel_bzip2::{Bz2Decoder, ReorderBuffer, ReorderWindow};
+use std::cell::RefCell;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::thread;
+use zstd::bulk::Compressor;
+
+use crate::progress::FileProgress;
+use crate::verify::FrameLog;
+use crate::writer::OutputWriter;
+
+thread_local! {
+    /// Per-thread zstd compressor, so its context is reused across blocks.
+    static COMPRESSOR: RefCell<Option<Compressor<'static>>> = const { RefCell::new(None) };
+}
+
+/// Outcome of converting one block: where it ends in the input, in bytes, its
+/// stored CRC and the compressed zstd data.
+type BlockResult = Result<(u64, u32, Vec<u8>)>;
+
+/// Converts the bzip2 data read from `reader` to zstd, writing it to `out`.
+///
+/// With `verify`, the output written to that path is then read back and checked.
+/// With `seekable`, a seek table of the frames is appended to the output.
+pub fn convert<R>(
+    reader: R,
+    out: Box<dyn Write + Send>,
+    zstd_level: i32,
+    progress: FileProgress,
+    verify: Option<&Path>,
+    seekable: bool,
+) -> Result<()>
+where
+    R: Read + Send + 'static,
+{
+    let blocks = Bz2Decoder::from_reader(reader)
+        .context("Failed to read bzip2 input")?
+        .into_blocks();
+    // Cancelling the decoder also stops the loop below waiting on the window
+    let cancel = blocks.cancellation_token();
+
+    // Unbounded, since the window already limits what can be in flight and the
+    // compression tasks must never block the pool the decoder runs on
+    let (result_sender, result_receiver) = unbounded::<(usize, BlockResult)>();
+    let window = ReorderWindow::new(rayon::current_num_threads() * 2);
+
+    thread::scope(|s| {
+        let writer_handle = s.spawn(|| {
+            let result = write_blocks(out, result_receiver, &window, progress, verify, seekable);
+            if result.is_err() {
+                cancel.cancel();
+            }
+            result
+        });
+
+        // Blocks arrive decoded and in order, so they are taken from this thread
+        // rather than from the pool, which the decoder needs for decoding them.
+        // Only the compression is spread over the pool; the indices here count
+        // output frames.
+        rayon::in_place_scope(|pool| {
+            for (idx, block) in blocks.enumerate() {
+                if !window.acquire(idx, &cancel) {
+                    break;
+                }
+                let block = match block {
+                    Ok(block) => block,
+                    Err(e) => {
+                        let result = Err(e).context("Failed to decode bzip2 input");
+                        let _ = result_sender.send((idx, result));
+                        break;
+                    }
+                };
+                let result_sender = result_sender.clone();
+                pool.spawn(move |_| {
+                    let result = COMPRESSOR.with_borrow_mut(|compressor| {
+                        let compressor = match compressor {
+                            Some(compressor) => compressor,
+                            None => compressor.insert(Compressor::new(zstd_level)?),
+                        };
+                        // Seek tables take their checksums from the frames
+                        compressor.include_checksum(seekable)?;
+                        compressor.compress(&block.data)
+                    });
+                    let result =
+                        result.map(|compressed| (block.end_bit / 8, block.stored_crc, compressed));
+                    // The writer only hangs up once it has failed
+                    let _ = result_sender.send((idx, result.context("Failed to compress chunk")));
+                });
+            }
+        });
+
+        drop(result_sender);
+        writer_handle.join().unwrap()
+    })
+}
+
+/// Writer stage: writes the compressed blocks in order.
+///
+/// Stream CRCs are already checked by the decoder, which sees the blocks in order.
+fn write_blocks(
+    out: Box<dyn Write + Send>,
+    result_receiver: crossbeam_channel::Receiver<(usize, BlockResult)>,
+    window: &ReorderWindow,
+    mut progress: FileProgress,
+    verify: Option<&Path>,
+    seekable: bool,
+) -> Result<()> {
+    let mut out = OutputWriter::new(out)?;
+    if seekable {
+        out = out.seekable();
+    }
+    let mut frames = FrameLog::default();
+    let mut buffer = ReorderBuffer::new();
+
+    for (idx, result) in result_receiver {
+        let _ = buffer.insert(idx, result);
+        while let Some(result) = buffer.pop() {
+            let (input_end, block_crc, compressed) = result?;
+            out.write_frame(&compressed)?;
+            progress.block(input_end, compressed.len());
+            if verify.is_some() {
+                frames.push(compressed.len(), block_crc);
+            }
+            window.advance(buffer.next_index());
+        }
+    }
+    out.finish()?;
+    if let Some(path) = verify {
+        frames.verify(path, seekable)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,183 @@
+//! `--verify`: checks a written zstd output against the bzip2 input.
+//!
+//! Each zstd frame holds exactly one bzip2 block, so as frames are written the
+//! writer records their length and the CRC stored in the header of their block.
+//! Once the output is complete, it is read back and every frame is decompressed
+//! in parallel and its bzip2 block CRC recomputed. The input is not read again:
+//! the CRCs it contributes were picked up while converting it. A seekable
+//! output must also end with a seek table that lists exactly those frames.
+
+use anyhow::{Context, Result};
+use memmap2::MmapOptions;
+use parallel_bzip2::crc::block_crc;
+use parallel_bzip2::SeekTable;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::Path;
+use zstd::bulk::Decompressor;
+use zstd::zstd_safe;
+
+/// Environment variable that makes verification read back a damaged output.
+///
+/// A test hook: it lets the end-to-end tests check how the binary handles a
+/// failed verification, which a correct output never triggers.
+const DAMAGE_OUTPUT_VAR: &str = "BZ2ZSTD_TEST_DAMAGE_OUTPUT";
+
+/// A zstd frame written to the output.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// Compressed length of the frame
+    len: usize,
+    /// CRC stored in the bzip2 block the frame was compressed from
+    block_crc: u32,
+}
+
+/// Frames written to an output, in order, to be checked once it is complete.
+#[derive(Debug, Default)]
+pub struct FrameLog(Vec<Frame>);
+
+impl FrameLog {
+    /// Records a frame of `len` bytes holding a bzip2 block with the given CRC.
+    pub fn push(&mut self, len: usize, block_crc: u32) {
+        self.0.push(Frame { len, block_crc });
+    }
+
+    /// Reads the output at `path` back and checks every frame against its block.
+    ///
+    /// With `seekable`, the output must end with a seek table listing the frames.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the output cannot be read, has a different length than the
+    /// frames written, or a frame does not decompress to data with its block's CRC.
+    pub fn verify(&self, path: &Path, seekable: bool) -> Result<()> {
+        let frames_len: usize = self.0.iter().map(|frame| frame.len).sum();
+        let file = File::open(path).context("Failed to open output for verification")?;
+        let len = file.metadata()?.len();
+        if !seekable && len != frames_len as u64 {
+            anyhow::bail!(
+                "Verification failed: output is {} bytes, {} were written",
+                len,
+                frames_len
+            );
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let mmap = unsafe {
+            MmapOptions::new()
+                .map(&file)
+                .context("Failed to mmap output for verification")?
+        };
+        let mut output = Cow::Borrowed(&mmap[..]);
+        if std::env::var_os(DAMAGE_OUTPUT_VAR).is_some() {
+            output.to_mut()[frames_len / 2] ^= 0x40;
+        }
+        if seekable {
+            self.verify_seek_table(&output)?;
+        }
+
+        let mut offset = 0;
+        let frames: Vec<(usize, usize, Frame)> = self
+            .0
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                offset += frame.len;
+                (i, offset - frame.len, *frame)
+            })
+            .collect();
+
+        frames.into_par_iter().try_for_each_init(
+            Decompressor::new,
+            |decompressor, (i, offset, frame)| -> Result<()> {
+                let decompressor = decompressor
+                    .as_mut()
+                    .map_err(|e| anyhow::anyhow!("Failed to create zstd decompressor: {}", e))?;
+                let data = &output[offset..offset + frame.len];
+                let decompressed = zstd_safe::get_frame_content_size(data)
+                    .ok()
+                    .flatten()
+                    .context("zstd frame has no content size")
+                    .and_then(|size| Ok(decompressor.decompress(data, size as usize)?))
+                    .with_context(|| {
+                        format!(
+                            "Verification failed: zstd frame {} at byte {} does not decompress",
+                            i, offset
+                        )
+                    })?;
+                let crc = block_crc(&decompressed);
+                if crc != frame.block_crc {
+                    anyhow::bail!(
+                        "Verification failed: zstd frame {} at byte {} decompresses to \
+                         CRC {:#010x}, but its bzip2 block has {:#010x}",
+                        i,
+                        offset,
+                        crc,
+                        frame.block_crc
+                    );
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Checks that `data` ends with a seek table listing exactly the frames written.
+    fn verify_seek_table(&self, data: &[u8]) -> Result<()> {
+        let table = SeekTable::parse(data).context("Verification failed")?;
+        let matches = table.len() == self.0.len()
+            && table.compressed_len() + table.encoded_len() as u64 == data.len() as u64
+            && table
+                .entries()
+                .iter()
+                .zip(&self.0)
+                .all(|(entry, frame)| entry.compressed_size as usize == frame.len);
+        if !matches {
+            anyhow::bail!("Verification failed: seek table does not match the frames written");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zstd::bulk::Compressor;
+
+    #[test]
+    fn test_verify_detects_corrupted_frame() {
+        let blocks: Vec<Vec<u8>> = (0..2u8)
+            .map(|seed| {
+                (0..50_000u32)
+                    .map(|i| (i * 31 + i / 7) as u8 ^ seed)
+                    .collect()
+            })
+            .collect();
+        let mut compressor = Compressor::new(3).unwrap();
+        let mut frames = FrameLog::default();
+        let mut output = Vec::new();
+        for block in &blocks {
+            let compressed = compressor.compress(block).unwrap();
+            frames.push(compressed.len(), block_crc(block));
+            output.extend_from_slice(&compressed);
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("bz2zstd_test_verify_{}.zst", std::process::id()));
+        std::fs::write(&path, &output).unwrap();
+        frames.verify(&path, false).unwrap();
+
+        // Flip a byte in the middle of the second frame
+        output[frames.0[0].len + frames.0[1].len / 2] ^= 0x40;
+        std::fs::write(&path, &output).unwrap();
+        let result = frames.verify(&path, false);
+        let _ = std::fs::remove_file(&path);
+        let err = result.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Verification failed: zstd frame 1"),
+            "unexpected error: {:#}",
+            err
+        );
+    }
+}
@@ -2,7 +2,10 @@
 //!
 //! This module provides a thin wrapper around the output writer to provide
 //! a consistent interface and ensure proper cleanup via the `finish()` method.
+//! With `--seekable`, it also records the frames written and appends their seek
+//! table when finished.
 
+use parallel_bzip2::SeekTable;
 use std::io::{self, Write};
 
 /// Wrapper around an output writer.
@@ -10,7 +13,7 @@ use std::io::{self, Write};
 /// This newtype pattern provides:
 /// - Explicit `finish()` method for flushing and cleanup
 /// - Consistent error handling
-/// - Future extensibility (e.g., progress tracking, checksums)
+/// - An optional seek table of the frames written with [`write_frame`](Self::write_frame)
 ///
 /// # Examples
 ///
@@ -23,12 +26,35 @@ use std::io::{self, Write};
 /// writer.write_all(b"data").unwrap();
 /// writer.finish().unwrap();
 /// ```
-pub struct OutputWriter(Box<dyn Write + Send>);
+pub struct OutputWriter {
+    inner: Box<dyn Write + Send>,
+    seek_table: Option<SeekTable>,
+}
 
 impl OutputWriter {
     /// Creates a new output writer.
     pub fn new(writer: Box<dyn Write + Send>) -> io::Result<Self> {
-        Ok(OutputWriter(writer))
+        Ok(OutputWriter {
+            inner: writer,
+            seek_table: None,
+        })
+    }
+
+    /// Makes the output seekable: frames are recorded in a seek table, with
+    /// their checksums, which is written by [`finish`](Self::finish).
+    ///
+    /// Frames must then carry their content size and checksum.
+    pub fn seekable(mut self) -> Self {
+        self.seek_table = Some(SeekTable::new(true));
+        self
+    }
+
+    /// Writes a complete zstd frame, recording it in the seek table if any.
+    pub fn write_frame(&mut self, frame: &[u8]) -> io::Result<()> {
+        if let Some(table) = &mut self.seek_table {
+            table.push_frame(frame)?;
+        }
+        self.inner.write_all(frame)
     }
 
     /// Flushes and finalizes the output.
@@ -36,17 +62,20 @@ impl OutputWriter {
     /// This should be called when writing is complete to ensure all data
     /// is written to the underlying writer.
     pub fn finish(mut self) -> io::Result<()> {
-        self.0.flush()?;
+        if let Some(table) = &self.seek_table {
+            table.write_to(&mut self.inner)?;
+        }
+        self.inner.flush()?;
         Ok(())
     }
 }
 
 impl Write for OutputWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.write(buf)
+        self.inner.write(buf)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.0.flush()
+        self.inner.flush()
     }
 }
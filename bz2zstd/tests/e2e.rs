@@ -0,0 +1,202 @@
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use parallel_bzip2::SeekableZstd;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const BIN_PATH: &str = env!("CARGO_BIN_EXE_bz2zstd");
+
+/// Creates an empty directory for the files of one test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bz2zstd_e2e_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Pseudo-random data, so that it barely compresses and spans several blocks.
+fn generate_data(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = 0x2545_F491u32 ^ seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// Compresses `data` into a single stream, like bzip2.
+fn compress_bzip2(data: &[u8]) -> Vec<u8> {
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Compresses `data` into one stream per 900k chunk, like pbzip2.
+fn compress_pbzip2(data: &[u8]) -> Vec<u8> {
+    data.chunks(900_000).flat_map(compress_bzip2).collect()
+}
+
+fn decompress_zstd(path: &Path) -> Vec<u8> {
+    zstd::decode_all(fs::File::open(path).unwrap()).expect("zstd decompression failed")
+}
+
+#[test]
+fn test_e2e_zstd_conversion() {
+    let dir = test_dir("zstd");
+    let bz2_file = dir.join("test.bin.bz2");
+    let zstd_file = dir.join("test.zst");
+
+    let original = generate_data(500_000, 1);
+    fs::write(&bz2_file, compress_pbzip2(&original)).unwrap();
+
+    // Convert to zstd
+    let status = Command::new(BIN_PATH)
+        .arg(&bz2_file)
+        .arg("--output")
+        .arg(&zstd_file)
+        .status()
+        .expect("Failed to run bz2zstd");
+    assert!(status.success());
+
+    assert_eq!(decompress_zstd(&zstd_file), original);
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_e2e_large_file() {
+    let dir = test_dir("large");
+    let bz2_file = dir.join("test.bin.bz2");
+    let zstd_file = dir.join("test.zst");
+
+    // A single stream of several blocks, so that the block splitter is exercised
+    // rather than just the stream boundaries pbzip2 leaves
+    let original = generate_data(5 * 1024 * 1024, 2);
+    fs::write(&bz2_file, compress_bzip2(&original)).unwrap();
+
+    let status = Command::new(BIN_PATH)
+        .arg(&bz2_file)
+        .arg("--output")
+        .arg(&zstd_file)
+        .status()
+        .expect("Failed to run bz2zstd");
+    assert!(status.success(), "bz2zstd failed");
+
+    assert_eq!(decompress_zstd(&zstd_file), original, "output mismatch");
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_e2e_stdin_stdout() {
+    let dir = test_dir("stdin");
+    let bz2_file = dir.join("test.bin.bz2");
+    let zstd_file = dir.join("test.zst");
+
+    let original = generate_data(2 * 1024 * 1024, 3);
+    fs::write(&bz2_file, compress_pbzip2(&original)).unwrap();
+
+    // Convert as a filter: bz2zstd - -c < input.bz2 > output.zst
+    let status = Command::new(BIN_PATH)
+        .arg("-")
+        .arg("-c")
+        .stdin(fs::File::open(&bz2_file).unwrap())
+        .stdout(fs::File::create(&zstd_file).unwrap())
+        .status()
+        .expect("Failed to run bz2zstd");
+    assert!(status.success(), "bz2zstd failed");
+
+    assert_eq!(decompress_zstd(&zstd_file), original, "output mismatch");
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_e2e_batch_keeps_going_after_failures() {
+    let dir = test_dir("batch");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+
+    let files = [dir.join("a.bin"), dir.join("sub").join("b.bin")];
+    let mut originals = Vec::new();
+    for (seed, file) in files.iter().enumerate() {
+        let original = generate_data(1024 * 1024, 4 + seed as u32);
+        fs::write(file.with_extension("bin.bz2"), compress_pbzip2(&original)).unwrap();
+        originals.push(original);
+    }
+    // Not bzip2 at all, so its conversion fails
+    fs::write(dir.join("bad.bz2"), b"not bzip2").unwrap();
+
+    let status = Command::new(BIN_PATH)
+        .arg("-r")
+        .arg(&dir)
+        .status()
+        .expect("Failed to run bz2zstd");
+    assert!(!status.success(), "bz2zstd should report the failed file");
+    assert!(!dir.join("bad.zst").exists());
+
+    // The other files are converted regardless
+    for (file, original) in files.iter().zip(originals) {
+        let output = decompress_zstd(&file.with_extension("bin.zst"));
+        assert_eq!(output, original, "output mismatch");
+    }
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_e2e_failed_verify_removes_output() {
+    let dir = test_dir("verify");
+    let bz2_file = dir.join("test.bin.bz2");
+    let zstd_file = dir.join("test.zst");
+
+    let original = generate_data(1024 * 1024, 6);
+    fs::write(&bz2_file, compress_pbzip2(&original)).unwrap();
+
+    // The output is written correctly, but read back with a byte flipped
+    let output = Command::new(BIN_PATH)
+        .arg(&bz2_file)
+        .arg("-o")
+        .arg(&zstd_file)
+        .arg("--verify")
+        .env("BZ2ZSTD_TEST_DAMAGE_OUTPUT", "1")
+        .output()
+        .expect("Failed to run bz2zstd");
+    assert!(!output.status.success(), "bz2zstd should fail verification");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Verification failed"));
+    assert!(!zstd_file.exists(), "output was not removed");
+    assert!(bz2_file.exists());
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_e2e_seekable() {
+    let dir = test_dir("seekable");
+    let bz2_file = dir.join("test.bin.bz2");
+    let zstd_file = dir.join("test.zst");
+
+    let original = generate_data(2 * 1024 * 1024, 7);
+    fs::write(&bz2_file, compress_pbzip2(&original)).unwrap();
+
+    let status = Command::new(BIN_PATH)
+        .arg(&bz2_file)
+        .arg("-o")
+        .arg(&zstd_file)
+        .arg("--seekable")
+        .arg("--verify")
+        .status()
+        .expect("Failed to run bz2zstd");
+    assert!(status.success(), "bz2zstd failed");
+
+    // The seek table sits in a skippable frame at the end
+    let data = fs::read(&zstd_file).unwrap();
+    assert_eq!(data[data.len() - 4..], 0x8F92_EAB1u32.to_le_bytes());
+
+    // Plain zstd decoders skip it
+    assert_eq!(decompress_zstd(&zstd_file), original, "output mismatch");
+
+    // And it gives random access to the frames
+    let file = SeekableZstd::open(&zstd_file).unwrap();
+    let mut buf = vec![0; 10_000];
+    assert_eq!(file.read_at(1_000_000, &mut buf).unwrap(), buf.len());
+    assert_eq!(buf, original[1_000_000..1_010_000]);
+    let _ = fs::remove_dir_all(dir);
+}
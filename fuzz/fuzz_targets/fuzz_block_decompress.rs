@@ -1,7 +1,7 @@
 #![no_main]
 
 use libfuzzer_sys::fuzz_target;
-use parallel_bzip2::decompress_block;
+use parallel_bzip2::{decompress_block, BlockRange};
 use arbitrary::Arbitrary;
 
 #[derive(Arbitrary, Debug)]
@@ -9,6 +9,7 @@ struct FuzzInput {
     data: Vec<u8>,
     start_bit: u64,
     end_bit: u64,
+    level: u8,
 }
 
 fuzz_target!(|input: FuzzInput| {
@@ -32,16 +33,17 @@ fuzz_target!(|input: FuzzInput| {
     
     // Try to decompress the block
     // This should either succeed, return an error, or panic on bugs
-    let _ = decompress_block(&input.data, start_bit, end_bit);
+    let level = input.level % 10; // Include the invalid level 0
+    let _ = decompress_block(&input.data, BlockRange::new(start_bit, end_bit, level));
     
     // Test edge cases explicitly
     if start_bit == end_bit {
         // Zero-length range
-        let _ = decompress_block(&input.data, start_bit, start_bit);
+        let _ = decompress_block(&input.data, BlockRange::new(start_bit, start_bit, level));
     }
     
     if end_bit == max_bits {
         // Range extending to the very end
-        let _ = decompress_block(&input.data, start_bit, max_bits);
+        let _ = decompress_block(&input.data, BlockRange::new(start_bit, max_bits, level));
     }
 });
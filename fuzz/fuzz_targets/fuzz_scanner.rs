@@ -19,7 +19,8 @@ fuzz_target!(|data: &[u8]| {
     
     // Collect detected blocks with strict limits
     let mut blocks = Vec::new();
-    while let Ok((start_bit, end_bit)) = receiver.recv_timeout(Duration::from_millis(100)) {
+    while let Ok(block) = receiver.recv_timeout(Duration::from_millis(100)) {
+        let (start_bit, end_bit) = (block.start_bit, block.end_bit);
         // Check timeout
         if start.elapsed() > timeout {
             break;
@@ -28,6 +29,7 @@ fuzz_target!(|data: &[u8]| {
         // Verify that bit positions are sane
         assert!(start_bit <= end_bit, "Invalid bit range: {} > {}", start_bit, end_bit);
         assert!(end_bit <= (data.len() as u64) * 8, "End bit {} exceeds data length", end_bit);
+        assert!((1..=9).contains(&block.level), "Invalid level {}", block.level);
         
         blocks.push((start_bit, end_bit));
         
@@ -66,7 +66,7 @@ fn bench_e2e_memory_usage(c: &mut Criterion) {
         println!("Generating test file for memory benchmark...");
 
         let status = std::process::Command::new("dd")
-            .args(&[
+            .args([
                 "if=/dev/urandom",
                 &format!("of={}", filename),
                 "bs=1M",
@@ -77,7 +77,7 @@ fn bench_e2e_memory_usage(c: &mut Criterion) {
 
         if status.is_ok() && status.unwrap().success() {
             std::process::Command::new("bzip2")
-                .args(&["-k", "-f", "-9", filename])
+                .args(["-k", "-f", "-9", filename])
                 .status()
                 .expect("Failed to compress");
 
@@ -128,7 +128,7 @@ fn bench_e2e_full_pipeline(c: &mut Criterion) {
         println!("Generating test file for pipeline benchmark...");
 
         let status = std::process::Command::new("dd")
-            .args(&[
+            .args([
                 "if=/dev/urandom",
                 &format!("of={}", filename),
                 "bs=1M",
@@ -139,7 +139,7 @@ fn bench_e2e_full_pipeline(c: &mut Criterion) {
 
         if status.is_ok() && status.unwrap().success() {
             std::process::Command::new("bzip2")
-                .args(&["-k", "-f", "-9", filename])
+                .args(["-k", "-f", "-9", filename])
                 .status()
                 .expect("Failed to compress");
 
@@ -1,7 +1,7 @@
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
-use parallel_bzip2::scan_blocks;
+use parallel_bzip2::{scan_blocks, Bz2EncoderBuilder};
 use pprof::criterion::{Output, PProfProfiler};
-use std::fs;
+use std::fs::{self, File};
 use std::path::Path;
 use std::process::Command;
 
@@ -19,7 +19,7 @@ fn generate_test_file(size_mb: usize) -> String {
 
     // Create random data
     let status = Command::new("dd")
-        .args(&[
+        .args([
             "if=/dev/urandom",
             &format!("of={}", filename),
             "bs=1M",
@@ -34,7 +34,7 @@ fn generate_test_file(size_mb: usize) -> String {
 
     // Compress with bzip2
     let status = Command::new("bzip2")
-        .args(&["-k", "-f", "-9", &filename])
+        .args(["-k", "-f", "-9", &filename])
         .status();
 
     if status.is_err() || !status.unwrap().success() {
@@ -75,7 +75,7 @@ fn bench_scanner(c: &mut Criterion) {
 }
 
 fn bench_scanner_multistream(c: &mut Criterion) {
-    // Generate a multi-stream file using pbzip2 if available
+    // Generate a multi-stream file with the parallel encoder
     let filename = "../bench_scan_multistream.bin";
     let bz2_filename = format!("{}.bz2", filename);
 
@@ -84,7 +84,7 @@ fn bench_scanner_multistream(c: &mut Criterion) {
 
         // Create 10MB random data
         let status = Command::new("dd")
-            .args(&[
+            .args([
                 "if=/dev/urandom",
                 &format!("of={}", filename),
                 "bs=1M",
@@ -94,19 +94,14 @@ fn bench_scanner_multistream(c: &mut Criterion) {
             .status();
 
         if status.is_ok() && status.unwrap().success() {
-            // Try pbzip2 for multi-stream
-            let pbzip2_status = Command::new("pbzip2")
-                .args(&["-k", "-f", "-p4", filename])
-                .status();
-
-            if pbzip2_status.is_err() || !pbzip2_status.unwrap().success() {
-                // Fallback to regular bzip2
-                println!("pbzip2 not available, using bzip2 (single stream)");
-                Command::new("bzip2")
-                    .args(&["-k", "-f", filename])
-                    .status()
-                    .expect("Failed to compress");
-            }
+            // One stream per block, like pbzip2 writes
+            let mut encoder = Bz2EncoderBuilder::new()
+                .multi_stream(true)
+                .build(File::create(&bz2_filename).expect("Failed to create bench file"))
+                .expect("Failed to create encoder");
+            let mut input = File::open(filename).expect("Failed to open generated data");
+            std::io::copy(&mut input, &mut encoder).expect("Failed to compress");
+            encoder.finish().expect("Failed to compress");
 
             let _ = fs::remove_file(filename);
         }
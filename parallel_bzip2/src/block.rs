@@ -0,0 +1,591 @@
+//! Pure-Rust decoder for a single bzip2 block.
+//!
+//! A bzip2 block is self-contained: after the 48-bit magic and the block CRC come
+//! the origin pointer of the Burrows-Wheeler transform, the set of byte values
+//! used, up to six Huffman tables and the table selectors, and finally the
+//! Huffman-coded symbols. Decoding undoes each stage in turn:
+//!
+//! 1. **Huffman**: symbols are decoded with the table chosen for each group of 50
+//! 2. **MTF/RLE2**: runs of zeros (RUNA/RUNB) and move-to-front indices become bytes
+//! 3. **Inverse BWT**: the origin pointer and byte counts restore the original order
+//! 4. **RLE1**: runs of 4 equal bytes followed by a count are expanded
+//!
+//! [`BlockDecoder`] reads the block straight from the compressed data at its bit
+//! offset, so nothing is copied or realigned first, and reports why a block is
+//! invalid as a [`BlockError`]. Its checks mirror those of libbz2, so both accept
+//! and reject the same blocks.
+
+/// Number of symbols coded with one table before switching to the next selector.
+const GROUP_SIZE: usize = 50;
+
+/// Largest number of Huffman tables in a block.
+const MAX_GROUPS: usize = 6;
+
+/// Largest alphabet: 256 byte values, RUNA/RUNB share two of them, plus EOB.
+const MAX_ALPHA_SIZE: usize = 258;
+
+/// Longest Huffman code allowed.
+const MAX_CODE_LEN: usize = 20;
+
+/// Selectors beyond this many can never be used and are ignored, like libbz2 does.
+const MAX_SELECTORS: usize = 2 + 900_000 / GROUP_SIZE;
+
+/// Bits resolved by a single lookup in a [`HuffmanTable`].
+const LOOKUP_BITS: u32 = 10;
+
+const RUNA: u16 = 0;
+const RUNB: u16 = 1;
+
+/// Reasons a block cannot be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum BlockError {
+    /// The stream level is not between 1 and 9.
+    #[error("invalid bzip2 block level {0}")]
+    InvalidLevel(u8),
+    /// The data does not start with a block magic.
+    #[error("no block magic at the start of the block")]
+    BadMagic,
+    /// The block's data runs past the end of its range.
+    #[error("block data ends before the end-of-block symbol")]
+    UnexpectedEnd,
+    /// The symbol bitmap marks no byte values as used.
+    #[error("block uses no symbols")]
+    NoSymbols,
+    /// The number of Huffman tables is not between 2 and 6.
+    #[error("invalid number of Huffman tables: {0}")]
+    InvalidTableCount(u32),
+    /// The block has no table selectors.
+    #[error("block has no selectors")]
+    NoSelectors,
+    /// A selector refers to a table that does not exist.
+    #[error("selector refers to a missing Huffman table")]
+    InvalidSelector,
+    /// More symbols were coded than the selectors cover.
+    #[error("more symbol groups than selectors")]
+    SelectorsExhausted,
+    /// A Huffman code length is outside 1 to 20.
+    #[error("invalid Huffman code length")]
+    InvalidCodeLength,
+    /// The bits do not form a valid Huffman code.
+    #[error("invalid Huffman code")]
+    InvalidCode,
+    /// A run of zeros is longer than any block.
+    #[error("run length too long")]
+    RunTooLong,
+    /// The block holds more data than its stream level allows.
+    #[error("block larger than {limit} bytes")]
+    BlockTooLarge {
+        /// Maximum block size at the stream's level
+        limit: usize,
+    },
+    /// The origin pointer lies outside the block.
+    #[error("origin pointer {orig_ptr} outside block of {len} bytes")]
+    OrigPtrOutOfRange {
+        /// The stored origin pointer
+        orig_ptr: u32,
+        /// Number of bytes in the block before run-length decoding
+        len: usize,
+    },
+}
+
+/// Reads big-endian bit fields from a range of bits.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: u64,
+    end: u64,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8], start_bit: u64, end_bit: u64) -> Self {
+        Self {
+            data,
+            pos: start_bit,
+            end: end_bit.min(data.len() as u64 * 8),
+        }
+    }
+
+    /// Returns the next `count` bits (at most 32) without consuming them.
+    ///
+    /// Bits past the end of the data read as zero.
+    #[inline]
+    fn peek(&self, count: u32) -> u32 {
+        let byte = (self.pos / 8) as usize;
+        let word = match self.data.get(byte..byte + 8) {
+            Some(bytes) => u64::from_be_bytes(bytes.try_into().unwrap()),
+            None => {
+                let mut bytes = [0u8; 8];
+                let tail = self.data.get(byte..).unwrap_or_default();
+                bytes[..tail.len()].copy_from_slice(tail);
+                u64::from_be_bytes(bytes)
+            }
+        };
+        ((word << (self.pos % 8)) >> (64 - count)) as u32
+    }
+
+    #[inline]
+    fn consume(&mut self, count: u32) -> Result<(), BlockError> {
+        self.pos += u64::from(count);
+        if self.pos > self.end {
+            return Err(BlockError::UnexpectedEnd);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn bits(&mut self, count: u32) -> Result<u32, BlockError> {
+        let value = self.peek(count);
+        self.consume(count)?;
+        Ok(value)
+    }
+
+    #[inline]
+    fn bit(&mut self) -> Result<bool, BlockError> {
+        Ok(self.bits(1)? == 1)
+    }
+}
+
+/// Canonical Huffman decoding table, built the same way as libbz2's.
+struct HuffmanTable {
+    min_len: u32,
+    /// Largest code of each length, or negative if there is none
+    limit: [i32; MAX_CODE_LEN + 3],
+    /// Offset from a code of each length to its index in `perm`
+    base: [i32; MAX_CODE_LEN + 3],
+    /// Symbols ordered by code length
+    perm: Vec<u16>,
+    /// Symbol and length of codes up to `LOOKUP_BITS` long, indexed by the next
+    /// `LOOKUP_BITS` bits; zero for longer codes
+    lookup: Vec<u16>,
+}
+
+impl HuffmanTable {
+    fn new(lengths: &[u8]) -> Self {
+        let min_len = u32::from(*lengths.iter().min().unwrap());
+        let max_len = u32::from(*lengths.iter().max().unwrap());
+
+        let mut perm = Vec::with_capacity(lengths.len());
+        for len in min_len..=max_len {
+            perm.extend(
+                (0..lengths.len() as u16).filter(|&sym| u32::from(lengths[sym as usize]) == len),
+            );
+        }
+
+        let mut base = [0i32; MAX_CODE_LEN + 3];
+        for &len in lengths {
+            base[len as usize + 1] += 1;
+        }
+        for i in 1..base.len() {
+            base[i] += base[i - 1];
+        }
+
+        let mut limit = [0i32; MAX_CODE_LEN + 3];
+        let mut vec = 0i32;
+        for len in min_len as usize..=max_len as usize {
+            vec += base[len + 1] - base[len];
+            limit[len] = vec - 1;
+            vec <<= 1;
+        }
+        for len in min_len as usize + 1..=max_len as usize {
+            base[len] = ((limit[len - 1] + 1) << 1) - base[len];
+        }
+
+        let mut table = Self {
+            min_len,
+            limit,
+            base,
+            perm,
+            lookup: vec![0; 1 << LOOKUP_BITS],
+        };
+        for bits in 0..1u32 << LOOKUP_BITS {
+            let mut len = min_len;
+            while len <= LOOKUP_BITS {
+                let code = (bits >> (LOOKUP_BITS - len)) as i32;
+                if code <= table.limit[len as usize] {
+                    if let Some(sym) = table.symbol(code, len) {
+                        table.lookup[bits as usize] = ((len as u16) << 9) | sym;
+                    }
+                    break;
+                }
+                len += 1;
+            }
+        }
+        table
+    }
+
+    /// Returns the symbol of `code`, a code of length `len` within the limit.
+    #[inline]
+    fn symbol(&self, code: i32, len: u32) -> Option<u16> {
+        let index = code - self.base[len as usize];
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.perm.get(index))
+            .copied()
+    }
+
+    /// Decodes the next symbol.
+    #[inline]
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, BlockError> {
+        let entry = self.lookup[reader.peek(LOOKUP_BITS) as usize];
+        if entry != 0 {
+            reader.consume(u32::from(entry >> 9))?;
+            return Ok(entry & 0x1FF);
+        }
+
+        // Codes longer than the lookup, resolved one bit at a time like libbz2
+        let mut len = self.min_len;
+        let mut code = reader.bits(len)? as i32;
+        loop {
+            if len as usize > MAX_CODE_LEN {
+                return Err(BlockError::InvalidCode);
+            }
+            if code <= self.limit[len as usize] {
+                break;
+            }
+            len += 1;
+            code = (code << 1) | reader.bits(1)? as i32;
+        }
+        self.symbol(code, len).ok_or(BlockError::InvalidCode)
+    }
+}
+
+/// Decodes bzip2 blocks, reusing its working memory from one block to the next.
+///
+/// The largest blocks need 3.6MB of working memory, so keeping a decoder per
+/// thread avoids allocating that for every block.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_bzip2::{scan_blocks, BlockDecoder};
+///
+/// let data = std::fs::read("tests/fixtures/fib.bz2").unwrap();
+/// let block = scan_blocks(&data).recv().unwrap();
+///
+/// let mut decoder = BlockDecoder::new();
+/// let mut out = Vec::new();
+/// decoder
+///     .decode(&data, block.start_bit, block.end_bit, block.level, &mut out)
+///     .unwrap();
+/// assert!(!out.is_empty());
+/// ```
+#[derive(Debug, Default)]
+pub struct BlockDecoder {
+    /// Bytes of the block before the inverse BWT, then the BWT links
+    tt: Vec<u32>,
+}
+
+impl BlockDecoder {
+    /// Creates a decoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the block starting at `start_bit` and appends it to `out`.
+    ///
+    /// The block magic must be at `start_bit`, and the block must end at or
+    /// before `end_bit`. `level` is the level of the block's stream, which
+    /// limits the block size. The block CRC is not checked, see
+    /// [`crate::crc::verify_block`].
+    ///
+    /// Returns the bit offset right after the block's end-of-block symbol.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`BlockError::UnexpectedEnd`] if the block does not end by
+    /// `end_bit`, and the other [`BlockError`] variants for invalid blocks.
+    pub fn decode(
+        &mut self,
+        data: &[u8],
+        start_bit: u64,
+        end_bit: u64,
+        level: u8,
+        out: &mut Vec<u8>,
+    ) -> Result<u64, BlockError> {
+        if !(1..=9).contains(&level) {
+            return Err(BlockError::InvalidLevel(level));
+        }
+        let max_len = usize::from(level) * 100_000;
+        let mut reader = BitReader::new(data, start_bit, end_bit);
+
+        let magic = (u64::from(reader.bits(24)?) << 24) | u64::from(reader.bits(24)?);
+        if magic != crate::scanner::MAGIC_BLOCK {
+            return Err(BlockError::BadMagic);
+        }
+        reader.bits(32)?; // Block CRC, checked by the caller
+        let randomised = reader.bit()?;
+        let orig_ptr = reader.bits(24)?;
+
+        // Byte values used in the block, in two levels of 16
+        let in_use16 = reader.bits(16)?;
+        let mut seq_to_unseq = Vec::with_capacity(256);
+        for group in 0..16u32 {
+            if in_use16 & (0x8000 >> group) != 0 {
+                let in_use = reader.bits(16)?;
+                for byte in 0..16u32 {
+                    if in_use & (0x8000 >> byte) != 0 {
+                        seq_to_unseq.push((group * 16 + byte) as u8);
+                    }
+                }
+            }
+        }
+        if seq_to_unseq.is_empty() {
+            return Err(BlockError::NoSymbols);
+        }
+        let alpha_size = seq_to_unseq.len() + 2;
+
+        let tables = reader.bits(3)?;
+        if !(2..=MAX_GROUPS as u32).contains(&tables) {
+            return Err(BlockError::InvalidTableCount(tables));
+        }
+        let selector_count = reader.bits(15)? as usize;
+        if selector_count == 0 {
+            return Err(BlockError::NoSelectors);
+        }
+
+        // Selectors are move-to-front coded in unary
+        let mut table_order: Vec<u8> = (0..tables as u8).collect();
+        let mut selectors = Vec::with_capacity(selector_count.min(MAX_SELECTORS));
+        for i in 0..selector_count {
+            let mut index = 0;
+            while reader.bit()? {
+                index += 1;
+                if index >= tables as usize {
+                    return Err(BlockError::InvalidSelector);
+                }
+            }
+            if i < MAX_SELECTORS {
+                let table = table_order[index];
+                table_order.copy_within(0..index, 1);
+                table_order[0] = table;
+                selectors.push(table);
+            }
+        }
+
+        // Code lengths are delta coded from a 5-bit starting value
+        let mut huffman = Vec::with_capacity(tables as usize);
+        let mut lengths = [0u8; MAX_ALPHA_SIZE];
+        for _ in 0..tables {
+            let mut len = reader.bits(5)?;
+            for length in &mut lengths[..alpha_size] {
+                loop {
+                    if !(1..=MAX_CODE_LEN as u32).contains(&len) {
+                        return Err(BlockError::InvalidCodeLength);
+                    }
+                    if !reader.bit()? {
+                        break;
+                    }
+                    if reader.bit()? {
+                        len -= 1;
+                    } else {
+                        len += 1;
+                    }
+                }
+                *length = len as u8;
+            }
+            huffman.push(HuffmanTable::new(&lengths[..alpha_size]));
+        }
+
+        // Huffman symbols to bytes, undoing the zero runs and move-to-front
+        let end_of_block = alpha_size as u16 - 1;
+        let mut mtf: [u8; 256] = std::array::from_fn(|i| i as u8);
+        let mut counts = [0u32; 256];
+        let tt = &mut self.tt;
+        tt.clear();
+        tt.reserve(max_len);
+
+        let mut groups = selectors.iter();
+        let mut table = &huffman[0];
+        let mut group_left = 0;
+        let mut run = 0u32;
+        let mut run_weight = 1u32;
+        loop {
+            if group_left == 0 {
+                let selector = groups.next().ok_or(BlockError::SelectorsExhausted)?;
+                table = &huffman[*selector as usize];
+                group_left = GROUP_SIZE;
+            }
+            group_left -= 1;
+            let sym = table.decode(&mut reader)?;
+
+            if sym == RUNA || sym == RUNB {
+                if run_weight >= 2 * 1024 * 1024 {
+                    return Err(BlockError::RunTooLong);
+                }
+                run += run_weight << sym;
+                run_weight <<= 1;
+                continue;
+            }
+            if run > 0 {
+                let byte = seq_to_unseq[mtf[0] as usize];
+                if tt.len() + run as usize > max_len {
+                    return Err(BlockError::BlockTooLarge { limit: max_len });
+                }
+                counts[byte as usize] += run;
+                tt.resize(tt.len() + run as usize, u32::from(byte));
+                run = 0;
+                run_weight = 1;
+            }
+            if sym == end_of_block {
+                break;
+            }
+
+            if tt.len() >= max_len {
+                return Err(BlockError::BlockTooLarge { limit: max_len });
+            }
+            let index = usize::from(sym - 1);
+            let value = mtf[index];
+            mtf.copy_within(0..index, 1);
+            mtf[0] = value;
+            let byte = seq_to_unseq.get(value as usize).copied().unwrap_or(0);
+            counts[byte as usize] += 1;
+            tt.push(u32::from(byte));
+        }
+
+        let len = tt.len();
+        if orig_ptr as usize >= len {
+            return Err(BlockError::OrigPtrOutOfRange { orig_ptr, len });
+        }
+
+        // Inverse BWT: link each position to the next one in the original order
+        let mut next = [0u32; 256];
+        let mut total = 0;
+        for (slot, &count) in next.iter_mut().zip(&counts) {
+            *slot = total;
+            total += count;
+        }
+        for i in 0..len {
+            let byte = (tt[i] & 0xFF) as usize;
+            tt[next[byte] as usize] |= (i as u32) << 8;
+            next[byte] += 1;
+        }
+
+        out.reserve(len);
+        let mut pos = tt[orig_ptr as usize] >> 8;
+        let mut random = Derandomiser::new(randomised);
+        let mut last = 0u8;
+        let mut repeats = 0;
+        for _ in 0..len {
+            let link = tt[pos as usize];
+            pos = link >> 8;
+            let byte = link as u8 ^ random.next_mask();
+
+            // Undo the initial run-length encoding. A block ending right after a
+            // run of four has no length for it, which libbz2 accepts too
+            if repeats == 4 {
+                out.resize(out.len() + usize::from(byte), last);
+                repeats = 0;
+                continue;
+            }
+            if repeats > 0 && byte == last {
+                repeats += 1;
+            } else {
+                last = byte;
+                repeats = 1;
+            }
+            out.push(byte);
+        }
+
+        Ok(reader.pos)
+    }
+}
+
+/// Undoes the randomisation that bzip2 0.9.0 applied to some blocks.
+struct Derandomiser {
+    enabled: bool,
+    to_go: u32,
+    index: usize,
+}
+
+impl Derandomiser {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            to_go: 0,
+            index: 0,
+        }
+    }
+
+    /// Returns the value to XOR with the next byte.
+    #[inline]
+    fn next_mask(&mut self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+        if self.to_go == 0 {
+            self.to_go = RANDOM_NUMBERS[self.index];
+            self.index = (self.index + 1) % RANDOM_NUMBERS.len();
+        }
+        self.to_go -= 1;
+        u8::from(self.to_go == 1)
+    }
+}
+
+/// The table of `BZ2_rNums` from libbz2.
+#[rustfmt::skip]
+const RANDOM_NUMBERS: [u32; 512] = [
+    619, 720, 127, 481, 931, 816, 813, 233, 566, 247, 985, 724, 205, 454, 863, 491,
+    741, 242, 949, 214, 733, 859, 335, 708, 621, 574, 73, 654, 730, 472, 419, 436,
+    278, 496, 867, 210, 399, 680, 480, 51, 878, 465, 811, 169, 869, 675, 611, 697,
+    867, 561, 862, 687, 507, 283, 482, 129, 807, 591, 733, 623, 150, 238, 59, 379,
+    684, 877, 625, 169, 643, 105, 170, 607, 520, 932, 727, 476, 693, 425, 174, 647,
+    73, 122, 335, 530, 442, 853, 695, 249, 445, 515, 909, 545, 703, 919, 874, 474,
+    882, 500, 594, 612, 641, 801, 220, 162, 819, 984, 589, 513, 495, 799, 161, 604,
+    958, 533, 221, 400, 386, 867, 600, 782, 382, 596, 414, 171, 516, 375, 682, 485,
+    911, 276, 98, 553, 163, 354, 666, 933, 424, 341, 533, 870, 227, 730, 475, 186,
+    263, 647, 537, 686, 600, 224, 469, 68, 770, 919, 190, 373, 294, 822, 808, 206,
+    184, 943, 795, 384, 383, 461, 404, 758, 839, 887, 715, 67, 618, 276, 204, 918,
+    873, 777, 604, 560, 951, 160, 578, 722, 79, 804, 96, 409, 713, 940, 652, 934,
+    970, 447, 318, 353, 859, 672, 112, 785, 645, 863, 803, 350, 139, 93, 354, 99,
+    820, 908, 609, 772, 154, 274, 580, 184, 79, 626, 630, 742, 653, 282, 762, 623,
+    680, 81, 927, 626, 789, 125, 411, 521, 938, 300, 821, 78, 343, 175, 128, 250,
+    170, 774, 972, 275, 999, 639, 495, 78, 352, 126, 857, 956, 358, 619, 580, 124,
+    737, 594, 701, 612, 669, 112, 134, 694, 363, 992, 809, 743, 168, 974, 944, 375,
+    748, 52, 600, 747, 642, 182, 862, 81, 344, 805, 988, 739, 511, 655, 814, 334,
+    249, 515, 897, 955, 664, 981, 649, 113, 974, 459, 893, 228, 433, 837, 553, 268,
+    926, 240, 102, 654, 459, 51, 686, 754, 806, 760, 493, 403, 415, 394, 687, 700,
+    946, 670, 656, 610, 738, 392, 760, 799, 887, 653, 978, 321, 576, 617, 626, 502,
+    894, 679, 243, 440, 680, 879, 194, 572, 640, 724, 926, 56, 204, 700, 707, 151,
+    457, 449, 797, 195, 791, 558, 945, 679, 297, 59, 87, 824, 713, 663, 412, 693,
+    342, 606, 134, 108, 571, 364, 631, 212, 174, 643, 304, 329, 343, 97, 430, 751,
+    497, 314, 983, 374, 822, 928, 140, 206, 73, 263, 980, 736, 876, 478, 430, 305,
+    170, 514, 364, 692, 829, 82, 855, 953, 676, 246, 369, 970, 294, 750, 807, 827,
+    150, 790, 288, 923, 804, 378, 215, 828, 592, 281, 565, 555, 710, 82, 896, 831,
+    547, 261, 524, 462, 293, 465, 502, 56, 661, 821, 976, 991, 658, 869, 905, 758,
+    745, 193, 768, 550, 608, 933, 378, 286, 215, 979, 792, 961, 61, 688, 793, 644,
+    986, 403, 106, 366, 905, 644, 372, 567, 466, 434, 645, 210, 389, 550, 919, 135,
+    780, 773, 635, 389, 707, 100, 626, 958, 165, 504, 920, 176, 193, 713, 857, 265,
+    203, 50, 668, 108, 645, 990, 626, 197, 510, 357, 358, 850, 858, 364, 936, 638,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_reader_unaligned() {
+        let data = [0b1010_1100, 0b0101_0011, 0xFF];
+        let mut reader = BitReader::new(&data, 3, 20);
+        assert_eq!(reader.bits(5).unwrap(), 0b01100);
+        assert_eq!(reader.bits(8).unwrap(), 0b0101_0011);
+        assert_eq!(reader.bits(4).unwrap(), 0b1111);
+        assert_eq!(reader.bits(1), Err(BlockError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn test_huffman_table_long_codes() {
+        // Lengths 1, 2, ... 11, 11: codes 0, 10, 110, ..., 11111111110, 11111111111
+        let mut lengths: Vec<u8> = (1..=11).collect();
+        lengths.push(11);
+        let table = HuffmanTable::new(&lengths);
+
+        let data = [0b0101_1011, 0b1111_1111, 0b1111_1111, 0b1110_0000];
+        let mut reader = BitReader::new(&data, 0, 32);
+        assert_eq!(table.decode(&mut reader).unwrap(), 0);
+        assert_eq!(table.decode(&mut reader).unwrap(), 1);
+        assert_eq!(table.decode(&mut reader).unwrap(), 2);
+        // Longer than a single lookup
+        assert_eq!(table.decode(&mut reader).unwrap(), 11);
+        assert_eq!(table.decode(&mut reader).unwrap(), 10);
+        assert_eq!(reader.pos, 1 + 2 + 3 + 11 + 11);
+    }
+}
@@ -0,0 +1,78 @@
+//! Cooperative cancellation of background decoding work.
+//!
+//! A [`CancellationToken`] is a cheap, cloneable flag shared between a caller and
+//! the threads doing work on its behalf. The threads check it between blocks, so
+//! cancelling stops scanning and decompression after the blocks already in progress.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Shared flag for stopping background work.
+///
+/// Clones refer to the same flag, so a token can be handed to another thread
+/// and cancelled from there. A [child token](Self::child_token) is also
+/// cancelled by its parent, but can be cancelled on its own.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_bzip2::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let handle = token.clone();
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    parent: Option<Box<CancellationToken>>,
+}
+
+impl CancellationToken {
+    /// Creates a token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that all work using this token stops.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` once [`cancel`](Self::cancel) has been called on any clone,
+    /// or on the token this one is a child of.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .parent
+                .as_ref()
+                .is_some_and(|parent| parent.is_cancelled())
+    }
+
+    /// Creates a token that is cancelled along with this one, but not the other
+    /// way around.
+    ///
+    /// Useful for stopping one piece of work without affecting the rest.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use parallel_bzip2::CancellationToken;
+    ///
+    /// let parent = CancellationToken::new();
+    /// let child = parent.child_token();
+    /// child.cancel();
+    /// assert!(!parent.is_cancelled());
+    ///
+    /// let child = parent.child_token();
+    /// parent.cancel();
+    /// assert!(child.is_cancelled());
+    /// ```
+    pub fn child_token(&self) -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::default(),
+            parent: Some(Box::new(self.clone())),
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! CRC verification for bzip2 blocks and streams.
+//!
+//! bzip2 protects its data with two levels of CRC:
+//!
+//! - **Block CRC**: a 32-bit CRC of the decompressed block contents, stored right
+//!   after the 48-bit block magic.
+//! - **Combined CRC**: a 32-bit value stored right after the end-of-stream magic,
+//!   computed by folding the block CRCs of the stream together in order.
+//!
+//! Because blocks are decompressed in isolation (and out of order), the underlying
+//! decompressor never sees the combined CRC, and a block that is cut short ends
+//! before its block CRC is checked. This module recomputes both so that corruption
+//! is reported instead of silently producing wrong output.
+
+use std::fmt;
+
+use crate::scanner::{read_u32_at, verify_magic, MAGIC_EOS};
+
+/// Lookup table for the bzip2 CRC (CRC-32 with polynomial 0x04C11DB7, MSB first).
+const CRC_TABLE: [u32; 256] = build_table();
+
+const fn build_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Computes the bzip2 block CRC of decompressed data.
+///
+/// # Examples
+///
+/// ```
+/// # use parallel_bzip2::crc::block_crc;
+/// assert_eq!(block_crc(b""), 0);
+/// assert_eq!(block_crc(b"123456789"), 0xFC89_1918);
+/// ```
+pub fn block_crc(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc = (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize];
+    }
+    !crc
+}
+
+/// Returns the CRC stored in the header of the block starting at `start_bit`.
+///
+/// `None` if the data ends before the CRC.
+pub fn stored_block_crc(data: &[u8], start_bit: u64) -> Option<u32> {
+    read_u32_at(data, start_bit + 48)
+}
+
+/// Folds a block CRC into a stream's running combined CRC.
+pub fn combine(combined: u32, block_crc: u32) -> u32 {
+    combined.rotate_left(1) ^ block_crc
+}
+
+/// A CRC stored in the bzip2 data does not match the decompressed output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcMismatch {
+    /// The CRC of a decompressed block differs from the one stored in its header.
+    Block {
+        /// Index of the block in the file, when known.
+        ///
+        /// Standalone calls such as `decompress_block` have no notion of block
+        /// order, so this is `None` for them.
+        block_index: Option<usize>,
+        /// Bit offset of the block magic.
+        bit_offset: u64,
+        /// CRC stored after the block magic.
+        stored: u32,
+        /// CRC of the decompressed data.
+        computed: u32,
+    },
+    /// The combined CRC stored after an end-of-stream marker differs from the
+    /// one recomputed from the stream's block CRCs.
+    Stream {
+        /// Index of the last block of the stream.
+        block_index: usize,
+        /// Bit offset of the end-of-stream magic.
+        bit_offset: u64,
+        /// Combined CRC stored after the end-of-stream magic.
+        stored: u32,
+        /// Combined CRC recomputed from the block CRCs.
+        computed: u32,
+    },
+}
+
+impl fmt::Display for CrcMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CrcMismatch::Block {
+                block_index,
+                bit_offset,
+                stored,
+                computed,
+            } => {
+                write!(f, "block CRC mismatch")?;
+                if let Some(index) = block_index {
+                    write!(f, " in block {}", index)?;
+                }
+                write!(
+                    f,
+                    " at bit {}: stored {:#010x}, computed {:#010x}",
+                    bit_offset, stored, computed
+                )
+            }
+            CrcMismatch::Stream {
+                block_index,
+                bit_offset,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "combined stream CRC mismatch after block {} at bit {}: stored {:#010x}, computed {:#010x}",
+                block_index, bit_offset, stored, computed
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CrcMismatch {}
+
+/// Checks the CRC of a decompressed block against the value stored in its header.
+///
+/// Returns the stored CRC on success, so it can be fed to [`StreamCrc`].
+pub(crate) fn verify_block(
+    data: &[u8],
+    block_index: Option<usize>,
+    start_bit: u64,
+    decompressed: &[u8],
+) -> Result<u32, CrcMismatch> {
+    let computed = block_crc(decompressed);
+    match read_u32_at(data, start_bit + 48) {
+        Some(stored) if stored == computed => Ok(stored),
+        stored => Err(CrcMismatch::Block {
+            block_index,
+            bit_offset: start_bit,
+            stored: stored.unwrap_or(0),
+            computed,
+        }),
+    }
+}
+
+/// Recomputes the combined CRC of each stream as blocks are consumed in order.
+///
+/// Feed every block to [`StreamCrc::push_block`] in file order, after its data
+/// has been decompressed and its block CRC verified. When a block ends at an
+/// end-of-stream marker, the combined CRC stored after that marker is checked
+/// and the running value is reset for the next stream.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::{crc::StreamCrc, decompress_block, scan_blocks};
+///
+/// let data = std::fs::read("file.bz2").unwrap();
+/// let mut stream_crc = StreamCrc::new();
+///
+/// for (index, block) in scan_blocks(&data).into_iter().enumerate() {
+///     let _decompressed = decompress_block(&data, block).unwrap();
+///     stream_crc
+///         .push_block(&data, index, block.start_bit, block.end_bit)
+///         .unwrap();
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct StreamCrc {
+    combined: u32,
+}
+
+impl StreamCrc {
+    /// Creates a tracker positioned at the start of a stream.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a tracker that resumes in the middle of a stream.
+    ///
+    /// `combined` is the value returned by [`combined`](Self::combined) after the
+    /// blocks before the resumption point were pushed.
+    pub fn resume(combined: u32) -> Self {
+        Self { combined }
+    }
+
+    /// Returns the combined CRC of the blocks pushed since the stream started.
+    pub fn combined(&self) -> u32 {
+        self.combined
+    }
+
+    /// Folds the next block into the combined CRC.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CrcMismatch::Stream`] if `end_bit` is an end-of-stream marker
+    /// and the stored combined CRC differs from the recomputed one.
+    pub fn push_block(
+        &mut self,
+        data: &[u8],
+        block_index: usize,
+        start_bit: u64,
+        end_bit: u64,
+    ) -> Result<(), CrcMismatch> {
+        let block_crc = stored_block_crc(data, start_bit).unwrap_or(0);
+        self.combined = combine(self.combined, block_crc);
+
+        if !verify_magic(data, end_bit, MAGIC_EOS) {
+            return Ok(());
+        }
+
+        let computed = std::mem::take(&mut self.combined);
+        let stored = read_u32_at(data, end_bit + 48);
+        match stored {
+            Some(stored) if stored == computed => Ok(()),
+            // A missing combined CRC means the file is truncated right after the
+            // marker; that is reported as a mismatch against zero.
+            stored => Err(CrcMismatch::Stream {
+                block_index,
+                bit_offset: end_bit,
+                stored: stored.unwrap_or(0),
+                computed,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_crc_matches_bzip2() {
+        // The rand.bz2 fixture stores 0x3ef9904c as the block CRC of "TEST\n"
+        assert_eq!(block_crc(b"TEST\n"), 0x3EF9_904C);
+    }
+
+    #[test]
+    fn test_combine_single_block() {
+        // A single-block stream's combined CRC equals its block CRC
+        assert_eq!(combine(0, 0x3EF9_904C), 0x3EF9_904C);
+    }
+}
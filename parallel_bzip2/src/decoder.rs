@@ -15,9 +15,12 @@
 //!
 //! - Parallel decompression scales with available CPU cores
 //! - Memory-mapped I/O for efficient file access
-//! - Bounded channels prevent excessive memory usage
+//! - A byte budget bounds the decoded data held in memory
 //! - Zero-copy design where possible
 //!
+//! Input that cannot be memory-mapped, such as stdin or a socket, is read through
+//! a sliding window instead (see [`Bz2Decoder::from_reader`]).
+//!
 //! # Example
 //!
 //! ```no_run
@@ -29,12 +32,89 @@
 //! decoder.read_to_end(&mut data).unwrap();
 //! ```
 
-use crossbeam_channel::{bounded, Receiver};
-use std::collections::HashMap;
-use std::io::{self, Read};
-use std::sync::Arc;
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::scanner::{self, MAGIC_EOS};
+use crate::segmenter::Segmenter;
+use crate::{
+    decompress_indexed_block_merging, spawn_block_scan, BlockIndex, BlockRange, BlockSpan,
+    CancellationToken, Error, IndexEntry, ReorderBuffer, ReorderWindow, Result, Scanner,
+    SourceInfo, StreamCrc,
+};
+
+/// Outcome of decoding one candidate range, as sent from the workers to the reader.
+type BlockResult = Result<(BlockSpan, Vec<u8>)>;
+
+/// Shared, thread-safe compressed data.
+type SharedData = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
+/// A candidate range to decode, along with the data it was found in.
+pub(crate) struct Job {
+    /// Data holding the range; the whole input, or a segment of it
+    pub(crate) data: SharedData,
+    /// Bit offset of `data` within the input, added to offsets in errors
+    pub(crate) offset_bits: u64,
+    /// The range, relative to `data`
+    pub(crate) block: BlockRange,
+    /// Number of following candidate ranges already known to be part of the block
+    pub(crate) absorbed: usize,
+}
+
+/// Where the driver thread gets its candidate ranges from.
+enum Source {
+    /// Data available in full, scanned in parallel
+    Data(SharedData),
+    /// Input read as it arrives, through a sliding window
+    Reader(Box<dyn Read + Send>),
+}
+
+/// Pipeline configuration, kept for restarting the pipeline after a seek.
+#[derive(Clone, Default)]
+struct Settings {
+    pool: Option<Arc<rayon::ThreadPool>>,
+    max_in_flight_bytes: Option<usize>,
+    reorder_window: Option<usize>,
+    scan_chunk_size: Option<usize>,
+}
 
-use crate::{decompress_block_into, scan_blocks};
+impl Settings {
+    fn scanner(&self) -> Scanner {
+        match self.scan_chunk_size {
+            Some(chunk_size) => Scanner::with_chunk_size(chunk_size),
+            None => Scanner::new(),
+        }
+    }
+}
+
+/// Messages sent from the background pipeline to the reader.
+enum Message {
+    /// Outcome of decoding the candidate range with the given index
+    Block(usize, Job, BlockResult),
+    /// Every candidate range has been dispatched; carries how many there were
+    Finished(usize),
+    /// The pipeline stopped after dispatching the given number of ranges
+    Failed(usize, Error),
+}
+
+/// Number of decoded blocks per thread held in memory by default.
+///
+/// Used for both the byte budget (in level-9 blocks) and the reorder window.
+const DEFAULT_BLOCKS_PER_THREAD: usize = 2;
+
+/// Estimated decoded size of a block in a stream with the given `BZhN` level.
+///
+/// Used as the reservation for a block before its decoded size is known. The level
+/// bounds a block's data before the final run-length decoding step, which can
+/// expand it up to 51 times, so a decoded block may be much larger than this.
+pub(crate) fn block_size_estimate(level: u8) -> usize {
+    level as usize * 100_000
+}
 
 /// Parallel bzip2 decoder implementing the `Read` trait.
 ///
@@ -42,32 +122,73 @@ use crate::{decompress_block_into, scan_blocks};
 /// ordering. It uses a background thread pool for decompression and buffers results
 /// to provide smooth streaming reads.
 ///
+/// Use [`Bz2Decoder::builder`] to run on a specific thread pool or to change the
+/// memory budget.
+///
 /// # Thread Safety
 ///
-/// The decoder spawns background threads for scanning and decompression. These threads
-/// are automatically cleaned up when the decoder is dropped (channels are closed).
+/// The decoder spawns background threads for scanning and decompression. Dropping
+/// the decoder cancels them: they finish the blocks already in progress and exit
+/// without scanning or decoding the rest of the input.
+///
+/// # Cancellation
+///
+/// [`Bz2Decoder::cancel`] stops the background work without dropping the decoder,
+/// and [`Bz2Decoder::cancellation_token`] hands out a token that can do the same
+/// from another thread. After cancellation, `read` fails with [`Error::Cancelled`].
 ///
 /// # Memory Management
 ///
-/// - Bounded channels limit memory usage even with fast decompression
-/// - The `data` field keeps the source data alive for the lifetime of the decoder
-/// - Pending blocks are buffered in a HashMap for reordering
+/// - Decoded blocks count against a byte budget until they have been read; no new
+///   block is dispatched while the budget is exhausted
+/// - Blocks are dispatched in file order and only within a fixed window of the next
+///   block to be read, so one slow block cannot make the reorder buffer grow with
+///   the file size
+/// - Compressed data is kept alive by the blocks referring to it
+/// - Pending blocks are buffered in a [`ReorderBuffer`] for reordering
+///
+/// # Integrity
+///
+/// Each block is checked against its stored CRC by the worker that decodes it, and
+/// the combined CRC of every stream is recomputed as blocks are read in order.
+///
+/// Errors are returned from `read` as `io::Error`s wrapping a [`crate::Error`],
+/// which `Error::from` turns back into the typed error. A CRC mismatch, for
+/// example, becomes an `InvalidData` error wrapping [`Error::CrcMismatch`].
+///
+/// Blocks that were split by a false-positive block magic are merged back together
+/// by the workers (see [`crate::decompress_block_merging`]); the reader then skips
+/// the candidate ranges that were absorbed.
+///
+/// `read` only reports end of stream once every block has been decoded. If the
+/// background threads stop early (e.g. a worker panics), the next `read` fails
+/// with [`Error::WorkerPanicked`]. Once a read fails, every later read fails too.
+///
+/// [`Bz2Decoder::into_blocks`] turns the decoder into an iterator over the decoded
+/// blocks and their position in the input.
+///
+/// Like `bzip2`, the decoder ignores trailing garbage after a complete stream.
+/// Streams found after such garbage are still decoded, but a failure there ends
+/// the output instead of being reported as an error.
 pub struct Bz2Decoder {
-    /// Source data kept alive for the decoder's lifetime.
-    /// The `#[allow(dead_code)]` is intentional - this field ensures the data
-    /// remains valid while background threads access it.
-    #[allow(dead_code)]
-    data: Arc<dyn AsRef<[u8]> + Send + Sync>,
-    /// Channel receiving decompressed blocks: (block_index, decompressed_data)
-    receiver: Receiver<(usize, Vec<u8>)>,
+    /// Decoded blocks in file order
+    blocks: DecodedBlocks,
+    /// Source data, kept for restarting the pipeline after a seek; `None` for readers
+    data: Option<SharedData>,
+    /// Configuration the pipeline was started with
+    settings: Settings,
+    /// Cancels the decoder, across pipeline restarts
+    cancel: CancellationToken,
     /// Current buffer being read from
     buffer: Vec<u8>,
+    /// Offset of the current buffer within the decompressed output
+    buffer_offset: u64,
     /// Position within the current buffer
     buffer_pos: usize,
-    /// Index of the next block we expect to read
-    next_block_idx: usize,
-    /// Out-of-order blocks waiting to be read
-    pending_blocks: HashMap<usize, Vec<u8>>,
+    /// Bytes of the current buffer still counted against the budget
+    buffer_reserved: usize,
+    /// Error that stopped the decoder, repeated on later reads
+    failed: Option<Error>,
 }
 
 impl Bz2Decoder {
@@ -83,8 +204,9 @@ impl Bz2Decoder {
     /// # Errors
     ///
     /// Returns an error if:
-    /// - The file cannot be opened
-    /// - Memory mapping fails (e.g., insufficient address space)
+    /// - The file cannot be opened ([`Error::Io`])
+    /// - Memory mapping fails, e.g. insufficient address space ([`Error::Io`])
+    /// - The file does not start with a bzip2 stream header ([`Error::NotBzip2`])
     ///
     /// # Safety
     ///
@@ -92,10 +214,8 @@ impl Bz2Decoder {
     /// - The file is opened read-only
     /// - The mmap is kept alive via Arc for the decoder's lifetime
     /// - No concurrent modifications to the file are expected
-    pub fn open<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<Self> {
-        let file = std::fs::File::open(path)?;
-        let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
-        Ok(Self::new(Arc::new(mmap)))
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self> {
+        Self::builder().open(path)
     }
 
     /// Creates a new decoder from any data source.
@@ -109,23 +229,27 @@ impl Bz2Decoder {
     /// The constructor sets up a three-stage pipeline:
     ///
     /// 1. **Driver thread**: Coordinates scanning and decompression
-    ///    - Calls `scan_blocks()` to get block boundaries
-    ///    - Feeds blocks to the worker pool via `par_bridge()`
+    ///    - Receives block boundaries from the scanner
+    ///    - Spawns one decode task per block, in file order, on the worker pool
     ///
-    /// 2. **Scanner thread** (inside `scan_blocks()`):
+    /// 2. **Scanner thread**:
     ///    - Scans data in parallel chunks
     ///    - Sends block boundaries to the driver
     ///
-    /// 3. **Worker pool** (Rayon global pool):
+    /// 3. **Worker pool** (Rayon global pool unless configured otherwise):
     ///    - Decompresses blocks in parallel
     ///    - Sends results with block indices for reordering
     ///
-    /// # Channel Sizing
+    /// # Memory Budget
     ///
-    /// The result channel is sized at `num_threads * 2` to:
-    /// - Prevent blocking workers when decompression is faster than reading
-    /// - Limit memory usage (each block can be several MB decompressed)
-    /// - Maintain good throughput without excessive buffering
+    /// The driver dispatches blocks in file order, and only once they are within a
+    /// window of two blocks per thread from the next block to be read. Before
+    /// dispatching a block, it also reserves an estimate of the block's size against
+    /// a budget of two level-9 blocks per thread. The reservation is corrected to the
+    /// real size once the block is decoded and released once it has been read, so:
+    /// - Workers keep busy while the reader catches up
+    /// - Memory use stays bounded (each block can be several MB decompressed)
+    /// - Workers never block, so the pool can be shared with other work
     ///
     /// # Arguments
     ///
@@ -138,47 +262,985 @@ impl Bz2Decoder {
     where
         T: AsRef<[u8]> + Send + Sync + 'static,
     {
-        // Channel for sending decompressed blocks back to the reader
-        // Sized at 2x thread count to allow some buffering without excessive memory use
-        let (result_sender, result_receiver) = bounded(rayon::current_num_threads() * 2);
-        let data_ref: Arc<dyn AsRef<[u8]> + Send + Sync> = data;
-        let data_clone = data_ref.clone();
+        Self::start(Source::Data(data), Settings::default(), BlockIndex::new())
+    }
 
-        // Spawn the driver thread that coordinates scanning and decompression
-        std::thread::spawn(move || {
-            let slice = data_clone.as_ref().as_ref();
-            // Get block boundaries from the scanner
-            let task_receiver = scan_blocks(slice);
-
-            // Parallel decompression using Rayon
-            // par_bridge() allows us to process an iterator in parallel
-            use rayon::prelude::*;
-            let _ = task_receiver
-                .into_iter()
-                .enumerate() // Add block index for reordering
-                .par_bridge() // Convert to parallel iterator
-                .try_for_each_init(
-                    Vec::new, // Thread-local scratch buffer (avoids allocations)
-                    |scratch, (idx, (start_bit, end_bit))| -> anyhow::Result<()> {
-                        let mut decomp_buf = Vec::new();
-                        // Decompress this block
-                        decompress_block_into(slice, start_bit, end_bit, &mut decomp_buf, scratch)?;
-                        // Send result with index for reordering
-                        result_sender.send((idx, decomp_buf)).unwrap();
-                        Ok(())
-                    },
-                );
-        });
+    /// Creates a decoder that reads compressed data from `reader` as it arrives.
+    ///
+    /// This works with sources that cannot be memory-mapped, such as stdin, a pipe
+    /// or a socket. The input is read in chunks and scanned as it arrives; each
+    /// block is dispatched to the worker pool once the marker ending it has been
+    /// read (and, unless it ends its stream, the following block's end too, so a
+    /// block split by a false-positive magic can still be merged back together).
+    ///
+    /// Input is dropped as soon as no undecoded block refers to it, so memory use
+    /// stays bounded by the reorder window and budget rather than the input size.
+    /// Reading the source stops while the pipeline is full.
+    ///
+    /// The first four bytes are read before returning, to check the stream header.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotBzip2`] if the input does not start with a bzip2 stream
+    /// header, or [`Error::Io`] if reading it fails. Later read errors are returned
+    /// from `read` once the blocks before them have been read.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use parallel_bzip2::Bz2Decoder;
+    /// use std::io::Read;
+    ///
+    /// let mut decoder = Bz2Decoder::from_reader(std::io::stdin()).unwrap();
+    /// let mut data = Vec::new();
+    /// decoder.read_to_end(&mut data).unwrap();
+    /// ```
+    pub fn from_reader<R>(reader: R) -> Result<Self>
+    where
+        R: Read + Send + 'static,
+    {
+        Self::builder().build_reader(reader)
+    }
 
+    /// Returns a builder for configuring the thread pool and memory budget.
+    pub fn builder() -> Bz2DecoderBuilder {
+        Bz2DecoderBuilder::new()
+    }
+
+    /// Spawns the background pipeline and returns the reading end.
+    fn start(source: Source, settings: Settings, index: BlockIndex) -> Self {
+        let data = match &source {
+            Source::Data(data) => Some(data.clone()),
+            Source::Reader(_) => None,
+        };
+        let cancel = CancellationToken::new();
         Self {
-            data: data_ref,
-            receiver: result_receiver,
+            blocks: DecodedBlocks::start(source, &settings, None, index, &cancel),
+            data,
+            settings,
+            cancel,
             buffer: Vec::new(),
+            buffer_offset: 0,
             buffer_pos: 0,
-            next_block_idx: 0,
-            pending_blocks: HashMap::new(),
+            buffer_reserved: 0,
+            failed: None,
+        }
+    }
+
+    /// Stops the background scanning and decompression.
+    ///
+    /// Blocks already being decompressed are finished and discarded. Every later
+    /// `read` fails with [`Error::Cancelled`].
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Returns a token that cancels this decoder, e.g. from another thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Returns the blocks decoded so far, by decompressed offset.
+    ///
+    /// The index grows as the decoder reads through the input and is what
+    /// [`Seek`] uses to jump back to blocks that have been decoded before.
+    pub fn index(&self) -> &BlockIndex {
+        self.blocks.index()
+    }
+
+    /// Returns the current position in the decompressed output.
+    fn position(&self) -> u64 {
+        self.buffer_offset + self.buffer_pos as u64
+    }
+
+    /// Makes `block` the current read buffer.
+    fn set_buffer(&mut self, block: DecodedBlock) {
+        self.blocks.budget.release(std::mem::replace(
+            &mut self.buffer_reserved,
+            block.data.len(),
+        ));
+        self.buffer_offset = block.decompressed_offset;
+        self.buffer = block.data;
+        self.buffer_pos = 0;
+    }
+
+    /// Loads the next block into the read buffer.
+    ///
+    /// Returns `Ok(false)` at the end of the output; the position then stays at
+    /// the end of the last block.
+    fn load_next_block(&mut self) -> Result<bool> {
+        match self.blocks.next_block()? {
+            Some(block) => {
+                self.set_buffer(block);
+                Ok(true)
+            }
+            None => {
+                self.buffer_pos = self.buffer.len();
+                Ok(false)
+            }
         }
     }
+
+    /// Restarts the pipeline at an indexed block and loads it into the buffer.
+    fn restart_at(&mut self, data: SharedData, entry: IndexEntry) -> Result<()> {
+        let index = std::mem::take(&mut self.blocks.index);
+        self.blocks
+            .budget
+            .release(std::mem::take(&mut self.buffer_reserved));
+        // Replacing the pipeline drops the old one, which stops its threads
+        self.blocks = DecodedBlocks::start(
+            Source::Data(data),
+            &self.settings,
+            Some(entry),
+            index,
+            &self.cancel,
+        );
+        self.buffer = Vec::new();
+        self.buffer_offset = entry.decompressed_offset;
+        self.buffer_pos = 0;
+        self.failed = None;
+        self.load_next_block()?;
+        Ok(())
+    }
+
+    /// Moves to `target` in the decompressed output.
+    ///
+    /// Blocks that have been indexed are reached by restarting the pipeline at the
+    /// block holding `target`. Otherwise decoding continues from the last indexed
+    /// block (or the current position, if that is further) and the blocks in
+    /// between are decoded and dropped. Seeking past the end stops at the end.
+    fn seek_to(&mut self, target: u64) -> Result<u64> {
+        if self.cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        // Within the current block: nothing to decode
+        let position = self.position();
+        let buffer_end = self.buffer_offset + self.buffer.len() as u64;
+        if target == position || (self.buffer_offset..buffer_end).contains(&target) {
+            self.buffer_pos = (target - self.buffer_offset) as usize;
+            return Ok(target);
+        }
+
+        if let Some(data) = self.data.clone() {
+            let index = &self.blocks.index;
+            if let Some(entry) = index.find(target).copied() {
+                self.restart_at(data, entry)?;
+                self.buffer_pos = (target - self.buffer_offset) as usize;
+                return Ok(target);
+            }
+            // Skip the indexed blocks instead of decoding them again
+            let complete = index.is_complete();
+            if let Some(&last) = index.entries().last() {
+                if last.decompressed_offset > position || complete {
+                    self.restart_at(data, last)?;
+                }
+            }
+        } else if target < position {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "cannot seek backwards in a bzip2 stream read from a reader",
+            )
+            .into());
+        }
+
+        // Decode forward until the block holding the target
+        while self.buffer_offset + (self.buffer.len() as u64) <= target {
+            if !self.load_next_block()? {
+                break;
+            }
+        }
+        let buffer_end = self.buffer_offset + self.buffer.len() as u64;
+        self.buffer_pos = (target.min(buffer_end) - self.buffer_offset) as usize;
+        Ok(self.position())
+    }
+
+    /// Turns the decoder into an iterator over the decoded blocks, in file order.
+    ///
+    /// The iterator starts with the block after the one `read` is currently in,
+    /// so this is normally called before reading anything.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use parallel_bzip2::Bz2Decoder;
+    ///
+    /// for block in Bz2Decoder::open("file.bz2").unwrap().into_blocks() {
+    ///     let block = block.unwrap();
+    ///     println!(
+    ///         "block {} of stream {} at bit {}: {} bytes",
+    ///         block.index,
+    ///         block.stream_index,
+    ///         block.start_bit,
+    ///         block.data.len()
+    ///     );
+    /// }
+    /// ```
+    pub fn into_blocks(self) -> DecodedBlocks {
+        self.blocks.budget.release(self.buffer_reserved);
+        self.blocks
+    }
+}
+
+/// A decompressed block along with where it was found.
+///
+/// Yielded in file order by [`DecodedBlocks`]. The block has been checked against
+/// its stored CRC, and the combined CRC of its stream once the stream ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedBlock {
+    /// Index of the block's candidate range among those found by the scanner.
+    ///
+    /// This is the index used in errors. A block that was split by a false-positive
+    /// magic covers several candidate ranges, so the next block skips their indices.
+    pub index: usize,
+    /// Index of the bzip2 stream containing the block, counting streams that have
+    /// blocks
+    pub stream_index: usize,
+    /// Bit offset of the block magic within the input
+    pub start_bit: u64,
+    /// Bit offset where the block ends within the input (exclusive)
+    pub end_bit: u64,
+    /// Block CRC stored after the block magic
+    pub stored_crc: u32,
+    /// Offset of the block's first byte within the decompressed output
+    pub decompressed_offset: u64,
+    /// Decompressed contents of the block
+    pub data: Vec<u8>,
+}
+
+/// Iterator over the decoded blocks of a bzip2 input, in file order.
+///
+/// Created by [`Bz2Decoder::into_blocks`]. Blocks are decoded in parallel by the
+/// same pipeline as [`Bz2Decoder`], with the same memory budget: a block counts
+/// against it until it has been returned from `next`.
+///
+/// An error ends the iteration. As with [`Bz2Decoder`], errors after trailing
+/// garbage end it without being reported. Dropping the iterator cancels the
+/// background work.
+pub struct DecodedBlocks {
+    /// Channel receiving decode results and completion from the pipeline
+    receiver: Receiver<Message>,
+    /// Out-of-order results waiting to be read, indexed by candidate range
+    pending: ReorderBuffer<(Job, BlockResult)>,
+    /// Combined CRC of the stream currently being read
+    stream_crc: StreamCrc,
+    /// Index of the stream currently being read
+    stream_index: usize,
+    /// Number of decompressed bytes returned so far
+    decompressed_offset: u64,
+    /// Number of candidate ranges, once the pipeline has dispatched them all
+    total_blocks: Option<usize>,
+    /// Why the pipeline stopped early, reported once the blocks before it are read
+    pipeline_error: Option<Error>,
+    /// Whether a complete stream was followed by something other than a stream
+    trailing_garbage: bool,
+    /// Set once all blocks have been read, the rest was trailing garbage or
+    /// reading failed
+    finished: bool,
+    /// Blocks read so far, by decompressed offset
+    index: BlockIndex,
+    /// Memory budget shared with the background threads
+    budget: Arc<Budget>,
+    /// Keeps the driver within a fixed number of blocks of the next one to read
+    window: Arc<ReorderWindow>,
+    /// Cancels the decoding, as seen by the caller
+    cancel: CancellationToken,
+    /// Stops the background threads; a child of `cancel`, cancelled on drop
+    pipeline: CancellationToken,
+}
+
+impl DecodedBlocks {
+    /// Spawns the background pipeline and returns the reading end.
+    ///
+    /// With `resume`, decoding starts at that block rather than the beginning of
+    /// the data, which must then be a [`Source::Data`].
+    fn start(
+        source: Source,
+        settings: &Settings,
+        resume: Option<IndexEntry>,
+        index: BlockIndex,
+        cancel: &CancellationToken,
+    ) -> Self {
+        let pool = settings.pool.clone();
+        let threads = pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            });
+        let limit = settings.max_in_flight_bytes.unwrap_or_else(|| {
+            threads * DEFAULT_BLOCKS_PER_THREAD * block_size_estimate(crate::DEFAULT_LEVEL)
+        });
+        let window = settings
+            .reorder_window
+            .unwrap_or(threads * DEFAULT_BLOCKS_PER_THREAD);
+
+        // Unbounded, since the budget already limits what can be in flight and
+        // workers must never block on a slow reader
+        let (result_sender, result_receiver) = unbounded();
+        let pipeline = cancel.child_token();
+        let shared = Arc::new(Shared {
+            budget: Arc::new(Budget::new(limit)),
+            window: Arc::new(ReorderWindow::new(window)),
+            cancel: pipeline.clone(),
+            results: result_sender,
+        });
+        let budget = shared.budget.clone();
+        let window = shared.window.clone();
+
+        // Indices continue from the block we resume at
+        let first_index = resume.map_or(0, |entry| entry.index);
+        let mut pending = ReorderBuffer::new();
+        pending.skip(first_index);
+        window.advance(first_index);
+
+        // A complete index already lists every block: no need to scan for them
+        let known = index.is_complete().then(|| {
+            let from = index
+                .entries()
+                .partition_point(|entry| entry.index < first_index);
+            index.entries()[from..].to_vec()
+        });
+
+        // Spawn the driver thread that coordinates scanning and decompression
+        let scanner = settings.scanner();
+        std::thread::spawn(move || shared.drive(source, scanner, pool, resume, known));
+
+        Self {
+            receiver: result_receiver,
+            pending,
+            stream_crc: StreamCrc::resume(resume.map_or(0, |entry| entry.stream_crc)),
+            stream_index: resume.map_or(0, |entry| entry.stream_index),
+            decompressed_offset: resume.map_or(0, |entry| entry.decompressed_offset),
+            total_blocks: None,
+            pipeline_error: None,
+            trailing_garbage: resume.is_some_and(|entry| entry.after_garbage),
+            finished: false,
+            index,
+            budget,
+            window,
+            cancel: cancel.clone(),
+            pipeline,
+        }
+    }
+
+    /// Returns the blocks read so far, by decompressed offset.
+    pub fn index(&self) -> &BlockIndex {
+        &self.index
+    }
+
+    /// Stops the background scanning and decompression.
+    ///
+    /// Blocks already being decompressed are finished and discarded. The next
+    /// call to `next` returns [`Error::Cancelled`].
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Returns a token that cancels the decoding, e.g. from another thread.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Drops a result that will not be read, returning its bytes to the budget.
+    fn discard(&self, result: BlockResult) {
+        if let Ok((_, block)) = result {
+            self.budget.release(block.len());
+        }
+    }
+
+    /// Turns the result of the next block in order into a [`DecodedBlock`].
+    ///
+    /// This is where the combined stream CRC is folded, since it depends on
+    /// seeing the blocks in file order, where ranges absorbed by a merged
+    /// block are skipped, and where trailing garbage after a stream is noticed.
+    /// The block's bytes stay counted against the budget.
+    fn take_block(&mut self, index: usize, job: Job, result: BlockResult) -> Result<DecodedBlock> {
+        let (span, block) = result?;
+
+        let data = job.data.as_ref().as_ref();
+        let entry = IndexEntry {
+            index,
+            stream_index: self.stream_index,
+            start_bit: job.offset_bits + span.start_bit,
+            end_bit: job.offset_bits + span.end_bit,
+            level: job.block.level,
+            stored_crc: scanner::read_u32_at(data, span.start_bit + 48).unwrap_or(0),
+            stream_crc: self.stream_crc.combined(),
+            after_garbage: self.trailing_garbage,
+            decompressed_offset: self.decompressed_offset,
+            decompressed_len: block.len() as u64,
+        };
+        self.stream_crc
+            .push_block(data, index, span.start_bit, span.end_bit)
+            .map_err(|mismatch| Error::from(mismatch).offset_by(job.offset_bits))?;
+        self.index.push(entry);
+        let decoded = DecodedBlock {
+            index,
+            stream_index: self.stream_index,
+            start_bit: job.offset_bits + span.start_bit,
+            end_bit: job.offset_bits + span.end_bit,
+            stored_crc: entry.stored_crc,
+            decompressed_offset: self.decompressed_offset,
+            data: block,
+        };
+        self.decompressed_offset += decoded.data.len() as u64;
+
+        if scanner::verify_magic(data, span.end_bit, MAGIC_EOS) {
+            self.stream_index += 1;
+            if !scanner::stream_followed_cleanly(data, span.end_bit) {
+                self.trailing_garbage = true;
+            }
+        }
+
+        // Drop results for ranges that turned out to be part of this block
+        for (_, result) in self.pending.skip(span.merged) {
+            self.discard(result);
+        }
+        self.window.advance(self.pending.next_index());
+        Ok(decoded)
+    }
+
+    /// Waits for the next block in order.
+    ///
+    /// Returns `Ok(None)` once every block has been read.
+    fn receive_block(&mut self) -> Result<Option<DecodedBlock>> {
+        loop {
+            // Check if we have the next expected block in pending blocks
+            let index = self.pending.next_index();
+            if let Some((job, result)) = self.pending.pop() {
+                return self.take_block(index, job, result).map(Some);
+            }
+            if self.total_blocks.is_some_and(|total| index >= total) {
+                // Every dispatched block has been read: report why dispatching stopped
+                return match self.pipeline_error.take() {
+                    Some(err) => Err(err),
+                    None => Ok(None),
+                };
+            }
+
+            // Receive blocks from the channel, buffering them until their turn
+            match self.receiver.recv() {
+                Ok(Message::Block(idx, job, result)) => {
+                    if let Some((_, result)) = self.pending.insert(idx, (job, result)) {
+                        // The range was absorbed by a merged block: drop it
+                        self.discard(result);
+                    }
+                }
+                Ok(Message::Finished(total)) => self.total_blocks = Some(total),
+                Ok(Message::Failed(_, Error::Cancelled)) => return Err(Error::Cancelled),
+                Ok(Message::Failed(dispatched, err)) => {
+                    self.total_blocks = Some(dispatched);
+                    self.pipeline_error = Some(err);
+                }
+                Err(_) if self.pipeline.is_cancelled() => return Err(Error::Cancelled),
+                Err(_) => {
+                    // The driver thread went away without saying it was done
+                    return Err(Error::WorkerPanicked {
+                        message: "decoder pipeline stopped before sending every block".into(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Returns the next block in order, applying the end-of-input rules.
+    ///
+    /// Returns `Ok(None)` once every block has been read, and after an error.
+    fn next_block(&mut self) -> Result<Option<DecodedBlock>> {
+        if self.finished {
+            return Ok(None);
+        }
+        if self.cancel.is_cancelled() {
+            self.finished = true;
+            return Err(Error::Cancelled);
+        }
+        match self.receive_block() {
+            Ok(Some(block)) => Ok(Some(block)),
+            Ok(None) => {
+                self.finished = true;
+                self.index.set_complete();
+                Ok(None)
+            }
+            // Streams after trailing garbage are best effort, like bzip2 ignoring them
+            Err(_) if self.trailing_garbage => {
+                self.finished = true;
+                self.index.set_complete();
+                Ok(None)
+            }
+            Err(err) => {
+                self.finished = true;
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Iterator for DecodedBlocks {
+    type Item = Result<DecodedBlock>;
+
+    fn next(&mut self) -> Option<Result<DecodedBlock>> {
+        let block = self.next_block().transpose()?;
+        if let Ok(block) = &block {
+            // The caller owns the block now
+            self.budget.release(block.data.len());
+        }
+        Some(block)
+    }
+}
+
+impl Drop for DecodedBlocks {
+    fn drop(&mut self) {
+        // Stop the background threads instead of letting them decode the rest of the input
+        self.pipeline.cancel();
+    }
+}
+
+/// Configures and creates a [`Bz2Decoder`].
+///
+/// By default the decoder decompresses on the global rayon pool, scans on a
+/// dedicated pool, and keeps up to two level-9 blocks (1.8MB) of decoded data per
+/// thread in memory, within a reorder window of two blocks per thread.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::Bz2Decoder;
+/// use std::io::Read;
+/// use std::sync::Arc;
+///
+/// let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap());
+/// let mut decoder = Bz2Decoder::builder()
+///     .thread_pool(pool)
+///     .max_in_flight_bytes(64 * 1024 * 1024)
+///     .open("file.bz2")
+///     .unwrap();
+///
+/// let mut data = Vec::new();
+/// decoder.read_to_end(&mut data).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Bz2DecoderBuilder {
+    pool: Option<Arc<rayon::ThreadPool>>,
+    num_threads: Option<usize>,
+    max_in_flight_bytes: Option<usize>,
+    reorder_window: Option<usize>,
+    scan_chunk_size: Option<usize>,
+    block_index: Option<BlockIndex>,
+}
+
+impl Bz2DecoderBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs scanning and decompression on the given thread pool.
+    ///
+    /// Tasks on the pool never block, so it can be shared with other work. The
+    /// decoder's own coordinating threads are ordinary threads outside the pool.
+    /// Takes precedence over [`num_threads`](Self::num_threads).
+    pub fn thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Runs scanning and decompression on a new pool with this many threads.
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Limits the decoded bytes held in memory at once.
+    ///
+    /// This covers blocks being decoded, blocks waiting to be read in order and
+    /// the block currently being read. Blocks are reserved at their stream's
+    /// nominal block size (the level times 100 kB) until decoded. A block is always
+    /// dispatched when nothing else is in flight, so a budget smaller than one block
+    /// still makes progress.
+    ///
+    /// The limit is not strict: blocks of highly repetitive data can decode to many
+    /// times their nominal size, and the blocks already in flight when that shows up
+    /// may exceed the budget by their decoded size. No further block is dispatched
+    /// until enough of them have been read.
+    pub fn max_in_flight_bytes(mut self, bytes: usize) -> Self {
+        self.max_in_flight_bytes = Some(bytes);
+        self
+    }
+
+    /// Limits how far ahead of the next block to be read decoding may run, in blocks.
+    ///
+    /// Blocks that finish ahead of a slow one wait in memory until it is done, so
+    /// this bounds the reorder buffer on pathological inputs. Together with
+    /// [`max_in_flight_bytes`](Self::max_in_flight_bytes), whichever limit is
+    /// reached first holds back dispatching.
+    pub fn reorder_window(mut self, blocks: usize) -> Self {
+        self.reorder_window = Some(blocks);
+        self
+    }
+
+    /// Sets the number of bytes scanned per parallel scanner task.
+    ///
+    /// Defaults to [`crate::scanner::DEFAULT_CHUNK_SIZE`].
+    pub fn scan_chunk_size(mut self, bytes: usize) -> Self {
+        self.scan_chunk_size = Some(bytes);
+        self
+    }
+
+    /// Decodes the blocks listed in a complete index instead of scanning for them.
+    ///
+    /// The index is typically [loaded](BlockIndex::load) from a `.bz2idx` file
+    /// saved earlier. It also lets the decoder seek anywhere without decoding
+    /// the blocks in between first. It is [validated](BlockIndex::validate)
+    /// against the input when the decoder is built, and ignored by
+    /// [`build_reader`](Self::build_reader).
+    pub fn block_index(mut self, index: BlockIndex) -> Self {
+        self.block_index = Some(index);
+        self
+    }
+
+    /// Creates a decoder for in-memory data, like [`Bz2Decoder::new`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if a thread pool was requested with
+    /// [`num_threads`](Self::num_threads) and could not be created, or
+    /// [`Error::InvalidIndex`] if a [block index](Self::block_index) does not
+    /// match `data`.
+    pub fn build<T>(self, data: Arc<T>) -> Result<Bz2Decoder>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        self.build_data(data, None)
+    }
+
+    /// Validates the block index, if any, against `data` and starts the decoder.
+    fn build_data<T>(mut self, data: Arc<T>, modified: Option<SystemTime>) -> Result<Bz2Decoder>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let index = match self.block_index.take() {
+            Some(index) => {
+                index.validate(data.as_ref().as_ref(), modified)?;
+                index
+            }
+            None => BlockIndex::new(),
+        };
+        self.start(Source::Data(data), index)
+    }
+
+    /// Decodes a bzip2 file once and returns its complete index.
+    ///
+    /// The index records the file's size, modification time and a sample hash
+    /// of its content, so that it can be [saved](BlockIndex::save) and checked
+    /// when it is used with the file again.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`open`](Self::open), plus any error from decoding the file.
+    pub fn build_index<P: AsRef<Path>>(self, path: P) -> Result<BlockIndex> {
+        let (mmap, modified) = map_file(path.as_ref())?;
+        let mmap = Arc::new(mmap);
+        let source = SourceInfo::new(&mmap[..], modified);
+
+        let mut blocks = self.build_data(mmap, modified)?.into_blocks();
+        for block in &mut blocks {
+            block?;
+        }
+        let mut index = std::mem::take(&mut blocks.index);
+        index.set_source(source);
+        Ok(index)
+    }
+
+    /// Creates the thread pool and scanner, then starts the decoder.
+    fn start(self, source: Source, index: BlockIndex) -> Result<Bz2Decoder> {
+        let pool = match (self.pool, self.num_threads) {
+            (Some(pool), _) => Some(pool),
+            (None, Some(num_threads)) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()
+                    .map_err(io::Error::other)?,
+            )),
+            (None, None) => None,
+        };
+
+        Ok(Bz2Decoder::start(
+            source,
+            Settings {
+                pool,
+                max_in_flight_bytes: self.max_in_flight_bytes,
+                reorder_window: self.reorder_window,
+                scan_chunk_size: self.scan_chunk_size,
+            },
+            index,
+        ))
+    }
+
+    /// Creates a decoder reading from `reader`, like [`Bz2Decoder::from_reader`].
+    ///
+    /// The [scan chunk size](Self::scan_chunk_size) is also the size of each read
+    /// from `reader`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Bz2Decoder::from_reader`] and [`build`](Self::build).
+    pub fn build_reader<R>(self, mut reader: R) -> Result<Bz2Decoder>
+    where
+        R: Read + Send + 'static,
+    {
+        let mut header = [0u8; 4];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(Error::NotBzip2 { byte_offset: 0 })
+            }
+            Err(e) => return Err(e.into()),
+        }
+        if !matches!(header, [b'B', b'Z', b'h', b'1'..=b'9']) {
+            return Err(Error::NotBzip2 { byte_offset: 0 });
+        }
+
+        // Put the header back in front of the rest of the input
+        let reader = io::Cursor::new(header).chain(reader);
+        self.start(Source::Reader(Box::new(reader)), BlockIndex::new())
+    }
+
+    /// Opens a bzip2 file with memory-mapped I/O, like [`Bz2Decoder::open`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`Bz2Decoder::open`] and [`build`](Self::build).
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<Bz2Decoder> {
+        let (mmap, modified) = map_file(path.as_ref())?;
+        self.build_data(Arc::new(mmap), modified)
+    }
+}
+
+/// Maps a bzip2 file into memory and returns it with its modification time.
+fn map_file(path: &Path) -> Result<(memmap2::Mmap, Option<SystemTime>)> {
+    let file = std::fs::File::open(path)?;
+    let modified = file.metadata()?.modified().ok();
+    let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
+    if !matches!(mmap.get(..4), Some([b'B', b'Z', b'h', b'1'..=b'9'])) {
+        return Err(Error::NotBzip2 { byte_offset: 0 });
+    }
+    Ok((mmap, modified))
+}
+
+/// Byte budget for decoded blocks, shared by the driver, the workers and the reader.
+///
+/// The driver reserves an estimate before dispatching a block, the worker corrects
+/// it to the decoded size, and the reader releases it once the block has been read
+/// or discarded. Since the estimate can fall short of the decoded size, the blocks in
+/// flight can exceed the limit; reservations then wait until enough has been released.
+struct Budget {
+    limit: usize,
+    in_flight: Mutex<usize>,
+    released: Condvar,
+}
+
+impl Budget {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Waits until `bytes` fit in the budget and reserves them.
+    ///
+    /// Always succeeds when nothing is in flight, so that an oversized block cannot
+    /// stall the pipeline. Returns `false` if `cancel` fires while waiting.
+    fn reserve(&self, bytes: usize, cancel: &CancellationToken) -> bool {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        loop {
+            if cancel.is_cancelled() {
+                return false;
+            }
+            if *in_flight == 0 || *in_flight + bytes <= self.limit {
+                *in_flight += bytes;
+                return true;
+            }
+            // Wake up now and then to notice cancellation
+            in_flight = self
+                .released
+                .wait_timeout(in_flight, Duration::from_millis(50))
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Replaces a reservation with the number of bytes actually held.
+    fn adjust(&self, reserved: usize, actual: usize) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        *in_flight = *in_flight - reserved + actual;
+        if actual < reserved {
+            self.released.notify_all();
+        }
+    }
+
+    /// Returns bytes to the budget.
+    fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        *self.in_flight.lock().unwrap() -= bytes;
+        self.released.notify_all();
+    }
+}
+
+/// State shared by the driver thread and the decode tasks it spawns.
+struct Shared {
+    budget: Arc<Budget>,
+    window: Arc<ReorderWindow>,
+    cancel: CancellationToken,
+    results: Sender<Message>,
+}
+
+impl Shared {
+    /// Dispatches every block found by the scanner to the pool, in file order.
+    fn drive(
+        self: Arc<Self>,
+        source: Source,
+        scanner: Scanner,
+        pool: Option<Arc<rayon::ThreadPool>>,
+        resume: Option<IndexEntry>,
+        known: Option<Vec<IndexEntry>>,
+    ) {
+        // Get block boundaries from the index, the scanner, or the sliding window
+        // over a reader
+        let first_index = resume.map_or(0, |entry| entry.index);
+        let resume_at = resume.map(|entry| (entry.start_bit, entry.level));
+        let jobs: Box<dyn Iterator<Item = Result<(usize, Job)>>> = match (source, known) {
+            (Source::Data(data), Some(entries)) => {
+                Box::new((0..entries.len()).map(move |i| {
+                    let entry = entries[i];
+                    // Ranges between indexed blocks were merged into the one before
+                    let absorbed = entries
+                        .get(i + 1)
+                        .map_or(0, |next| next.index - entry.index - 1);
+                    Ok((
+                        entry.index,
+                        Job {
+                            data: data.clone(),
+                            offset_bits: 0,
+                            block: BlockRange::new(entry.start_bit, entry.end_bit, entry.level),
+                            absorbed,
+                        },
+                    ))
+                }))
+            }
+            (Source::Data(data), None) => Box::new(
+                (first_index..)
+                    .zip(spawn_block_scan(
+                        data.clone(),
+                        scanner,
+                        pool.clone(),
+                        resume_at,
+                    ))
+                    .map(move |(idx, block)| {
+                        Ok((
+                            idx,
+                            Job {
+                                data: data.clone(),
+                                offset_bits: 0,
+                                block,
+                                absorbed: 0,
+                            },
+                        ))
+                    }),
+            ),
+            (Source::Reader(reader), _) => Box::new(
+                (first_index..)
+                    .zip(Segmenter::new(reader, scanner))
+                    .map(|(idx, job)| job.map(|job| (idx, job))),
+            ),
+        };
+        let mut block_count = first_index;
+        let mut failure = None;
+
+        for job in jobs {
+            let (idx, job) = match job {
+                Ok(job) => job,
+                Err(err) => {
+                    failure = Some(err);
+                    break;
+                }
+            };
+            // Stay within the window of the next block to be read, then within budget
+            if !self.window.acquire(idx, &self.cancel) {
+                break;
+            }
+            let reserved = block_size_estimate(job.block.level);
+            if !self.budget.reserve(reserved, &self.cancel) {
+                break;
+            }
+            block_count = idx + 1;
+
+            let task_shared = self.clone();
+            let task = move || task_shared.decode(idx, job, reserved);
+            match &pool {
+                Some(pool) => pool.spawn(task),
+                None => rayon::spawn(task),
+            }
+        }
+
+        // Breaking out early dropped the scanner's receiver, which stops it too
+        let message = if self.cancel.is_cancelled() {
+            Message::Failed(block_count, Error::Cancelled)
+        } else if let Some(err) = failure {
+            Message::Failed(block_count, err)
+        } else {
+            Message::Finished(block_count)
+        };
+        let _ = self.results.send(message);
+    }
+
+    /// Decodes one candidate range and sends the result to the reader.
+    ///
+    /// Failures are sent to the reader rather than stopping the pipeline: a
+    /// failed range may turn out to be the tail of a block that an earlier
+    /// task recovered by merging, in which case the reader ignores it.
+    fn decode(&self, idx: usize, job: Job, reserved: usize) {
+        if self.cancel.is_cancelled() {
+            self.budget.adjust(reserved, 0);
+            return;
+        }
+
+        // Panics are caught so the reader gets an error instead of a missing block
+        let data = job.data.as_ref().as_ref();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut decomp_buf = Vec::new();
+            // Decompress this block and check it against its stored CRC
+            decompress_indexed_block_merging(data, Some(idx), job.block, &mut decomp_buf)
+                .map(|span| {
+                    let merged = span.merged + job.absorbed;
+                    (BlockSpan { merged, ..span }, decomp_buf)
+                })
+                .map_err(|err| err.offset_by(job.offset_bits))
+        }))
+        .unwrap_or_else(|payload| {
+            Err(Error::WorkerPanicked {
+                message: panic_message(payload.as_ref()),
+            })
+        });
+
+        let decoded = result.as_ref().map_or(0, |(_, block)| block.len());
+        self.budget.adjust(reserved, decoded);
+        // Send result with index for reordering; the reader may be gone
+        let _ = self.results.send(Message::Block(idx, job, result));
+    }
+}
+
+/// Extracts the message from a panic payload.
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
 }
 
 impl Read for Bz2Decoder {
@@ -191,60 +1253,94 @@ impl Read for Bz2Decoder {
     /// # Algorithm
     ///
     /// 1. If we have buffered data, return it immediately
-    /// 2. Otherwise, try to get the next expected block from pending blocks
+    /// 2. Otherwise, release the finished block and try to get the next expected
+    ///    block from pending blocks
     /// 3. If not available, receive blocks from the channel until we get the right one
     /// 4. Buffer out-of-order blocks for later
-    /// 5. Recursively call read() to actually copy data to the caller's buffer
+    /// 5. Copy data from the new block to the caller's buffer
     ///
     /// # Returns
     ///
     /// - `Ok(n)` where n > 0: Successfully read n bytes
     /// - `Ok(0)`: End of stream (all blocks decompressed)
-    /// - `Err(e)`: I/O error (should not happen in normal operation)
+    /// - `Err(e)`: a [`crate::Error`] converted to `io::Error`, e.g. `InvalidData`
+    ///   if a block fails to decode or a CRC does not match
     ///
     /// # Performance
     ///
-    /// The recursive call at the end is optimized away by the compiler (tail call).
-    /// The HashMap lookup for pending blocks is O(1) average case.
+    /// Out-of-order blocks wait in a [`crate::ReorderBuffer`], where taking the
+    /// next one is O(1) on average. Blocks are only dispatched within the reorder
+    /// window, so the buffer never holds more than a few blocks per thread.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        // Fast path: return buffered data if available
-        if self.buffer_pos < self.buffer.len() {
-            let len = std::cmp::min(buf.len(), self.buffer.len() - self.buffer_pos);
-            buf[..len].copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + len]);
-            self.buffer_pos += len;
-            return Ok(len);
+        if let Some(err) = &self.failed {
+            return Err(err.duplicate().into());
+        }
+        if self.cancel.is_cancelled() {
+            self.failed = Some(Error::Cancelled);
+            return Err(Error::Cancelled.into());
         }
 
         // Buffer empty, need to get the next block
-        loop {
-            // Check if we have the next expected block in pending blocks
-            if let Some(block) = self.pending_blocks.remove(&self.next_block_idx) {
-                self.buffer = block;
-                self.buffer_pos = 0;
-                self.next_block_idx += 1;
-                // Tail recursion: actually copy data to caller's buffer
-                return self.read(buf);
-            }
-
-            // Receive blocks from the channel
-            match self.receiver.recv() {
-                Ok((idx, block)) => {
-                    if idx == self.next_block_idx {
-                        // This is the block we're waiting for
-                        self.buffer = block;
-                        self.buffer_pos = 0;
-                        self.next_block_idx += 1;
-                        return self.read(buf);
-                    } else {
-                        // Out-of-order block, buffer it for later
-                        self.pending_blocks.insert(idx, block);
-                    }
-                }
-                Err(_) => {
-                    // Channel closed, all blocks have been processed
-                    return Ok(0);
+        while self.buffer_pos >= self.buffer.len() {
+            // The current block has been read: let the driver dispatch more work
+            self.blocks
+                .budget
+                .release(std::mem::take(&mut self.buffer_reserved));
+            match self.load_next_block() {
+                Ok(true) => {}
+                Ok(false) => return Ok(0),
+                Err(err) => {
+                    self.failed = Some(err.duplicate());
+                    return Err(err.into());
                 }
             }
         }
+
+        // Return buffered data
+        let len = std::cmp::min(buf.len(), self.buffer.len() - self.buffer_pos);
+        buf[..len].copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + len]);
+        self.buffer_pos += len;
+        Ok(len)
+    }
+}
+
+impl Seek for Bz2Decoder {
+    /// Moves to an offset in the decompressed output.
+    ///
+    /// Seeking into a block that has already been decoded restarts decoding at
+    /// that block, using the [index](Bz2Decoder::index) built while reading. Seeking
+    /// further decodes the blocks in between once and records them in the index;
+    /// [`SeekFrom::End`] therefore decodes the whole input the first time.
+    /// Positions past the end are clamped to the end.
+    ///
+    /// # Errors
+    ///
+    /// - `InvalidInput` if the target is before the start of the output
+    /// - `Unsupported` when seeking backwards in a decoder created with
+    ///   [`Bz2Decoder::from_reader`], which cannot go back in its input
+    /// - any error from decoding the blocks up to the target
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => return self.seek_to(offset).map_err(Into::into),
+            SeekFrom::Current(delta) => (self.position(), delta),
+            SeekFrom::End(delta) => {
+                let len = match self.blocks.index.decompressed_len() {
+                    Some(len) => len,
+                    None => self.seek_to(u64::MAX)?,
+                };
+                (len, delta)
+            }
+        };
+        let target = base.checked_add_signed(delta).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        self.seek_to(target).map_err(Into::into)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position())
     }
 }
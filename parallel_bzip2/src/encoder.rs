@@ -0,0 +1,591 @@
+//! Parallel bzip2 encoder.
+//!
+//! bzip2 compresses its input in independent blocks, so blocks can be compressed
+//! in parallel as easily as they are decompressed. [`Bz2Encoder`] implements
+//! `Write`: it cuts its input into blocks of the configured level's size,
+//! compresses them on a Rayon pool and writes them out in order.
+//!
+//! # Output format
+//!
+//! Each block is compressed as a one-block stream by libbz2. By default the blocks
+//! are then bit-concatenated into a single stream, with the combined CRC folded
+//! over all of them, so that the output is indistinguishable from what `bzip2`
+//! itself writes and is readable by any decoder. With
+//! [`multi_stream`](Bz2EncoderBuilder::multi_stream), the one-block streams are
+//! written as they are instead, like `pbzip2` does.
+//!
+//! # Example
+//!
+//! ```
+//! use parallel_bzip2::Bz2Encoder;
+//! use std::io::Write;
+//!
+//! let mut encoder = Bz2Encoder::new(Vec::new());
+//! encoder.write_all(b"hello world").unwrap();
+//! let compressed = encoder.finish().unwrap();
+//! assert!(compressed.starts_with(b"BZh9"));
+//! ```
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+
+use crate::crc::combine;
+use crate::decoder::panic_message;
+use crate::scanner::{read_u32_at, verify_magic, MAGIC_EOS};
+use crate::{Error, ReorderBuffer, Result, DEFAULT_LEVEL};
+
+/// Number of blocks per thread compressed or waiting to be written by default.
+const DEFAULT_BLOCKS_PER_THREAD: usize = 2;
+
+/// Outcome of compressing one block, as sent from the workers to the encoder.
+type BlockResult = Result<CompressedBlock>;
+
+/// A block compressed by libbz2 as a complete one-block stream.
+struct CompressedBlock {
+    /// The stream, from its `BZh` header to the padding after its combined CRC
+    stream: Vec<u8>,
+    /// Bit offset of the end-of-stream magic in `stream`
+    eos_bit: u64,
+    /// CRC of the block's uncompressed data
+    crc: u32,
+}
+
+impl CompressedBlock {
+    /// Bit offset of the block magic, right after the stream header.
+    const START_BIT: u64 = 32;
+
+    /// Compresses `data` into a stream holding a single block.
+    fn compress(data: &[u8], level: u8) -> Result<Self> {
+        let mut encoder = bzip2::write::BzEncoder::new(
+            Vec::with_capacity(data.len() / 2 + 64),
+            bzip2::Compression::new(u32::from(level)),
+        );
+        encoder.write_all(data)?;
+        let stream = encoder.finish()?;
+
+        // The stream ends with the end-of-stream magic, the combined CRC and up
+        // to 7 bits of padding
+        let end = stream.len() as u64 * 8 - 80;
+        let eos_bit = (end.saturating_sub(7)..=end)
+            .rev()
+            .find(|&bit| verify_magic(&stream, bit, MAGIC_EOS))
+            .ok_or_else(|| io::Error::other("libbz2 wrote no end-of-stream marker"))?;
+        let combined = read_u32_at(&stream, eos_bit + 48).unwrap_or_default();
+
+        if data.is_empty() {
+            return Ok(Self {
+                eos_bit,
+                crc: combined,
+                stream,
+            });
+        }
+        // With a single block, the combined CRC is the block CRC
+        let crc = read_u32_at(&stream, Self::START_BIT + 48).unwrap_or_default();
+        if crc != combined {
+            return Err(io::Error::other("libbz2 split the input into several blocks").into());
+        }
+        Ok(Self {
+            stream,
+            eos_bit,
+            crc,
+        })
+    }
+}
+
+/// Input gathered for the next block, measured the way libbz2 fills a block.
+///
+/// libbz2 run-length encodes its input before sorting it, turning runs of 4 to
+/// 255 equal bytes into 5 bytes, and starts a new block once the encoded data
+/// reaches the block size. Measuring the same way lets every chunk be as large
+/// as possible while still compressing to exactly one block.
+struct Chunk {
+    data: Vec<u8>,
+    /// Capacity of a block at the encoder's level, in run-length encoded bytes
+    capacity: usize,
+    /// Run-length encoded size of `data`
+    encoded: usize,
+    /// Byte and length of the run at the end of `data`
+    run: Option<(u8, usize)>,
+}
+
+impl Chunk {
+    fn new(level: u8) -> Self {
+        // libbz2 leaves 19 bytes of each block unused
+        let capacity = usize::from(level) * 100_000 - 19;
+        Self {
+            data: Vec::with_capacity(capacity),
+            capacity,
+            encoded: 0,
+            run: None,
+        }
+    }
+
+    /// Appends as much of `buf` as fits and returns how many bytes were taken.
+    fn fill(&mut self, buf: &[u8]) -> usize {
+        for (taken, &byte) in buf.iter().enumerate() {
+            let (run, growth) = match self.run {
+                Some((last, len)) if last == byte && len < 255 => {
+                    // A run of 4 costs 5 bytes, longer runs only change the count
+                    let growth = match len + 1 {
+                        ..=3 => 1,
+                        4 => 2,
+                        _ => 0,
+                    };
+                    ((byte, len + 1), growth)
+                }
+                _ => ((byte, 1), 1),
+            };
+            if self.encoded + growth > self.capacity {
+                self.data.extend_from_slice(&buf[..taken]);
+                return taken;
+            }
+            self.encoded += growth;
+            self.run = Some(run);
+        }
+        self.data.extend_from_slice(buf);
+        buf.len()
+    }
+
+    fn is_full(&self) -> bool {
+        // Any further byte could grow the encoded size by 2
+        self.encoded + 2 > self.capacity
+    }
+
+    /// Takes the gathered input, leaving the chunk empty.
+    fn take(&mut self) -> Vec<u8> {
+        self.encoded = 0;
+        self.run = None;
+        std::mem::replace(&mut self.data, Vec::with_capacity(self.capacity))
+    }
+}
+
+/// Bit-level output buffer for concatenating blocks.
+///
+/// Holds whole bytes not yet written plus a partially filled last byte.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits used in the last byte of `bytes`, or 0 if it is complete
+    partial: u32,
+}
+
+impl BitWriter {
+    /// Appends bits `start..end` of `src`, most significant bit first.
+    fn push_bits(&mut self, src: &[u8], start: u64, end: u64) {
+        let mut pos = start;
+        // Single bits up to the next byte boundary of `src`
+        while pos < end && !pos.is_multiple_of(8) {
+            self.push_bit(src[(pos / 8) as usize] >> (7 - pos % 8) & 1);
+            pos += 1;
+        }
+        let whole = &src[(pos / 8) as usize..(end / 8) as usize];
+        if self.partial == 0 {
+            self.bytes.extend_from_slice(whole);
+        } else {
+            for &byte in whole {
+                *self.bytes.last_mut().unwrap() |= byte >> self.partial;
+                self.bytes.push(byte << (8 - self.partial));
+            }
+        }
+        pos = pos.max(end / 8 * 8);
+        while pos < end {
+            self.push_bit(src[(pos / 8) as usize] >> (7 - pos % 8) & 1);
+            pos += 1;
+        }
+    }
+
+    fn push_bit(&mut self, bit: u8) {
+        if self.partial == 0 {
+            self.bytes.push(bit << 7);
+        } else {
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.partial);
+        }
+        self.partial = (self.partial + 1) % 8;
+    }
+
+    /// Writes out the complete bytes, keeping a partial last byte.
+    fn drain_to<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let complete = self.bytes.len() - usize::from(self.partial != 0);
+        writer.write_all(&self.bytes[..complete])?;
+        self.bytes.drain(..complete);
+        Ok(())
+    }
+}
+
+/// Parallel bzip2 encoder implementing the `Write` trait.
+///
+/// Input is cut into blocks that are compressed in parallel and written to the
+/// inner writer in order. Call [`finish`](Self::finish) to write the end of the
+/// stream and get the writer back; dropping the encoder finishes it too, but
+/// ignores errors.
+///
+/// Use [`Bz2EncoderBuilder`] to change the level, the thread pool or the
+/// output format.
+///
+/// # Memory Usage
+///
+/// Besides the block being gathered, up to two blocks per thread are held while
+/// they are compressed or wait for an earlier block to finish. `write` blocks
+/// once that many are in flight.
+pub struct Bz2Encoder<W: Write> {
+    /// The inner writer, taken by `finish`
+    writer: Option<W>,
+    level: u8,
+    multi_stream: bool,
+    pool: Option<Arc<rayon::ThreadPool>>,
+    /// Blocks that may be compressed or waiting to be written at once
+    max_in_flight: usize,
+    chunk: Chunk,
+    /// Index of the next block to dispatch
+    next_index: usize,
+    results_tx: Sender<(usize, BlockResult)>,
+    results_rx: Receiver<(usize, BlockResult)>,
+    reorder: ReorderBuffer<BlockResult>,
+    out: BitWriter,
+    /// Combined CRC of the blocks written so far, for single-stream output
+    combined_crc: u32,
+    /// Set once the stream trailer has been written
+    finished: bool,
+    /// Error that stopped the encoder, repeated on later calls
+    failed: Option<Error>,
+}
+
+impl<W: Write> Bz2Encoder<W> {
+    /// Creates an encoder writing a single level-9 stream to `writer`.
+    ///
+    /// Compresses on the global Rayon pool.
+    pub fn new(writer: W) -> Self {
+        Bz2EncoderBuilder::new().start(writer, None)
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        self.writer.as_ref().expect("writer present until finished")
+    }
+
+    /// Compresses the remaining input, writes the end of the stream and returns
+    /// the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// Any error from compressing a block or writing to the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.try_finish()?;
+        Ok(self.writer.take().expect("writer present until finished"))
+    }
+
+    /// Writes everything out and ends the stream, unless already done.
+    fn try_finish(&mut self) -> Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.check_failed()?;
+        let result = self.end_stream();
+        self.record_failure(result).map_err(Error::from)
+    }
+
+    fn end_stream(&mut self) -> Result<()> {
+        if !self.chunk.data.is_empty() || self.next_index == 0 {
+            // Even empty input gets a stream, so the output is a valid bzip2 file
+            self.dispatch();
+        }
+        self.drain(0)?;
+
+        if !self.multi_stream {
+            // The end-of-stream magic and combined CRC, then padding to a byte
+            let mut trailer = [0u8; 10];
+            trailer[..6].copy_from_slice(&MAGIC_EOS.to_be_bytes()[2..]);
+            trailer[6..].copy_from_slice(&self.combined_crc.to_be_bytes());
+            self.out.push_bits(&trailer, 0, 80);
+            self.out.partial = 0;
+        }
+        let writer = self.writer.as_mut().expect("writer present until finished");
+        self.out.drain_to(writer)?;
+        writer.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Fails with the error that stopped the encoder, if any.
+    fn check_failed(&self) -> io::Result<()> {
+        match &self.failed {
+            Some(err) => Err(err.duplicate().into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Remembers a failure, since the output is unusable after it.
+    fn record_failure(&mut self, result: Result<()>) -> io::Result<()> {
+        result.map_err(|err| {
+            self.failed = Some(err.duplicate());
+            err.into()
+        })
+    }
+
+    /// Sends the gathered input to the pool to be compressed.
+    fn dispatch(&mut self) {
+        let data = self.chunk.take();
+        let level = self.level;
+        let index = self.next_index;
+        self.next_index += 1;
+
+        let results = self.results_tx.clone();
+        let task = move || {
+            // Panics are caught so the encoder gets an error instead of a missing block
+            let result =
+                panic::catch_unwind(AssertUnwindSafe(|| CompressedBlock::compress(&data, level)))
+                    .unwrap_or_else(|payload| {
+                        Err(Error::WorkerPanicked {
+                            message: panic_message(payload.as_ref()),
+                        })
+                    });
+            let _ = results.send((index, result));
+        };
+        match &self.pool {
+            Some(pool) => pool.spawn(task),
+            None => rayon::spawn(task),
+        }
+    }
+
+    /// Writes out compressed blocks in order until at most `in_flight` remain.
+    fn drain(&mut self, in_flight: usize) -> Result<()> {
+        while self.next_index - self.reorder.next_index() > in_flight {
+            let (index, result) = self.results_rx.recv().expect("the encoder holds a sender");
+            self.reorder.insert(index, result);
+            while let Some(result) = self.reorder.pop() {
+                self.emit(result?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends a compressed block to the output.
+    fn emit(&mut self, block: CompressedBlock) -> Result<()> {
+        if self.multi_stream {
+            self.out
+                .push_bits(&block.stream, 0, block.stream.len() as u64 * 8);
+        } else {
+            // Only the first block keeps its stream header
+            let start = if self.reorder.next_index() == 1 {
+                0
+            } else {
+                CompressedBlock::START_BIT
+            };
+            self.out.push_bits(&block.stream, start, block.eos_bit);
+            if block.eos_bit > CompressedBlock::START_BIT {
+                self.combined_crc = combine(self.combined_crc, block.crc);
+            }
+        }
+        let writer = self.writer.as_mut().expect("writer present until finished");
+        self.out.drain_to(writer)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Bz2Encoder<W> {
+    /// Gathers input and dispatches each full block for compression.
+    ///
+    /// Blocks while too many dispatched blocks are still being compressed or
+    /// waiting for an earlier one. Takes all of `buf` unless an error occurs.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_failed()?;
+        let mut taken = 0;
+        while taken < buf.len() {
+            taken += self.chunk.fill(&buf[taken..]);
+            if self.chunk.is_full() || taken < buf.len() {
+                self.dispatch();
+                let result = self.drain(self.max_in_flight);
+                self.record_failure(result)?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Compresses the input gathered so far and writes out every block.
+    ///
+    /// The input is cut into a block early, so frequent flushing hurts the
+    /// compression ratio. Up to 7 bits of the last block stay buffered, as they
+    /// share a byte with whatever follows.
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_failed()?;
+        if !self.chunk.data.is_empty() {
+            self.dispatch();
+        }
+        let result = self.drain(0);
+        self.record_failure(result)?;
+        self.writer
+            .as_mut()
+            .expect("writer present until finished")
+            .flush()
+    }
+}
+
+impl<W: Write> Drop for Bz2Encoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// Configures and creates a [`Bz2Encoder`].
+///
+/// By default the encoder writes a single level-9 stream, compresses on the
+/// global Rayon pool and keeps up to two blocks per thread in flight.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::Bz2EncoderBuilder;
+/// use std::fs::File;
+/// use std::io::{self, BufWriter};
+///
+/// let mut encoder = Bz2EncoderBuilder::new()
+///     .level(6)
+///     .num_threads(4)
+///     .multi_stream(true)
+///     .build(BufWriter::new(File::create("file.bz2").unwrap()))
+///     .unwrap();
+/// io::copy(&mut File::open("file").unwrap(), &mut encoder).unwrap();
+/// encoder.finish().unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Bz2EncoderBuilder {
+    level: Option<u8>,
+    multi_stream: bool,
+    pool: Option<Arc<rayon::ThreadPool>>,
+    num_threads: Option<usize>,
+    max_in_flight_blocks: Option<usize>,
+}
+
+impl Bz2EncoderBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the compression level, 1 to 9, which is also the block size in
+    /// units of 100k. Values outside that range are clamped.
+    pub fn level(mut self, level: u8) -> Self {
+        self.level = Some(level.clamp(1, 9));
+        self
+    }
+
+    /// Writes each block as a separate stream, like `pbzip2`.
+    ///
+    /// Every bzip2 decoder reads such files, but some older tools stop after
+    /// the first stream. Off by default.
+    pub fn multi_stream(mut self, multi_stream: bool) -> Self {
+        self.multi_stream = multi_stream;
+        self
+    }
+
+    /// Compresses on the given thread pool.
+    ///
+    /// Takes precedence over [`num_threads`](Self::num_threads).
+    pub fn thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Compresses on a new pool with this many threads.
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Limits how many blocks may be compressed or waiting to be written at once.
+    ///
+    /// Defaults to two per thread of the pool. At least one.
+    pub fn max_in_flight_blocks(mut self, blocks: usize) -> Self {
+        self.max_in_flight_blocks = Some(blocks.max(1));
+        self
+    }
+
+    /// Creates an encoder writing to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if a thread pool was requested with
+    /// [`num_threads`](Self::num_threads) and could not be created.
+    pub fn build<W: Write>(self, writer: W) -> Result<Bz2Encoder<W>> {
+        let pool = match (self.pool.clone(), self.num_threads) {
+            (Some(pool), _) => Some(pool),
+            (None, Some(num_threads)) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()
+                    .map_err(io::Error::other)?,
+            )),
+            (None, None) => None,
+        };
+        Ok(self.start(writer, pool))
+    }
+
+    fn start<W: Write>(self, writer: W, pool: Option<Arc<rayon::ThreadPool>>) -> Bz2Encoder<W> {
+        let level = self.level.unwrap_or(DEFAULT_LEVEL);
+        let threads = pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            });
+        let (results_tx, results_rx) = unbounded();
+        Bz2Encoder {
+            writer: Some(writer),
+            level,
+            multi_stream: self.multi_stream,
+            pool,
+            max_in_flight: self
+                .max_in_flight_blocks
+                .unwrap_or(threads * DEFAULT_BLOCKS_PER_THREAD),
+            chunk: Chunk::new(level),
+            next_index: 0,
+            results_tx,
+            results_rx,
+            reorder: ReorderBuffer::new(),
+            out: BitWriter::default(),
+            combined_crc: 0,
+            finished: false,
+            failed: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bit_writer_concatenates_unaligned() {
+        let mut out = BitWriter::default();
+        out.push_bits(&[0b1010_0000], 0, 3);
+        out.push_bits(&[0xFF, 0x0F], 4, 16);
+        out.push_bits(&[0b1000_0000], 0, 1);
+        assert_eq!(out.bytes, vec![0b1011_1110, 0b0001_1111]);
+        assert_eq!(out.partial, 0);
+
+        let mut written = Vec::new();
+        out.push_bits(&[0xAB], 0, 4);
+        out.drain_to(&mut written).unwrap();
+        assert!(written.len() == 2 && out.bytes == vec![0xA0]);
+    }
+
+    #[test]
+    fn test_chunk_counts_runs_like_libbz2() {
+        // Runs of four equal bytes expand the most: 4 bytes become 5
+        let worst: Vec<u8> = (0..200_000u32).map(|i| (i / 4) as u8).collect();
+        let mut chunk = Chunk::new(1);
+        let taken = chunk.fill(&worst);
+        assert!(chunk.is_full());
+        assert_eq!(taken, 79_985);
+        assert_eq!(chunk.encoded, 99_981);
+
+        // The gathered input still compresses to a single block
+        let block = CompressedBlock::compress(&chunk.take(), 1).unwrap();
+        assert_eq!(block.crc, crate::crc::block_crc(&worst[..taken]));
+        assert_eq!(chunk.fill(&worst[taken..taken + 10]), 10);
+        assert_eq!(chunk.encoded, 11);
+    }
+}
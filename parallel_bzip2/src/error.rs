@@ -0,0 +1,248 @@
+//! Error type for the parallel_bzip2 public API.
+//!
+//! Every fallible function in this crate returns [`Error`], so callers can tell
+//! apart failures that deserve different handling (retrying an I/O error, alerting
+//! on corruption, ignoring a cancellation) without matching on messages.
+//!
+//! Errors from the streaming [`crate::Bz2Decoder`] surface as `std::io::Error`
+//! because it implements `Read`. The typed error is kept inside and can be
+//! recovered with `Error::from(io_error)`.
+
+use std::io;
+
+use crate::CrcMismatch;
+
+/// Convenience alias for results returned by this crate.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by parallel_bzip2.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Reading the input failed.
+    #[error("I/O error: {0}")]
+    Io(#[source] io::Error),
+
+    /// The input does not start with a bzip2 stream header (`BZh1`..`BZh9`).
+    #[error("not a bzip2 stream at byte {byte_offset}")]
+    NotBzip2 {
+        /// Byte offset where a stream header was expected.
+        byte_offset: u64,
+    },
+
+    /// The data ends in the middle of a block.
+    #[error("bzip2 stream truncated in block{} starting at bit {bit_offset}", block_label(.block_index))]
+    Truncated {
+        /// Index of the incomplete block, when known.
+        block_index: Option<usize>,
+        /// Bit offset of the incomplete block's magic.
+        bit_offset: u64,
+    },
+
+    /// A block could not be decompressed.
+    #[error("failed to decode block{} at bits {start_bit}..{end_bit}: {source}", block_label(.block_index))]
+    BlockDecode {
+        /// Index of the block, when known.
+        ///
+        /// Standalone calls such as [`crate::decompress_block`] have no notion of
+        /// block order, so this is `None` for them.
+        block_index: Option<usize>,
+        /// Bit offset of the block magic.
+        start_bit: u64,
+        /// Bit offset where the block ends (exclusive).
+        end_bit: u64,
+        /// Why the block could not be decoded.
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A block or stream CRC does not match the decompressed data.
+    #[error(transparent)]
+    CrcMismatch(#[from] CrcMismatch),
+
+    /// Decoding was stopped before the end of the input.
+    #[error("decoding was cancelled")]
+    Cancelled,
+
+    /// A block index file is malformed, or does not describe the input it is used with.
+    #[error("invalid block index: {reason}")]
+    InvalidIndex {
+        /// What is wrong with the index.
+        reason: String,
+    },
+
+    /// A zstd seek table is malformed, or does not describe the file it ends.
+    #[error("invalid zstd seek table: {reason}")]
+    InvalidSeekTable {
+        /// What is wrong with the seek table.
+        reason: String,
+    },
+
+    /// A zstd frame cannot be decoded, or does not match its seek table entry.
+    #[error("invalid zstd frame {frame_index}: {reason}")]
+    ZstdFrame {
+        /// Index of the frame in the file.
+        frame_index: usize,
+        /// What is wrong with the frame.
+        reason: String,
+    },
+
+    /// A background decoding or compression thread panicked or stopped without
+    /// reporting completion.
+    #[error("background thread failed: {message}")]
+    WorkerPanicked {
+        /// The panic message, if any.
+        message: String,
+    },
+}
+
+fn block_label(block_index: &Option<usize>) -> String {
+    match block_index {
+        Some(index) => format!(" {}", index),
+        None => String::new(),
+    }
+}
+
+impl Error {
+    /// Returns the index of the block this error relates to, if any.
+    pub fn block_index(&self) -> Option<usize> {
+        match self {
+            Error::Truncated { block_index, .. } | Error::BlockDecode { block_index, .. } => {
+                *block_index
+            }
+            Error::CrcMismatch(CrcMismatch::Block { block_index, .. }) => *block_index,
+            Error::CrcMismatch(CrcMismatch::Stream { block_index, .. }) => Some(*block_index),
+            Error::Io(_)
+            | Error::NotBzip2 { .. }
+            | Error::InvalidIndex { .. }
+            | Error::InvalidSeekTable { .. }
+            | Error::ZstdFrame { .. }
+            | Error::Cancelled
+            | Error::WorkerPanicked { .. } => None,
+        }
+    }
+}
+
+impl Error {
+    /// Shifts the bit offsets in the error by `offset_bits`.
+    ///
+    /// Blocks read through a sliding window are decoded from a segment of the
+    /// input; this turns offsets within the segment into offsets within the input.
+    pub(crate) fn offset_by(self, offset_bits: u64) -> Self {
+        match self {
+            Error::Truncated {
+                block_index,
+                bit_offset,
+            } => Error::Truncated {
+                block_index,
+                bit_offset: bit_offset + offset_bits,
+            },
+            Error::BlockDecode {
+                block_index,
+                start_bit,
+                end_bit,
+                source,
+            } => Error::BlockDecode {
+                block_index,
+                start_bit: start_bit + offset_bits,
+                end_bit: end_bit + offset_bits,
+                source,
+            },
+            Error::CrcMismatch(mut mismatch) => {
+                match &mut mismatch {
+                    CrcMismatch::Block { bit_offset, .. }
+                    | CrcMismatch::Stream { bit_offset, .. } => *bit_offset += offset_bits,
+                }
+                Error::CrcMismatch(mismatch)
+            }
+            other => other,
+        }
+    }
+}
+
+impl Error {
+    /// Returns a copy of the error, for readers and writers that report the same
+    /// failure on every later call.
+    ///
+    /// Sources that cannot be cloned are replaced by their message, except for
+    /// [`crate::BlockError`], which is kept as is.
+    pub(crate) fn duplicate(&self) -> Self {
+        match self {
+            Error::Io(e) => Error::Io(io::Error::new(e.kind(), e.to_string())),
+            Error::NotBzip2 { byte_offset } => Error::NotBzip2 {
+                byte_offset: *byte_offset,
+            },
+            Error::Truncated {
+                block_index,
+                bit_offset,
+            } => Error::Truncated {
+                block_index: *block_index,
+                bit_offset: *bit_offset,
+            },
+            Error::BlockDecode {
+                block_index,
+                start_bit,
+                end_bit,
+                source,
+            } => Error::BlockDecode {
+                block_index: *block_index,
+                start_bit: *start_bit,
+                end_bit: *end_bit,
+                source: match source.downcast_ref::<crate::BlockError>() {
+                    Some(block_error) => Box::new(*block_error),
+                    None => source.to_string().into(),
+                },
+            },
+            Error::CrcMismatch(mismatch) => Error::CrcMismatch(*mismatch),
+            Error::Cancelled => Error::Cancelled,
+            Error::InvalidIndex { reason } => Error::InvalidIndex {
+                reason: reason.clone(),
+            },
+            Error::InvalidSeekTable { reason } => Error::InvalidSeekTable {
+                reason: reason.clone(),
+            },
+            Error::ZstdFrame {
+                frame_index,
+                reason,
+            } => Error::ZstdFrame {
+                frame_index: *frame_index,
+                reason: reason.clone(),
+            },
+            Error::WorkerPanicked { message } => Error::WorkerPanicked {
+                message: message.clone(),
+            },
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    /// Wraps the error for `Read` implementations.
+    ///
+    /// I/O errors are passed through unchanged. Everything else is wrapped with a
+    /// matching [`io::ErrorKind`] and can be recovered with `Error::from`.
+    fn from(err: Error) -> Self {
+        let kind = match err {
+            Error::Io(e) => return e,
+            Error::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+            // Not `Interrupted`: `read_to_end` and friends silently retry on it
+            Error::Cancelled | Error::WorkerPanicked { .. } => io::ErrorKind::Other,
+            Error::NotBzip2 { .. }
+            | Error::BlockDecode { .. }
+            | Error::CrcMismatch(_)
+            | Error::InvalidIndex { .. }
+            | Error::InvalidSeekTable { .. }
+            | Error::ZstdFrame { .. } => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Unwraps an [`Error`] carried inside an `io::Error`, or wraps it as [`Error::Io`].
+    fn from(err: io::Error) -> Self {
+        if !err.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            return Error::Io(err);
+        }
+        let inner = err.into_inner().expect("checked above");
+        *inner.downcast::<Error>().expect("checked above")
+    }
+}
@@ -0,0 +1,494 @@
+//! Map from decompressed offsets to the blocks that hold them.
+//!
+//! bzip2 does not record how much data a block decompresses to, so the only way
+//! to find the block holding a given output offset is to decode everything in
+//! front of it once. A [`BlockIndex`] remembers the result: for every block it
+//! records where it lives in the compressed input and in the output, plus the
+//! state needed to resume decoding there, so that [`crate::Bz2Decoder`] can seek
+//! straight to it.
+//!
+//! # Index files
+//!
+//! An index can be saved next to its input as a `.bz2idx` file and loaded with
+//! [`Bz2DecoderBuilder::block_index`](crate::Bz2DecoderBuilder::block_index), which
+//! then decodes the blocks it lists instead of scanning the input. All integers
+//! are little-endian:
+//!
+//! | Offset | Size | Field |
+//! |-------:|-----:|-------|
+//! | 0      | 8    | Magic `BZ2INDEX` |
+//! | 8      | 2    | Format version, currently 1 |
+//! | 10     | 2    | Flags: bit 0 complete, bit 1 source recorded, bit 2 source mtime recorded |
+//! | 12     | 4    | Source sample CRC ([`SourceInfo::sample_crc`]) |
+//! | 16     | 8    | Source length in bytes |
+//! | 24     | 8    | Source mtime, seconds since the Unix epoch |
+//! | 32     | 4    | Source mtime, nanoseconds |
+//! | 36     | 4    | Reserved, zero |
+//! | 40     | 8    | Number of entries |
+//! | 48     | 60 × n | Entries |
+//! | end    | 4    | bzip2 CRC of everything before it |
+//!
+//! Each entry describes one block:
+//!
+//! | Offset | Size | Field |
+//! |-------:|-----:|-------|
+//! | 0      | 8    | [`IndexEntry::index`] |
+//! | 8      | 8    | [`IndexEntry::stream_index`] |
+//! | 16     | 8    | [`IndexEntry::start_bit`] |
+//! | 24     | 8    | [`IndexEntry::end_bit`] |
+//! | 32     | 8    | [`IndexEntry::decompressed_offset`] |
+//! | 40     | 8    | [`IndexEntry::decompressed_len`] |
+//! | 48     | 4    | [`IndexEntry::stored_crc`] |
+//! | 52     | 4    | [`IndexEntry::stream_crc`] |
+//! | 56     | 1    | [`IndexEntry::level`], from the stream header |
+//! | 57     | 1    | Flags: bit 0 [`IndexEntry::after_garbage`] |
+//! | 58     | 2    | Reserved, zero |
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::crc::block_crc;
+use crate::scanner::{self, MAGIC_BLOCK};
+use crate::{Error, Result};
+
+/// Magic bytes at the start of an index file.
+const MAGIC: &[u8; 8] = b"BZ2INDEX";
+
+/// Index file format version written by this crate.
+const VERSION: u16 = 1;
+
+const HEADER_LEN: usize = 48;
+const ENTRY_LEN: usize = 60;
+
+const FLAG_COMPLETE: u16 = 1;
+const FLAG_SOURCE: u16 = 1 << 1;
+const FLAG_MODIFIED: u16 = 1 << 2;
+const ENTRY_FLAG_AFTER_GARBAGE: u8 = 1;
+
+/// Bytes hashed at each end of the input for [`SourceInfo::sample_crc`].
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+/// Location and decoding state of one block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Index of the block's candidate range among those found by the scanner
+    pub index: usize,
+    /// Index of the bzip2 stream containing the block, counting streams that have
+    /// blocks
+    pub stream_index: usize,
+    /// Bit offset of the block magic within the input
+    pub start_bit: u64,
+    /// Bit offset where the block ends within the input (exclusive)
+    pub end_bit: u64,
+    /// Level from the header of the block's stream (1-9)
+    pub level: u8,
+    /// CRC stored in the block header
+    pub stored_crc: u32,
+    /// Combined CRC of the blocks of the same stream before this one
+    pub stream_crc: u32,
+    /// Whether trailing garbage was found before this block
+    pub after_garbage: bool,
+    /// Offset of the block's first byte within the decompressed output
+    pub decompressed_offset: u64,
+    /// Number of bytes the block decompresses to
+    pub decompressed_len: u64,
+}
+
+impl IndexEntry {
+    /// Returns the offset just past the block's last byte in the decompressed output.
+    pub fn decompressed_end(&self) -> u64 {
+        self.decompressed_offset + self.decompressed_len
+    }
+}
+
+/// Identifies the input an index was built from.
+///
+/// Checked before an index is used, so that an index left over from an older
+/// version of a file is rejected instead of decoding garbage. Hashing all of a
+/// large input would cost as much as scanning it, so only its length, its
+/// modification time and a CRC of its first and last 64KiB are recorded; the
+/// stored CRC of every indexed block is checked as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceInfo {
+    /// Length of the input in bytes
+    pub len: u64,
+    /// Modification time of the input file, as a duration since the Unix epoch
+    pub modified: Option<Duration>,
+    /// bzip2 CRC of the first and last 64KiB of the input
+    pub sample_crc: u32,
+}
+
+impl SourceInfo {
+    /// Describes in-memory input, with the modification time of its file if known.
+    pub fn new(data: &[u8], modified: Option<SystemTime>) -> Self {
+        let head = &data[..data.len().min(SAMPLE_BYTES)];
+        let tail = &data[data.len().saturating_sub(SAMPLE_BYTES)..];
+        Self {
+            len: data.len() as u64,
+            modified: modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()),
+            sample_crc: block_crc(&[head, tail].concat()),
+        }
+    }
+
+    /// Checks that `other` describes the same input.
+    ///
+    /// Modification times are only compared when both are known.
+    fn check(&self, other: &SourceInfo) -> Result<()> {
+        if self.len != other.len {
+            return Err(invalid(format!(
+                "built for an input of {} bytes, got {} bytes",
+                self.len, other.len
+            )));
+        }
+        if let (Some(expected), Some(actual)) = (self.modified, other.modified) {
+            if expected != actual {
+                return Err(invalid("input was modified after the index was built"));
+            }
+        }
+        if self.sample_crc != other.sample_crc {
+            return Err(invalid("input content differs from the indexed input"));
+        }
+        Ok(())
+    }
+}
+
+/// Blocks of a bzip2 input ordered by decompressed offset.
+///
+/// Entries cover the output contiguously from offset zero. The index is
+/// [complete](Self::is_complete) once it reaches the end of the output.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::Bz2Decoder;
+/// use std::io::{Seek, SeekFrom};
+///
+/// let mut decoder = Bz2Decoder::open("file.bz2").unwrap();
+/// // Decodes the whole file once to find its length...
+/// decoder.seek(SeekFrom::End(0)).unwrap();
+/// // ...after which every block can be found in the index
+/// let index = decoder.index();
+/// assert!(index.is_complete());
+/// println!("{} blocks", index.entries().len());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockIndex {
+    entries: Vec<IndexEntry>,
+    complete: bool,
+    source: Option<SourceInfo>,
+}
+
+impl BlockIndex {
+    /// Creates an empty index.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes a bzip2 file once and returns its complete index.
+    ///
+    /// The index records the file's [`SourceInfo`]. Use
+    /// [`Bz2DecoderBuilder::build_index`](crate::Bz2DecoderBuilder::build_index)
+    /// to configure the decoding.
+    ///
+    /// # Errors
+    ///
+    /// Any error from opening or decoding the file.
+    pub fn build<P: AsRef<Path>>(path: P) -> Result<Self> {
+        crate::Bz2Decoder::builder().build_index(path)
+    }
+
+    /// Returns the indexed blocks in output order.
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Returns `true` once the index covers the whole output.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the input the index was built from, if recorded.
+    pub fn source(&self) -> Option<&SourceInfo> {
+        self.source.as_ref()
+    }
+
+    /// Returns the number of output bytes covered by the index.
+    pub fn indexed_len(&self) -> u64 {
+        self.entries.last().map_or(0, IndexEntry::decompressed_end)
+    }
+
+    /// Returns the total decompressed size, if the index is complete.
+    pub fn decompressed_len(&self) -> Option<u64> {
+        self.complete.then(|| self.indexed_len())
+    }
+
+    /// Finds the block holding the byte at `offset` of the output.
+    pub fn find(&self, offset: u64) -> Option<&IndexEntry> {
+        let pos = self
+            .entries
+            .partition_point(|entry| entry.decompressed_end() <= offset);
+        self.entries
+            .get(pos)
+            .filter(|entry| entry.decompressed_offset <= offset)
+    }
+
+    /// Checks that the index is complete and describes `data`.
+    ///
+    /// `modified` is the modification time of the file `data` was read from, if
+    /// any. Besides the recorded [`SourceInfo`], every entry is checked to start
+    /// with a block magic followed by its stored CRC.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidIndex`] describing the first mismatch.
+    pub fn validate(&self, data: &[u8], modified: Option<SystemTime>) -> Result<()> {
+        if !self.complete {
+            return Err(invalid("index does not cover the whole input"));
+        }
+        if let Some(source) = &self.source {
+            source.check(&SourceInfo::new(data, modified))?;
+        }
+        let data_bits = data.len() as u64 * 8;
+        for entry in &self.entries {
+            let matches = entry.end_bit <= data_bits
+                && scanner::verify_magic(data, entry.start_bit, MAGIC_BLOCK)
+                && scanner::read_u32_at(data, entry.start_bit + 48) == Some(entry.stored_crc);
+            if !matches {
+                return Err(invalid(format!(
+                    "block {} at bit {} does not match the input",
+                    entry.index, entry.start_bit
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the index in the `.bz2idx` format described in the [module
+    /// documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if writing fails.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut out = Vec::with_capacity(HEADER_LEN + ENTRY_LEN * self.entries.len() + 4);
+        let mut flags = 0;
+        if self.complete {
+            flags |= FLAG_COMPLETE;
+        }
+        let source = self.source.unwrap_or(SourceInfo {
+            len: 0,
+            modified: None,
+            sample_crc: 0,
+        });
+        if self.source.is_some() {
+            flags |= FLAG_SOURCE;
+        }
+        let modified = source.modified.unwrap_or_default();
+        if source.modified.is_some() {
+            flags |= FLAG_MODIFIED;
+        }
+
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&flags.to_le_bytes());
+        out.extend_from_slice(&source.sample_crc.to_le_bytes());
+        out.extend_from_slice(&source.len.to_le_bytes());
+        out.extend_from_slice(&modified.as_secs().to_le_bytes());
+        out.extend_from_slice(&modified.subsec_nanos().to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+
+        for entry in &self.entries {
+            out.extend_from_slice(&(entry.index as u64).to_le_bytes());
+            out.extend_from_slice(&(entry.stream_index as u64).to_le_bytes());
+            out.extend_from_slice(&entry.start_bit.to_le_bytes());
+            out.extend_from_slice(&entry.end_bit.to_le_bytes());
+            out.extend_from_slice(&entry.decompressed_offset.to_le_bytes());
+            out.extend_from_slice(&entry.decompressed_len.to_le_bytes());
+            out.extend_from_slice(&entry.stored_crc.to_le_bytes());
+            out.extend_from_slice(&entry.stream_crc.to_le_bytes());
+            out.push(entry.level);
+            out.push(if entry.after_garbage {
+                ENTRY_FLAG_AFTER_GARBAGE
+            } else {
+                0
+            });
+            out.extend_from_slice(&[0; 2]);
+        }
+
+        out.extend_from_slice(&block_crc(&out).to_le_bytes());
+        writer.write_all(&out)?;
+        Ok(())
+    }
+
+    /// Reads an index in the `.bz2idx` format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if reading fails, or [`Error::InvalidIndex`] if the
+    /// data is not a valid index file.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        if data.len() < HEADER_LEN + 4 || &data[..8] != MAGIC {
+            return Err(invalid("not a block index file"));
+        }
+        let (body, checksum) = data.split_at(data.len() - 4);
+        if block_crc(body).to_le_bytes() != checksum {
+            return Err(invalid("index file checksum mismatch"));
+        }
+        let mut fields = Fields(body);
+        fields.skip(8);
+        let version = fields.u16();
+        if version != VERSION {
+            return Err(invalid(format!("unsupported index version {}", version)));
+        }
+        let flags = fields.u16();
+        let sample_crc = fields.u32();
+        let len = fields.u64();
+        let secs = fields.u64();
+        let nanos = fields.u32();
+        fields.skip(4);
+        let count = fields.u64();
+        if (fields.0.len() as u64) != count.saturating_mul(ENTRY_LEN as u64) {
+            return Err(invalid("index file length does not match its entry count"));
+        }
+
+        let source = (flags & FLAG_SOURCE != 0).then(|| SourceInfo {
+            len,
+            modified: (flags & FLAG_MODIFIED != 0).then(|| Duration::new(secs, nanos)),
+            sample_crc,
+        });
+        let mut index = BlockIndex {
+            entries: Vec::with_capacity(count as usize),
+            complete: false,
+            source,
+        };
+        for _ in 0..count {
+            let entry = IndexEntry {
+                index: fields.u64() as usize,
+                stream_index: fields.u64() as usize,
+                start_bit: fields.u64(),
+                end_bit: fields.u64(),
+                decompressed_offset: fields.u64(),
+                decompressed_len: fields.u64(),
+                stored_crc: fields.u32(),
+                stream_crc: fields.u32(),
+                level: fields.u8(),
+                after_garbage: fields.u8() & ENTRY_FLAG_AFTER_GARBAGE != 0,
+            };
+            fields.skip(2);
+            let next_index = index.entries.last().map_or(0, |last| last.index + 1);
+            let valid = entry.start_bit < entry.end_bit
+                && (1..=9).contains(&entry.level)
+                && entry.decompressed_offset == index.indexed_len()
+                && entry.index >= next_index;
+            if !valid {
+                return Err(invalid(format!(
+                    "entry for block {} is invalid",
+                    entry.index
+                )));
+            }
+            index.entries.push(entry);
+        }
+        index.complete = flags & FLAG_COMPLETE != 0;
+        Ok(index)
+    }
+
+    /// Saves the index to a file, see [`write_to`](Self::write_to).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = File::create(path)?;
+        self.write_to(&mut file)?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Loads an index from a file, see [`read_from`](Self::read_from).
+    ///
+    /// # Errors
+    ///
+    /// Same as [`read_from`](Self::read_from).
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::read_from(File::open(path)?)
+    }
+
+    /// Records the input the index describes.
+    pub(crate) fn set_source(&mut self, source: SourceInfo) {
+        self.source = Some(source);
+    }
+
+    /// Appends the next block of the output.
+    ///
+    /// Blocks that are already indexed, or that would leave a gap, are ignored.
+    pub(crate) fn push(&mut self, entry: IndexEntry) {
+        let next_index = self.entries.last().map_or(0, |last| last.index + 1);
+        if !self.complete
+            && entry.decompressed_offset == self.indexed_len()
+            && entry.index >= next_index
+        {
+            self.entries.push(entry);
+        }
+    }
+
+    /// Records that the index reaches the end of the output.
+    pub(crate) fn set_complete(&mut self) {
+        self.complete = true;
+    }
+}
+
+/// Returns the default index file path for a bzip2 file: `file.bz2` is indexed
+/// in `file.bz2idx`, other names get `.bz2idx` appended.
+pub fn index_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    if path.extension().is_some_and(|ext| ext == "bz2") {
+        path.with_extension("bz2idx")
+    } else {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".bz2idx");
+        PathBuf::from(name)
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidIndex {
+        reason: reason.into(),
+    }
+}
+
+/// Little-endian field reader over a buffer whose length has been checked.
+pub(crate) struct Fields<'a>(pub(crate) &'a [u8]);
+
+impl Fields<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        field.try_into().expect("split at N")
+    }
+
+    pub(crate) fn skip(&mut self, len: usize) {
+        self.0 = &self.0[len..];
+    }
+
+    pub(crate) fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    pub(crate) fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    pub(crate) fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    pub(crate) fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+}
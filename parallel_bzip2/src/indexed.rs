@@ -0,0 +1,355 @@
+//! Random access to the decompressed contents of a bzip2 file.
+//!
+//! [`crate::Bz2Decoder`] reads its input front to back, and even with
+//! [`std::io::Seek`] each decoder has a single position. [`IndexedBz2`] instead
+//! answers independent `read_at` calls, from any number of threads at once: it
+//! looks up the blocks overlapping the requested range in a complete
+//! [`BlockIndex`], decodes just those, and keeps recently used blocks in an LRU
+//! cache so that nearby reads do not decode them again.
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    decompress_indexed_block_into, index_path, BlockIndex, BlockRange, Error, IndexEntry, Result,
+};
+
+/// Number of decoded blocks cached by default.
+///
+/// Sixteen level-9 blocks take up to 14.4MB.
+pub const DEFAULT_CACHE_BLOCKS: usize = 16;
+
+/// Shared, thread-safe compressed data.
+type SharedData = Arc<dyn AsRef<[u8]> + Send + Sync>;
+
+/// Cache hit and miss counts of an [`IndexedBz2`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Blocks served from the cache
+    pub hits: u64,
+    /// Blocks that had to be decoded
+    pub misses: u64,
+}
+
+/// A bzip2 file that can be read at arbitrary decompressed offsets.
+///
+/// All methods take `&self`, so an `IndexedBz2` can be shared between threads,
+/// e.g. in an `Arc`. Blocks are decoded on the calling thread; two threads
+/// missing the cache for the same block both decode it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::IndexedBz2;
+///
+/// let file = IndexedBz2::open("file.bz2").unwrap().cache_capacity(64);
+/// let mut buf = vec![0; 4096];
+/// let read = file.read_at(1 << 20, &mut buf).unwrap();
+/// println!("read {} bytes", read);
+/// ```
+pub struct IndexedBz2 {
+    reader: CachedReader,
+    index: BlockIndex,
+}
+
+impl IndexedBz2 {
+    /// Opens a bzip2 file with memory-mapped I/O.
+    ///
+    /// Uses the index file next to it (see [`index_path`]) if there is one and it
+    /// still matches the file. Otherwise the file is decoded once to build the
+    /// index, which is not saved.
+    ///
+    /// # Errors
+    ///
+    /// Any error from opening the file or, without a usable index file, from
+    /// decoding it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)?;
+        let modified = file.metadata()?.modified().ok();
+        let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
+
+        // A missing, unreadable or stale index file is rebuilt rather than an error
+        let index = match BlockIndex::load(index_path(path)) {
+            Ok(index) if index.validate(&mmap[..], modified).is_ok() => index,
+            _ => BlockIndex::build(path)?,
+        };
+        Self::new(Arc::new(mmap), index)
+    }
+
+    /// Creates a reader for in-memory data described by a complete index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::InvalidIndex`] if the index is incomplete or does
+    /// not match `data`.
+    pub fn new<T>(data: Arc<T>, index: BlockIndex) -> Result<Self>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        index.validate(data.as_ref().as_ref(), None)?;
+        Ok(Self {
+            reader: CachedReader::new(data),
+            index,
+        })
+    }
+
+    /// Sets how many decoded blocks are cached, [`DEFAULT_CACHE_BLOCKS`] by default.
+    ///
+    /// Zero disables the cache.
+    pub fn cache_capacity(self, blocks: usize) -> Self {
+        self.reader.set_capacity(blocks);
+        self
+    }
+
+    /// Returns the decompressed length of the file.
+    pub fn len(&self) -> u64 {
+        self.index.indexed_len()
+    }
+
+    /// Returns `true` if the file decompresses to nothing.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the index of the file's blocks.
+    pub fn index(&self) -> &BlockIndex {
+        &self.index
+    }
+
+    /// Returns how many blocks were served from the cache and how many were decoded.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.reader.stats()
+    }
+
+    /// Reads decompressed data starting at `offset` into `buf`.
+    ///
+    /// Returns the number of bytes read, which is less than `buf.len()` only when
+    /// the end of the data is reached, and zero for offsets at or past the end.
+    ///
+    /// # Errors
+    ///
+    /// Any error from decoding an overlapping block, as returned by
+    /// [`crate::decompress_block_into`], or [`Error::InvalidIndex`] if a block
+    /// does not decompress to the length the index gives it.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read_at(
+            offset,
+            buf,
+            self.len(),
+            |pos| {
+                *self
+                    .index
+                    .find(pos)
+                    .expect("complete index covers the data")
+            },
+            |data, entry| {
+                let mut out = Vec::new();
+                decompress_indexed_block_into(
+                    data,
+                    Some(entry.index),
+                    BlockRange::new(entry.start_bit, entry.end_bit, entry.level),
+                    &mut out,
+                )?;
+                Ok(out)
+            },
+        )
+    }
+}
+
+impl Unit for IndexEntry {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn decompressed_offset(&self) -> u64 {
+        self.decompressed_offset
+    }
+
+    fn decompressed_len(&self) -> u64 {
+        self.decompressed_len
+    }
+
+    fn length_mismatch(&self, len: usize) -> Error {
+        Error::InvalidIndex {
+            reason: format!(
+                "block {} decompressed to {} bytes, the index says {}",
+                self.index, len, self.decompressed_len
+            ),
+        }
+    }
+}
+
+/// A part of the data that is decoded on its own, such as a bzip2 block or a
+/// zstd frame, as described by the table of a [`CachedReader`]'s owner.
+pub(crate) trait Unit {
+    /// Position of the unit in the file, which keys it in the cache
+    fn index(&self) -> usize;
+    /// Offset of the unit's first byte within the decompressed output
+    fn decompressed_offset(&self) -> u64;
+    /// Number of bytes the unit decompresses to, according to the table
+    fn decompressed_len(&self) -> u64;
+    /// Error for a unit that decompressed to `len` bytes instead
+    fn length_mismatch(&self, len: usize) -> Error;
+}
+
+/// Random access to compressed data made of units decoded on their own.
+///
+/// Holds what [`IndexedBz2`] and [`crate::SeekableZstd`] have in common: the
+/// data, the cache of decoded units and its statistics, and the loop serving a
+/// read from the units it overlaps. Each reader only finds the units in its own
+/// table and decodes them.
+pub(crate) struct CachedReader {
+    data: SharedData,
+    cache: Mutex<BlockCache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CachedReader {
+    pub(crate) fn new(data: SharedData) -> Self {
+        Self {
+            data,
+            cache: Mutex::new(BlockCache::new(DEFAULT_CACHE_BLOCKS)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn set_capacity(&self, units: usize) {
+        self.lock_cache().set_capacity(units);
+    }
+
+    pub(crate) fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reads decompressed data starting at `offset` into `buf`, for data that
+    /// decompresses to `len` bytes.
+    ///
+    /// `find` returns the unit holding a decompressed offset below `len`, and
+    /// `decode` decodes a unit from the compressed data. Units that decode to
+    /// another length than their table gives are rejected.
+    pub(crate) fn read_at<U: Unit>(
+        &self,
+        offset: u64,
+        buf: &mut [u8],
+        len: u64,
+        find: impl Fn(u64) -> U,
+        decode: impl Fn(&[u8], &U) -> Result<Vec<u8>>,
+    ) -> Result<usize> {
+        let end = len.min(offset.saturating_add(buf.len() as u64));
+        let mut pos = offset;
+        while pos < end {
+            let unit = find(pos);
+            let decoded = self.unit(&unit, &decode)?;
+
+            let unit_start = unit.decompressed_offset();
+            let unit_end = unit_start + unit.decompressed_len();
+            let from = (pos - unit_start) as usize;
+            let to = (end.min(unit_end) - unit_start) as usize;
+            let written = (pos - offset) as usize;
+            buf[written..written + to - from].copy_from_slice(&decoded[from..to]);
+            pos += (to - from) as u64;
+        }
+        Ok(end.saturating_sub(offset) as usize)
+    }
+
+    /// Returns a decoded unit, from the cache if possible.
+    fn unit<U: Unit>(
+        &self,
+        unit: &U,
+        decode: impl Fn(&[u8], &U) -> Result<Vec<u8>>,
+    ) -> Result<Arc<Vec<u8>>> {
+        if let Some(decoded) = self.lock_cache().get(unit.index()) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(decoded);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // Decode without holding the lock, so other threads can use the cache
+        let out = decode(self.data.as_ref().as_ref(), unit)?;
+        if out.len() as u64 != unit.decompressed_len() {
+            return Err(unit.length_mismatch(out.len()));
+        }
+        let decoded = Arc::new(out);
+        self.lock_cache().insert(unit.index(), decoded.clone());
+        Ok(decoded)
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, BlockCache> {
+        // The cache is only ever left consistent, even by a panicking thread
+        self.cache
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Least-recently-used cache of decoded blocks, keyed by block index.
+///
+/// Most recently used first. Capacities are small, so a linear scan is cheaper
+/// than maintaining a map alongside the order.
+pub(crate) struct BlockCache {
+    capacity: usize,
+    blocks: VecDeque<(usize, Arc<Vec<u8>>)>,
+}
+
+impl BlockCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.blocks.truncate(capacity);
+    }
+
+    pub(crate) fn get(&mut self, index: usize) -> Option<Arc<Vec<u8>>> {
+        let pos = self
+            .blocks
+            .iter()
+            .position(|(cached, _)| *cached == index)?;
+        let entry = self.blocks.remove(pos)?;
+        let block = entry.1.clone();
+        self.blocks.push_front(entry);
+        Some(block)
+    }
+
+    pub(crate) fn insert(&mut self, index: usize, block: Arc<Vec<u8>>) {
+        if self.capacity == 0 || self.blocks.iter().any(|(cached, _)| *cached == index) {
+            return;
+        }
+        self.blocks.truncate(self.capacity - 1);
+        self.blocks.push_front((index, block));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_cache_evicts_least_recently_used() {
+        let mut cache = BlockCache::new(2);
+        cache.insert(0, Arc::new(vec![0]));
+        cache.insert(1, Arc::new(vec![1]));
+        // Touching block 0 makes block 1 the one to go
+        assert!(cache.get(0).is_some());
+        cache.insert(2, Arc::new(vec![2]));
+        assert!(cache.get(1).is_none());
+        assert_eq!(cache.get(0).unwrap().as_slice(), &[0]);
+        assert_eq!(cache.get(2).unwrap().as_slice(), &[2]);
+
+        cache.set_capacity(0);
+        cache.insert(3, Arc::new(vec![3]));
+        assert!(cache.get(3).is_none());
+    }
+}
@@ -10,13 +10,16 @@
 //! - **Streaming API**: Implements `std::io::Read` for easy integration
 //! - **Memory-efficient**: Uses bounded channels to limit memory usage
 //! - **Zero-copy where possible**: Memory-mapped I/O for file access
+//! - **Parallel compression**: `Bz2Encoder` implements `std::io::Write`, with the
+//!   `encoder` feature
 //!
 //! # Architecture
 //!
 //! The library uses a multi-stage pipeline:
 //!
 //! 1. **Scanning**: Identifies block boundaries using parallel pattern matching
-//! 2. **Decompression**: Processes blocks in parallel using Rayon
+//! 2. **Decompression**: Processes blocks in parallel using Rayon, with the
+//!    pure-Rust [`BlockDecoder`]
 //! 3. **Reordering**: Ensures output maintains correct block order
 //!
 //! # Quick Start
@@ -42,8 +45,8 @@
 //! let compressed_data = std::fs::read("file.bz2").unwrap();
 //! let block_receiver = scan_blocks(&compressed_data);
 //!
-//! for (start_bit, end_bit) in block_receiver {
-//!     let decompressed = decompress_block(&compressed_data, start_bit, end_bit).unwrap();
+//! for block in block_receiver {
+//!     let decompressed = decompress_block(&compressed_data, block).unwrap();
 //!     // Process decompressed block...
 //! }
 //! ```
@@ -58,22 +61,84 @@
 //! All public types are thread-safe. The library uses Rayon's global thread pool by default,
 //! but creates dedicated pools where needed to avoid deadlocks.
 
+pub mod block;
+pub mod cancel;
+pub mod crc;
 pub mod decoder;
+#[cfg(feature = "encoder")]
+pub mod encoder;
+pub mod error;
+pub mod index;
+pub mod indexed;
+pub mod recovery;
 pub mod scanner;
-pub use decoder::Bz2Decoder;
-pub use scanner::{extract_bits, MarkerType, Scanner};
+#[cfg(feature = "seekable")]
+pub mod seekable;
+mod segmenter;
+pub mod window;
+pub use block::{BlockDecoder, BlockError};
+pub use cancel::CancellationToken;
+pub use crc::{CrcMismatch, StreamCrc};
+pub use decoder::{Bz2Decoder, Bz2DecoderBuilder, DecodedBlock, DecodedBlocks};
+#[cfg(feature = "encoder")]
+pub use encoder::{Bz2Encoder, Bz2EncoderBuilder};
+pub use error::{Error, Result};
+pub use index::{index_path, BlockIndex, IndexEntry, SourceInfo};
+pub use indexed::{CacheStats, IndexedBz2};
+pub use recovery::{recover, FillMode, LostBlock, RecoveryBuilder, RecoveryReport};
+pub use scanner::{
+    extract_bits, stream_level, Confidence, GapKind, MarkerType, Scanner, StreamMarker,
+};
+#[cfg(feature = "seekable")]
+pub use seekable::{SeekEntry, SeekTable, SeekableZstd};
+pub use window::{ReorderBuffer, ReorderWindow};
 
-use anyhow::{Context, Result};
-use bzip2::read::BzDecoder;
 use crossbeam_channel::bounded;
-use std::collections::HashMap;
+use std::cell::RefCell;
 use std::io::Read;
+use std::sync::Arc;
+
+/// Level assumed for blocks whose stream header could not be found.
+///
+/// Level 9 has the largest block size, so it can decode blocks of any stream.
+pub const DEFAULT_LEVEL: u8 = 9;
+
+/// Location of a single bzip2 block within the compressed data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRange {
+    /// Bit offset of the block magic
+    pub start_bit: u64,
+    /// Bit offset where the block ends (exclusive): the next block magic, the
+    /// end-of-stream marker, or the end of the data
+    pub end_bit: u64,
+    /// Level from the `BZhN` header of the stream containing this block (1-9).
+    ///
+    /// The stream's maximum block size is `level * 100_000` bytes, and blocks are
+    /// decoded with a workspace of that size.
+    pub level: u8,
+}
+
+impl BlockRange {
+    /// Creates a block range.
+    pub fn new(start_bit: u64, end_bit: u64, level: u8) -> Self {
+        Self {
+            start_bit,
+            end_bit,
+            level,
+        }
+    }
+}
 
 /// Scans bzip2 data for block boundaries and returns them via a channel.
 ///
 /// This function spawns background threads to scan the data in parallel and identify
 /// block start and end positions. The results are sent through a channel as
-/// (start_bit, end_bit) tuples representing block boundaries.
+/// [`BlockRange`]s, which also carry the level declared by the stream header
+/// (`BZh1`..`BZh9`) that the block belongs to.
+///
+/// The background threads outlive this call, so `data` is first copied onto the
+/// heap. For large or memory-mapped inputs use [`scan_blocks_shared`] or
+/// [`scan_blocks_scoped`], which scan the data in place.
 ///
 /// # Architecture
 ///
@@ -87,7 +152,7 @@ use std::io::Read;
 ///
 /// # Returns
 ///
-/// A receiver that yields (start_bit, end_bit) tuples for each block found.
+/// A receiver that yields a [`BlockRange`] for each block found.
 /// The receiver will be closed when all blocks have been identified.
 ///
 /// # Performance
@@ -104,76 +169,195 @@ use std::io::Read;
 /// let data = std::fs::read("file.bz2").unwrap();
 /// let blocks = scan_blocks(&data);
 ///
-/// for (start, end) in blocks {
-///     println!("Block from bit {} to bit {}", start, end);
+/// for block in blocks {
+///     println!("Block from bit {} to bit {}", block.start_bit, block.end_bit);
+/// }
+/// ```
+pub fn scan_blocks(data: &[u8]) -> crossbeam_channel::Receiver<BlockRange> {
+    // Clone data into an Arc for safe sharing across threads
+    scan_blocks_shared(Arc::new(data.to_vec()))
+}
+
+/// Same as [`scan_blocks`], but scans shared data without copying it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::scan_blocks_shared;
+/// use std::sync::Arc;
+///
+/// let file = std::fs::File::open("file.bz2").unwrap();
+/// let mmap = Arc::new(unsafe { memmap2::Mmap::map(&file).unwrap() });
+///
+/// for block in scan_blocks_shared(mmap) {
+///     println!("Block from bit {} to bit {}", block.start_bit, block.end_bit);
+/// }
+/// ```
+pub fn scan_blocks_shared<T>(data: Arc<T>) -> crossbeam_channel::Receiver<BlockRange>
+where
+    T: AsRef<[u8]> + Send + Sync + 'static,
+{
+    spawn_block_scan(data, Scanner::new(), None, None)
+}
+
+/// Same as [`scan_blocks_shared`], but scans on the given thread pool.
+///
+/// [`scan_blocks_shared`] builds a pool for each scan; this lets many inputs be
+/// scanned on one pool instead. The pool must not be the one whose threads read
+/// from the returned receiver, or they may wait on scan tasks that cannot run.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::scan_blocks_shared_in;
+/// use std::sync::Arc;
+///
+/// let pool = Arc::new(rayon::ThreadPoolBuilder::new().build().unwrap());
+/// for path in ["a.bz2", "b.bz2"] {
+///     let file = std::fs::File::open(path).unwrap();
+///     let mmap = Arc::new(unsafe { memmap2::Mmap::map(&file).unwrap() });
+///     println!("{}: {} blocks", path, scan_blocks_shared_in(pool.clone(), mmap).iter().count());
 /// }
 /// ```
-pub fn scan_blocks(data: &[u8]) -> crossbeam_channel::Receiver<(u64, u64)> {
+pub fn scan_blocks_shared_in<T>(
+    pool: Arc<rayon::ThreadPool>,
+    data: Arc<T>,
+) -> crossbeam_channel::Receiver<BlockRange>
+where
+    T: AsRef<[u8]> + Send + Sync + 'static,
+{
+    spawn_block_scan(data, Scanner::new(), Some(pool), None)
+}
+
+/// Same as [`scan_blocks`], but scans borrowed data on threads of `scope`.
+///
+/// The scanning threads are joined when the scope ends, so `data` does not need
+/// to be copied or shared. Dropping the receiver stops them early.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::scan_blocks_scoped;
+///
+/// let file = std::fs::File::open("file.bz2").unwrap();
+/// let mmap = unsafe { memmap2::Mmap::map(&file).unwrap() };
+///
+/// let count = std::thread::scope(|s| scan_blocks_scoped(s, &mmap).iter().count());
+/// println!("{} blocks", count);
+/// ```
+pub fn scan_blocks_scoped<'scope, 'env>(
+    scope: &'scope std::thread::Scope<'scope, 'env>,
+    data: &'env [u8],
+) -> crossbeam_channel::Receiver<BlockRange> {
+    let (task_sender, task_receiver) = bounded(100);
+
+    scope.spawn(move || {
+        let (chunk_tx, chunk_rx) = bounded(4);
+        scope.spawn(move || Scanner::new().scan_stream(data, 0, DEFAULT_LEVEL, chunk_tx));
+        send_block_ranges(data, chunk_rx, None, &task_sender);
+    });
+
+    task_receiver
+}
+
+/// Starts the scanning pipeline behind [`scan_blocks`] on shared data.
+///
+/// Scanning runs on `pool` if given, or on a dedicated pool otherwise. With
+/// `resume`, given as the start bit and level of a known block, scanning starts
+/// at that block instead of the beginning of the data.
+pub(crate) fn spawn_block_scan(
+    data: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    scanner: Scanner,
+    pool: Option<Arc<rayon::ThreadPool>>,
+    resume: Option<(u64, u8)>,
+) -> crossbeam_channel::Receiver<BlockRange> {
     // Channel for sending block boundaries to the caller
     // Buffer size of 100 allows good throughput without excessive memory use
     let (task_sender, task_receiver) = bounded(100);
 
-    // Clone data into an Arc for safe sharing across threads
-    let data_vec = data.to_vec();
-    let data_arc = std::sync::Arc::new(data_vec);
-    let data_clone = data_arc.clone();
-
     std::thread::spawn(move || {
-        let scanner = Scanner::new();
         // Small buffer for chunks to prevent scanning too far ahead
         // This maintains cache locality and limits memory usage
         let (chunk_tx, chunk_rx) = bounded(4);
 
         // Spawn the actual scanning in a background thread
-        let scan_data = data_clone.clone();
+        let scan_data = data.clone();
+        let scan_start = resume.map_or(0, |(start_bit, _)| start_bit / 8);
+        let scan_level = resume.map_or(DEFAULT_LEVEL, |(_, level)| level);
         let _scan_handle = std::thread::spawn(move || {
-            scanner.scan_stream(&scan_data, 0, chunk_tx);
+            let scan_data = &scan_data.as_ref().as_ref()[scan_start as usize..];
+            let base = scan_start * 8;
+            match pool {
+                Some(pool) => scanner.scan_stream_in(&pool, scan_data, base, scan_level, chunk_tx),
+                None => scanner.scan_stream(scan_data, base, scan_level, chunk_tx),
+            }
         });
 
-        // Reorder chunks and convert markers to block boundaries
-        let mut chunk_buffer: HashMap<usize, Vec<(u64, MarkerType)>> = HashMap::new();
-        let mut next_chunk_idx = 0;
-        let mut current_block_start: Option<u64> = None;
-
-        for (idx, markers) in chunk_rx {
-            chunk_buffer.insert(idx, markers);
-
-            // Process chunks in order
-            while let Some(markers) = chunk_buffer.remove(&next_chunk_idx) {
-                for (marker_pos, mtype) in markers {
-                    match mtype {
-                        MarkerType::Block => {
-                            // Block marker: end previous block (if any) and start new one
-                            if let Some(start) = current_block_start {
-                                if task_sender.send((start, marker_pos)).is_err() {
-                                    return; // Receiver dropped, stop scanning
-                                }
+        send_block_ranges(data.as_ref().as_ref(), chunk_rx, resume, &task_sender);
+    });
+
+    task_receiver
+}
+
+/// Reorders scanned chunks and converts their markers to block boundaries.
+///
+/// Returns once every chunk has been converted, or early if the receiver of
+/// `task_sender` was dropped. See [`spawn_block_scan`] for `resume`.
+fn send_block_ranges(
+    data: &[u8],
+    chunk_rx: crossbeam_channel::Receiver<(usize, Vec<(u64, MarkerType)>)>,
+    resume: Option<(u64, u8)>,
+    task_sender: &crossbeam_channel::Sender<BlockRange>,
+) {
+    let mut chunk_buffer = ReorderBuffer::new();
+    let mut current_block_start: Option<u64> = resume.map(|(start_bit, _)| start_bit);
+    let mut level = resume.map_or(DEFAULT_LEVEL, |(_, level)| level);
+
+    for (idx, markers) in chunk_rx {
+        chunk_buffer.insert(idx, markers);
+
+        // Process chunks in order
+        while let Some(markers) = chunk_buffer.pop() {
+            for (marker_pos, mtype) in markers {
+                // When resuming, the block we start in is already open
+                if resume.is_some_and(|(start_bit, _)| marker_pos <= start_bit) {
+                    continue;
+                }
+                match mtype {
+                    MarkerType::Block => {
+                        // Block marker: end previous block (if any) and start new one
+                        if let Some(start) = current_block_start {
+                            let block = BlockRange::new(start, marker_pos, level);
+                            if task_sender.send(block).is_err() {
+                                return; // Receiver dropped, stop scanning
                             }
-                            current_block_start = Some(marker_pos);
+                        } else {
+                            // First block of a stream: pick up the level from its header
+                            level =
+                                scanner::stream_level(data, marker_pos).unwrap_or(DEFAULT_LEVEL);
                         }
-                        MarkerType::Eos => {
-                            // End-of-stream marker: end current block
-                            if let Some(start) = current_block_start {
-                                if task_sender.send((start, marker_pos)).is_err() {
-                                    return;
-                                }
-                                current_block_start = None;
+                        current_block_start = Some(marker_pos);
+                    }
+                    MarkerType::Eos => {
+                        // End-of-stream marker: end current block
+                        if let Some(start) = current_block_start {
+                            let block = BlockRange::new(start, marker_pos, level);
+                            if task_sender.send(block).is_err() {
+                                return;
                             }
+                            current_block_start = None;
                         }
                     }
                 }
-                next_chunk_idx += 1;
             }
         }
+    }
 
-        // Handle edge case: block without EOS marker (truncated file)
-        if let Some(start) = current_block_start {
-            let end = (data_clone.len() as u64) * 8;
-            let _ = task_sender.send((start, end));
-        }
-    });
-
-    task_receiver
+    // Handle edge case: block without EOS marker (truncated file)
+    if let Some(start) = current_block_start {
+        let end = (data.len() as u64) * 8;
+        let _ = task_sender.send(BlockRange::new(start, end, level));
+    }
 }
 
 /// Decompresses a single bzip2 block and returns the decompressed data.
@@ -185,8 +369,7 @@ pub fn scan_blocks(data: &[u8]) -> crossbeam_channel::Receiver<(u64, u64)> {
 /// # Arguments
 ///
 /// * `data` - The complete bzip2 file data
-/// * `start_bit` - Bit offset where the block starts
-/// * `end_bit` - Bit offset where the block ends
+/// * `block` - Location and stream level of the block, as returned by [`scan_blocks`]
 ///
 /// # Returns
 ///
@@ -194,7 +377,9 @@ pub fn scan_blocks(data: &[u8]) -> crossbeam_channel::Receiver<(u64, u64)> {
 ///
 /// # Errors
 ///
-/// Returns an error if the block is corrupted or cannot be decompressed.
+/// Returns [`Error::BlockDecode`] if the block is corrupted, [`Error::Truncated`]
+/// if it runs into the end of the data, or [`Error::CrcMismatch`] if the
+/// decompressed data does not match the block CRC.
 ///
 /// # Examples
 ///
@@ -204,15 +389,14 @@ pub fn scan_blocks(data: &[u8]) -> crossbeam_channel::Receiver<(u64, u64)> {
 /// let data = std::fs::read("file.bz2").unwrap();
 /// let blocks = scan_blocks(&data);
 ///
-/// if let Some((start, end)) = blocks.iter().next() {
-///     let decompressed = decompress_block(&data, start, end).unwrap();
+/// if let Some(block) = blocks.iter().next() {
+///     let decompressed = decompress_block(&data, block).unwrap();
 ///     println!("Decompressed {} bytes", decompressed.len());
 /// }
 /// ```
-pub fn decompress_block(data: &[u8], start_bit: u64, end_bit: u64) -> Result<Vec<u8>> {
+pub fn decompress_block(data: &[u8], block: BlockRange) -> Result<Vec<u8>> {
     let mut out = Vec::new();
-    let mut scratch = Vec::new();
-    decompress_block_into(data, start_bit, end_bit, &mut out, &mut scratch)?;
+    decompress_block_into(data, block, &mut out)?;
     Ok(out)
 }
 
@@ -224,20 +408,23 @@ pub fn decompress_block(data: &[u8], start_bit: u64, end_bit: u64) -> Result<Vec
 /// # Arguments
 ///
 /// * `data` - The complete bzip2 file data
-/// * `start_bit` - Bit offset where the block starts
-/// * `end_bit` - Bit offset where the block ends
+/// * `block` - Location and stream level of the block, as returned by [`scan_blocks`]
 /// * `out` - Output buffer for decompressed data (will be cleared)
-/// * `scratch` - Scratch buffer for compressed data with header (will be cleared)
 ///
 /// # Performance
 ///
-/// By reusing `scratch` across multiple calls, this function avoids allocating
-/// a new buffer for each block. This is especially important in parallel scenarios
-/// where thousands of blocks may be processed.
+/// Reusing `out` across multiple calls avoids allocating a new buffer for each
+/// block. This is especially important in parallel scenarios where thousands of
+/// blocks may be processed. The block is read in place at its bit offset in
+/// `data`, by a per-thread [`BlockDecoder`], so no copy of the compressed block
+/// is made.
 ///
 /// # Errors
 ///
-/// Returns an error if the block is corrupted or cannot be decompressed.
+/// Returns [`Error::BlockDecode`] if the block is corrupted or cannot be
+/// decompressed (including a block larger than its stream level allows),
+/// [`Error::Truncated`] if it runs into the end of the data without decoding,
+/// or [`Error::CrcMismatch`] if the decompressed data does not match the block CRC.
 ///
 /// # Examples
 ///
@@ -248,36 +435,189 @@ pub fn decompress_block(data: &[u8], start_bit: u64, end_bit: u64) -> Result<Vec
 /// let blocks = scan_blocks(&data);
 ///
 /// let mut out = Vec::new();
-/// let mut scratch = Vec::new();
 ///
-/// for (start, end) in blocks {
-///     decompress_block_into(&data, start, end, &mut out, &mut scratch).unwrap();
+/// for block in blocks {
+///     decompress_block_into(&data, block, &mut out).unwrap();
 ///     // Process `out`...
 /// }
 /// ```
-pub fn decompress_block_into(
+pub fn decompress_block_into(data: &[u8], block: BlockRange, out: &mut Vec<u8>) -> Result<()> {
+    decompress_indexed_block_into(data, None, block, out)?;
+    Ok(())
+}
+
+thread_local! {
+    /// Per-thread block decoder, so its working memory is reused across blocks.
+    static BLOCK_DECODER: RefCell<BlockDecoder> = RefCell::new(BlockDecoder::new());
+}
+
+/// Same as [`decompress_block_into`], but reports the block index in errors.
+///
+/// Returns the bit offset where the block's compressed data ends, which is
+/// `block.end_bit` unless the range holds more than one block.
+pub(crate) fn decompress_indexed_block_into(
     data: &[u8],
-    start_bit: u64,
-    end_bit: u64,
+    block_index: Option<usize>,
+    block: BlockRange,
     out: &mut Vec<u8>,
-    scratch: &mut Vec<u8>,
-) -> Result<()> {
-    scratch.clear();
-    // Add minimal bzip2 header (BZh9 = highest compression level)
-    scratch.extend_from_slice(b"BZh9");
-    // Extract the block bits and append to scratch buffer
-    extract_bits(data, start_bit, end_bit, scratch);
-
-    // Decompress using the bzip2 crate
-    // Note: The last block may not have a proper EOS marker, causing UnexpectedEof
+) -> Result<u64> {
+    // A block that runs into the end of the data (no next block or EOS marker)
+    // and does not decode is most likely cut short rather than corrupt
+    let truncated = block.end_bit >= data.len() as u64 * 8;
+    let truncated_error = || Error::Truncated {
+        block_index,
+        bit_offset: block.start_bit,
+    };
+
+    // The block is decoded in place, at its bit offset in `data`
     out.clear();
-    let mut decoder = BzDecoder::new(&scratch[..]);
-    match decoder.read_to_end(out) {
-        Ok(_) => Ok(()),
-        // UnexpectedEof is expected for the last block without EOS marker
-        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(()),
-        Err(e) => Err(e).context("Failed to decompress block"),
+    let decoded = BLOCK_DECODER.with(|decoder| {
+        decoder
+            .borrow_mut()
+            .decode(data, block.start_bit, block.end_bit, block.level, out)
+    });
+    let data_end = match decoded {
+        Ok(data_end) => data_end,
+        Err(e) if truncated && !matches!(e, BlockError::InvalidLevel(_)) => {
+            return Err(truncated_error())
+        }
+        Err(e) => {
+            return Err(Error::BlockDecode {
+                block_index,
+                start_bit: block.start_bit,
+                end_bit: block.end_bit,
+                source: e.into(),
+            })
+        }
+    };
+
+    match crc::verify_block(data, block_index, block.start_bit, out) {
+        Ok(_) => Ok(data_end),
+        Err(_) if truncated => Err(truncated_error()),
+        Err(mismatch) => Err(mismatch.into()),
+    }
+}
+
+/// Maximum number of candidate ranges merged into one block when recovering
+/// from false-positive block magics.
+///
+/// Each extra candidate costs a full decode attempt, so this bounds the work
+/// spent on a block that is genuinely corrupt rather than split.
+pub(crate) const MAX_MERGED_CANDIDATES: usize = 8;
+
+/// Bit range a block was actually decoded from.
+///
+/// Returned by [`decompress_block_merging`]. When the scanner split a block at a
+/// false-positive magic, the span covers several candidate ranges and `merged`
+/// tells how many of the following candidates were absorbed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSpan {
+    /// Bit offset where the block starts
+    pub start_bit: u64,
+    /// Bit offset where the block ends (exclusive)
+    pub end_bit: u64,
+    /// Number of following candidate ranges merged into this block
+    pub merged: usize,
+}
+
+/// Decompresses a block, merging it with the following candidate ranges if needed.
+///
+/// The scanner accepts any 48-bit match of the block magic as a block start, and
+/// compressed payload can contain that bit pattern by chance. Such a false positive
+/// cuts a real block in two, and both halves fail to decode. This function first
+/// tries `block` as given. If that fails (including a CRC mismatch)
+/// and the range does not end at an end-of-stream marker or the end of the data, it
+/// extends the range to the end of the next candidate and retries, up to a small
+/// fixed number of candidates.
+///
+/// Callers iterating over the ranges from [`scan_blocks`] should skip the next
+/// [`BlockSpan::merged`] ranges after a successful merge, since their bits are
+/// part of this block.
+///
+/// # Errors
+///
+/// Returns the error from the original range if no merged range decodes either.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::{decompress_block_merging, scan_blocks};
+///
+/// let data = std::fs::read("file.bz2").unwrap();
+/// let mut out = Vec::new();
+/// let mut skip = 0;
+///
+/// for block in scan_blocks(&data) {
+///     if skip > 0 {
+///         skip -= 1;
+///         continue;
+///     }
+///     let span = decompress_block_merging(&data, block, &mut out).unwrap();
+///     skip = span.merged;
+///     // Process `out`...
+/// }
+/// ```
+pub fn decompress_block_merging(
+    data: &[u8],
+    block: BlockRange,
+    out: &mut Vec<u8>,
+) -> Result<BlockSpan> {
+    decompress_indexed_block_merging(data, None, block, out)
+}
+
+/// Same as [`decompress_block_merging`], but reports the block index in errors.
+pub(crate) fn decompress_indexed_block_merging(
+    data: &[u8],
+    block_index: Option<usize>,
+    block: BlockRange,
+    out: &mut Vec<u8>,
+) -> Result<BlockSpan> {
+    match decompress_indexed_block_into(data, block_index, block, out) {
+        Ok(_) => Ok(BlockSpan {
+            start_bit: block.start_bit,
+            end_bit: block.end_bit,
+            merged: 0,
+        }),
+        Err(first_err) => merge_following(data, block_index, block, out).ok_or(first_err),
+    }
+}
+
+/// Retries a block that failed to decode, extended over the following candidates.
+///
+/// Returns `None` if no merged range decodes.
+pub(crate) fn merge_following(
+    data: &[u8],
+    block_index: Option<usize>,
+    block: BlockRange,
+    out: &mut Vec<u8>,
+) -> Option<BlockSpan> {
+    // Rare path: only build a scanner once a block has actually failed
+    let scanner = Scanner::new();
+    let data_end = data.len() as u64 * 8;
+    let mut merged = block;
+
+    for merged_count in 1..=MAX_MERGED_CANDIDATES {
+        // Blocks never span an end-of-stream marker or the end of the data
+        if merged.end_bit >= data_end
+            || scanner::verify_magic(data, merged.end_bit, scanner::MAGIC_EOS)
+        {
+            break;
+        }
+        // Extend to the end of the next candidate, exactly as scan_blocks would
+        merged.end_bit = scanner
+            .next_marker(data, merged.end_bit + 48, block.level)
+            .map_or(data_end, |(pos, _)| pos);
+
+        if decompress_indexed_block_into(data, block_index, merged, out).is_ok() {
+            return Some(BlockSpan {
+                start_bit: merged.start_bit,
+                end_bit: merged.end_bit,
+                merged: merged_count,
+            });
+        }
     }
+
+    None
 }
 
 /// Decompresses an entire bzip2 file and returns the decompressed data.
@@ -298,9 +638,9 @@ pub fn decompress_block_into(
 /// # Errors
 ///
 /// Returns an error if:
-/// - The file cannot be opened
-/// - The file is not a valid bzip2 file
-/// - Decompression fails
+/// - The file cannot be opened ([`Error::Io`])
+/// - The file is not a valid bzip2 file ([`Error::NotBzip2`])
+/// - Decompression fails (see [`Error`] for the possible causes)
 ///
 /// # Examples
 ///
@@ -313,6 +653,6 @@ pub fn decompress_block_into(
 pub fn parallel_bzip2_cat<P: AsRef<std::path::Path>>(path: P) -> Result<Vec<u8>> {
     let mut decoder = Bz2Decoder::open(path)?;
     let mut data = Vec::new();
-    decoder.read_to_end(&mut data)?;
+    decoder.read_to_end(&mut data).map_err(Error::from)?;
     Ok(data)
 }
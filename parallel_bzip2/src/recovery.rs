@@ -0,0 +1,367 @@
+//! Salvaging the intact blocks of a damaged bzip2 file.
+//!
+//! Every block of a bzip2 stream carries its own CRC and can be decoded on its
+//! own, so damage to one block does not have to cost the rest of the file. This
+//! is what `bzip2recover` does by splitting a file into one stream per block;
+//! [`recover`] does it in parallel and in one pass. It scans the data with
+//! [`crate::Scanner`], decodes every candidate range, writes out the blocks that
+//! pass their CRC in order, and skips or zero-fills the others. The returned
+//! [`RecoveryReport`] lists what was lost and where.
+//!
+//! Decompressed sizes are not stored in bzip2, so the position and length of lost
+//! data in the original output can only be estimated. The estimate scales the
+//! compressed size of the lost range by the compression ratio of the blocks
+//! recovered before it.
+
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::decoder::block_size_estimate;
+use crate::{
+    decompress_indexed_block_into, merge_following, scan_blocks_scoped, BlockRange, BlockSpan,
+    Result,
+};
+
+/// Longest gap after a decoded block that does not hide a lost block.
+///
+/// A damaged end-of-stream marker leaves the block before it running into the
+/// next stream: the 48-bit marker, the 32-bit combined CRC, up to 7 bits of
+/// padding and the 32-bit header of the next stream. Even an empty block is
+/// longer than that.
+const MAX_TRAILER_BITS: u64 = 48 + 32 + 7 + 32;
+
+/// Candidate ranges decoded per batch and thread.
+///
+/// Blocks are written in order once their whole batch is decoded, so this trades
+/// memory for fewer idle threads at the end of each batch.
+const RANGES_PER_THREAD: usize = 4;
+
+/// What to write in place of data that could not be recovered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum FillMode {
+    /// Leave it out, so the output holds only recovered data
+    #[default]
+    Skip,
+    /// Write zeros of the estimated decompressed length, so that recovered data
+    /// stays near its original offset
+    Zeros,
+}
+
+/// A range of the compressed data that could not be recovered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LostBlock {
+    /// Index of the candidate range in scan order, as in [`crate::Error`]
+    pub block_index: usize,
+    /// Bit offset where the lost data starts
+    pub start_bit: u64,
+    /// Bit offset where the lost data ends (exclusive)
+    pub end_bit: u64,
+    /// Estimated offset of the lost data in the original decompressed data
+    pub estimated_offset: u64,
+    /// Estimated decompressed length of the lost data
+    pub estimated_len: u64,
+    /// Offset in the recovered output where the data is missing, or where its
+    /// zero fill starts
+    pub output_offset: u64,
+    /// Why the range could not be decoded
+    pub error: String,
+}
+
+/// Outcome of [`recover`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RecoveryReport {
+    /// Length of the compressed input in bytes
+    pub input_len: u64,
+    /// How lost data was handled in the output
+    pub fill: FillMode,
+    /// Blocks decoded and verified against their CRC
+    pub recovered_blocks: usize,
+    /// Decompressed bytes recovered
+    pub recovered_bytes: u64,
+    /// Bytes written to the output, including any zero fill
+    pub output_len: u64,
+    /// Ranges that could not be recovered, in file order
+    pub lost: Vec<LostBlock>,
+}
+
+impl RecoveryReport {
+    /// Returns `true` if every block was recovered.
+    pub fn is_complete(&self) -> bool {
+        self.lost.is_empty()
+    }
+
+    /// Returns the estimated number of decompressed bytes lost.
+    pub fn estimated_lost_bytes(&self) -> u64 {
+        self.lost.iter().map(|lost| lost.estimated_len).sum()
+    }
+}
+
+/// Recovers what it can from damaged bzip2 data, writing it to `out`.
+///
+/// Uses Rayon's global thread pool and skips lost data; see [`RecoveryBuilder`]
+/// for the other options.
+///
+/// # Errors
+///
+/// Only errors from writing to `out`. Damage to the input is reported in the
+/// returned [`RecoveryReport`] instead.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::recover;
+///
+/// let data = std::fs::read("damaged.bz2").unwrap();
+/// let mut out = Vec::new();
+/// let report = recover(&data, &mut out).unwrap();
+/// for lost in &report.lost {
+///     eprintln!("lost bits {}..{}: {}", lost.start_bit, lost.end_bit, lost.error);
+/// }
+/// ```
+pub fn recover<W: Write>(data: &[u8], out: W) -> Result<RecoveryReport> {
+    RecoveryBuilder::new().recover(data, out)
+}
+
+/// Configures and runs a recovery.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::{FillMode, RecoveryBuilder};
+/// use std::fs::File;
+///
+/// let data = std::fs::read("damaged.bz2").unwrap();
+/// let report = RecoveryBuilder::new()
+///     .fill(FillMode::Zeros)
+///     .num_threads(8)
+///     .recover(&data, File::create("recovered").unwrap())
+///     .unwrap();
+/// println!("{} blocks recovered", report.recovered_blocks);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryBuilder {
+    fill: FillMode,
+    pool: Option<Arc<rayon::ThreadPool>>,
+    num_threads: Option<usize>,
+}
+
+impl RecoveryBuilder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets what is written in place of lost data, [`FillMode::Skip`] by default.
+    pub fn fill(mut self, fill: FillMode) -> Self {
+        self.fill = fill;
+        self
+    }
+
+    /// Decodes blocks on the given thread pool.
+    ///
+    /// Takes precedence over [`num_threads`](Self::num_threads).
+    pub fn thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Decodes blocks on a new pool with this many threads.
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Recovers what it can from damaged bzip2 data, writing it to `out`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::Error::Io`] if a thread pool was requested with
+    /// [`num_threads`](Self::num_threads) and could not be created, or if
+    /// writing to `out` fails.
+    pub fn recover<W: Write>(self, data: &[u8], out: W) -> Result<RecoveryReport> {
+        let pool = match (self.pool, self.num_threads) {
+            (Some(pool), _) => Some(pool),
+            (None, Some(num_threads)) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(num_threads)
+                    .build()
+                    .map_err(io::Error::other)?,
+            )),
+            (None, None) => None,
+        };
+        let threads = pool
+            .as_ref()
+            .map_or_else(rayon::current_num_threads, |pool| {
+                pool.current_num_threads()
+            });
+        let batch_len = threads * RANGES_PER_THREAD;
+
+        let mut salvager = Salvager::new(data.len() as u64, self.fill, out);
+        std::thread::scope(|s| -> Result<()> {
+            let ranges = scan_blocks_scoped(s, data);
+            let mut batch = Vec::with_capacity(batch_len);
+            let mut first_index = 0;
+            loop {
+                batch.extend(ranges.iter().take(batch_len));
+                if batch.is_empty() {
+                    return Ok(());
+                }
+                let decode = || {
+                    batch
+                        .par_iter()
+                        .enumerate()
+                        .map(|(i, range)| decode_range(data, first_index + i, *range))
+                        .collect::<Vec<_>>()
+                };
+                let outcomes = match &pool {
+                    Some(pool) => pool.install(decode),
+                    None => decode(),
+                };
+                for (i, (range, outcome)) in batch.drain(..).zip(outcomes).enumerate() {
+                    salvager.push(first_index + i, range, outcome)?;
+                }
+                first_index += batch_len;
+            }
+        })?;
+        salvager.finish()
+    }
+}
+
+/// Result of decoding one candidate range.
+enum Outcome {
+    /// The block decoded and passed its CRC
+    Decoded {
+        span: BlockSpan,
+        /// Where the block's compressed data ends, if it was decoded from the
+        /// range as given
+        data_end: Option<u64>,
+        data: Vec<u8>,
+    },
+    /// The range did not decode, even merged with the following ones
+    Lost(String),
+}
+
+fn decode_range(data: &[u8], block_index: usize, range: BlockRange) -> Outcome {
+    let mut out = Vec::new();
+    let first_err = match decompress_indexed_block_into(data, Some(block_index), range, &mut out) {
+        Ok(data_end) => {
+            let span = BlockSpan {
+                start_bit: range.start_bit,
+                end_bit: range.end_bit,
+                merged: 0,
+            };
+            return Outcome::Decoded {
+                span,
+                data_end: Some(data_end),
+                data: out,
+            };
+        }
+        Err(e) => e,
+    };
+    // The range may be half of a block split by a false-positive magic
+    match merge_following(data, Some(block_index), range, &mut out) {
+        Some(span) => Outcome::Decoded {
+            span,
+            data_end: None,
+            data: out,
+        },
+        None => Outcome::Lost(first_err.to_string()),
+    }
+}
+
+/// Writes decoded ranges in order and accounts for the lost ones.
+struct Salvager<W> {
+    out: W,
+    report: RecoveryReport,
+    /// Candidate ranges still to be skipped, as part of a merged block
+    skip: usize,
+    /// Estimated position in the original decompressed data
+    original_offset: u64,
+    /// Compressed bits and decompressed bytes of the blocks recovered so far
+    recovered_bits: u64,
+}
+
+impl<W: Write> Salvager<W> {
+    fn new(input_len: u64, fill: FillMode, out: W) -> Self {
+        Self {
+            out,
+            report: RecoveryReport {
+                input_len,
+                fill,
+                ..RecoveryReport::default()
+            },
+            skip: 0,
+            original_offset: 0,
+            recovered_bits: 0,
+        }
+    }
+
+    fn push(&mut self, block_index: usize, range: BlockRange, outcome: Outcome) -> Result<()> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return Ok(());
+        }
+        match outcome {
+            Outcome::Decoded {
+                span,
+                data_end,
+                data,
+            } => {
+                self.out.write_all(&data)?;
+                let data_end = data_end.unwrap_or(span.end_bit);
+                self.report.recovered_blocks += 1;
+                self.report.recovered_bytes += data.len() as u64;
+                self.report.output_len += data.len() as u64;
+                self.original_offset += data.len() as u64;
+                self.recovered_bits += data_end - span.start_bit;
+                self.skip = span.merged;
+
+                // Data left over after the block is one whose magic was damaged
+                if span.end_bit - data_end > MAX_TRAILER_BITS {
+                    let lost = BlockRange::new(data_end, span.end_bit, range.level);
+                    let error = "no block magic where the previous block ends".to_string();
+                    self.lose(block_index, lost, error)?;
+                }
+            }
+            Outcome::Lost(error) => self.lose(block_index, range, error)?,
+        }
+        Ok(())
+    }
+
+    fn lose(&mut self, block_index: usize, range: BlockRange, error: String) -> Result<()> {
+        let bits = range.end_bit - range.start_bit;
+        let estimated_len = match self.recovered_bits {
+            0 => block_size_estimate(range.level) as u64,
+            recovered_bits => {
+                (bits as u128 * self.report.recovered_bytes as u128 / recovered_bits as u128) as u64
+            }
+        };
+        self.report.lost.push(LostBlock {
+            block_index,
+            start_bit: range.start_bit,
+            end_bit: range.end_bit,
+            estimated_offset: self.original_offset,
+            estimated_len,
+            output_offset: self.report.output_len,
+            error,
+        });
+        self.original_offset += estimated_len;
+
+        if self.report.fill == FillMode::Zeros {
+            io::copy(&mut io::repeat(0).take(estimated_len), &mut self.out)?;
+            self.report.output_len += estimated_len;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<RecoveryReport> {
+        self.out.flush()?;
+        Ok(self.report)
+    }
+}
@@ -13,7 +13,14 @@
 //! Since these markers can appear at any bit offset (not just byte boundaries), the
 //! scanner generates 8 shifted patterns for each magic number and uses Aho-Corasick
 //! for efficient multi-pattern matching. Candidates are then verified by extracting
-//! and comparing the full 48-bit value.
+//! and comparing the full 48-bit value. Block candidates must also be followed by
+//! a plausible block header for the level of their stream (see
+//! [`block_confidence`]), which weeds out most magics that occur by chance in
+//! compressed data.
+//!
+//! On top of the raw markers, [`Scanner::scan_layout`] reports how the input is
+//! laid out in streams: where each `BZh` header starts, the combined CRC after
+//! each end-of-stream marker, and any padding or garbage between streams.
 //!
 //! # Performance
 //!
@@ -24,6 +31,8 @@
 
 use aho_corasick::AhoCorasick;
 
+use crate::{ReorderBuffer, DEFAULT_LEVEL};
+
 /// Marker type found in bzip2 streams.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MarkerType {
@@ -33,13 +42,89 @@ pub enum MarkerType {
     Eos,
 }
 
+/// A feature of the stream layout of bzip2 data, see [`Scanner::scan_layout`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamMarker {
+    /// A `BZh1`..`BZh9` stream header.
+    StreamStart {
+        /// Byte offset of the `B` of the header
+        byte_offset: u64,
+        /// Level from the header (1-9)
+        level: u8,
+    },
+    /// A block magic inside a stream.
+    Block {
+        /// Bit offset of the block magic
+        bit_offset: u64,
+        /// CRC stored after the magic, if the data holds it
+        stored_crc: Option<u32>,
+        /// How plausible the block header is, given the stream's level
+        confidence: Confidence,
+    },
+    /// An end-of-stream magic.
+    StreamEnd {
+        /// Bit offset of the end-of-stream magic
+        bit_offset: u64,
+        /// Combined CRC of the stream stored after the magic, if the data holds it
+        combined_crc: Option<u32>,
+    },
+    /// Bytes outside any stream: before the first, between two, or after the last.
+    ///
+    /// Markers found in such a region, without a stream header in front of them,
+    /// are considered part of it.
+    Gap {
+        /// Byte offset where the region starts
+        start_byte: u64,
+        /// Byte offset where the region ends (exclusive)
+        end_byte: u64,
+        /// What the region holds
+        kind: GapKind,
+    },
+}
+
+/// How likely a block candidate is to start a real block, see [`block_confidence`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// The data ends before the block header could be checked
+    Unchecked,
+    /// The header is valid, but uses features bzip2 itself never writes
+    Low,
+    /// The header is valid and looks like one written by bzip2
+    High,
+}
+
+/// Contents of a [`StreamMarker::Gap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapKind {
+    /// Only zero bytes, as left by tools that pad streams to a block size
+    Padding,
+    /// Anything else; bzip2 stops decoding at such data
+    Garbage,
+}
+
 /// Block start magic number from bzip2 specification.
 /// This is π represented in hexadecimal: 3.14159265359...
-const MAGIC_BLOCK: u64 = 0x314159265359;
+pub(crate) const MAGIC_BLOCK: u64 = 0x314159265359;
 
 /// End-of-stream magic number from bzip2 specification.
 /// This is √π represented in hexadecimal: 1.77245385090...
-const MAGIC_EOS: u64 = 0x177245385090;
+pub(crate) const MAGIC_EOS: u64 = 0x177245385090;
+
+/// Longest block header checked by [`block_confidence`], in bits: magic, CRC,
+/// randomised flag, origPtr, the full symbol bitmap, table and selector counts.
+const MAX_BLOCK_HEADER_BITS: u64 = 48 + 32 + 1 + 24 + 16 + 16 * 16 + 3 + 15;
+
+/// Bytes needed after the start of a block candidate to check its header at
+/// any bit alignment.
+pub(crate) const BLOCK_HEADER_BYTES: u64 = (MAX_BLOCK_HEADER_BITS + 7).div_ceil(8);
+
+/// Default number of bytes scanned per parallel task.
+///
+/// 1MB chunks provide a good balance between:
+/// - Cache locality (fits in L3 cache on most CPUs)
+/// - Parallelism (enough chunks to keep all cores busy)
+/// - Overhead (not too many small tasks)
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
 
 /// Parallel scanner for bzip2 block boundaries.
 ///
@@ -52,6 +137,8 @@ pub struct Scanner {
     /// Pattern metadata: (magic_number, marker_type, bit_shift)
     /// Used to verify and classify matches from the Aho-Corasick automaton
     patterns_info: Vec<(u64, MarkerType, usize)>,
+    /// Number of bytes scanned per parallel task
+    chunk_size: usize,
 }
 
 impl Scanner {
@@ -101,27 +188,52 @@ impl Scanner {
 
         let ac = AhoCorasick::new(patterns).unwrap();
 
-        Self { ac, patterns_info }
+        Self {
+            ac,
+            patterns_info,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Creates a scanner that splits the input into chunks of `chunk_size` bytes.
+    ///
+    /// Smaller chunks let scanning start producing blocks sooner and use less
+    /// memory per task; larger chunks reduce per-task overhead. The default is
+    /// [`DEFAULT_CHUNK_SIZE`].
+    pub fn with_chunk_size(chunk_size: usize) -> Self {
+        Self {
+            // Chunks must be non-empty to make progress
+            chunk_size: chunk_size.max(1),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the number of bytes scanned per parallel task.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
     }
 
     /// Scans data in parallel and streams marker locations to a channel.
     ///
-    /// This method divides the input into 1MB chunks and processes them in parallel
-    /// using a dedicated thread pool. Results are sent as (chunk_index, markers)
-    /// tuples, allowing the caller to reorder them if needed.
+    /// This method divides the input into chunks (1MB by default) and processes them
+    /// in parallel using a dedicated thread pool. Results are sent as
+    /// (chunk_index, markers) tuples, in chunk order.
     ///
     /// # Arguments
     ///
     /// * `data` - The bzip2 compressed data to scan
     /// * `base_offset_bits` - Bit offset to add to all marker positions (for multi-file scanning)
+    /// * `level` - Level of the stream `data` starts in, or [`DEFAULT_LEVEL`] if
+    ///   unknown; block candidates are checked against their stream's level
     /// * `sender` - Channel to send results: (chunk_index, Vec<(bit_position, marker_type)>)
     ///
     /// # Performance
     ///
-    /// - **Chunk size**: 1MB for optimal cache locality
+    /// - **Chunk size**: 1MB by default for good cache locality
     /// - **Overlap**: 8 bytes between chunks to catch markers at boundaries
     /// - **Thread pool**: Creates a dedicated pool to avoid deadlock with caller's pool
-    /// - **Blocking**: This method blocks until all chunks are processed
+    /// - **Blocking**: This method blocks until all chunks are processed, or until a
+    ///   send fails because the receiver was dropped
     ///
     /// # Algorithm
     ///
@@ -134,18 +246,9 @@ impl Scanner {
         &self,
         data: &[u8],
         base_offset_bits: u64,
+        level: u8,
         sender: crossbeam_channel::Sender<(usize, Vec<(u64, MarkerType)>)>,
     ) {
-        // Performance: 1MB chunks provide good balance between:
-        // - Cache locality (fits in L3 cache on most CPUs)
-        // - Parallelism (enough chunks to keep all cores busy)
-        // - Overhead (not too many small tasks)
-        let chunk_size = 1024 * 1024;
-        // Overlap ensures we don't miss markers that span chunk boundaries
-        let overlap = 8;
-        let len = data.len();
-        let num_chunks = len.div_ceil(chunk_size);
-
         // Create a dedicated thread pool to prevent deadlock:
         // If we used the global pool and the caller is also using it (e.g., via par_bridge),
         // we could deadlock when all threads are waiting for scanner results but the scanner
@@ -155,21 +258,72 @@ impl Scanner {
             .build()
             .unwrap();
 
-        // Use pool.scope to allow borrowing `data` in the closure.
-        // This blocks until all tasks are finished, which is desired behavior since
-        // we're in a dedicated scanner thread sending results via channel.
+        self.scan_stream_in(&pool, data, base_offset_bits, level, sender);
+    }
+
+    /// Same as [`Scanner::scan_stream`], but scans on the given thread pool.
+    ///
+    /// The chunk tasks never block the pool: their results are forwarded to
+    /// `sender` by the calling thread, which only keeps a few chunks per pool
+    /// thread in flight. The pool can therefore be shared with other work, as long
+    /// as the calling thread is not one of its workers.
+    pub fn scan_stream_in(
+        &self,
+        pool: &rayon::ThreadPool,
+        data: &[u8],
+        base_offset_bits: u64,
+        level: u8,
+        sender: crossbeam_channel::Sender<(usize, Vec<(u64, MarkerType)>)>,
+    ) {
+        let window = pool.current_num_threads() * 2;
+        pool.in_place_scope(|s| {
+            self.scan_chunks(s, window, data, base_offset_bits, level, &sender)
+        });
+    }
 
-        pool.scope(|s| {
-            for i in 0..num_chunks {
-                let sender = sender.clone();
+    /// Spawns one task per chunk on `scope`, keeping at most `window` in flight.
+    ///
+    /// Runs on the calling thread, which forwards each chunk's markers to `sender`
+    /// in order and stops spawning once the receiver is gone.
+    ///
+    /// A chunk task only knows the level of the stream it is in once it meets a
+    /// stream's first block or an end-of-stream marker. Block candidates in front
+    /// of that are checked against level 9, the loosest bound, and checked again
+    /// here against the level the previous chunks end with.
+    fn scan_chunks<'scope>(
+        &'scope self,
+        scope: &rayon::Scope<'scope>,
+        window: usize,
+        data: &'scope [u8],
+        base_offset_bits: u64,
+        mut level: u8,
+        sender: &crossbeam_channel::Sender<(usize, Vec<(u64, MarkerType)>)>,
+    ) {
+        let chunk_size = self.chunk_size;
+        // Overlap ensures we don't miss markers that span chunk boundaries
+        let overlap = 8;
+        let len = data.len();
+        let num_chunks = len.div_ceil(chunk_size);
+
+        // Chunk tasks report here rather than to `sender`, so a slow consumer
+        // never blocks a pool thread
+        let (chunk_tx, chunk_rx) = crossbeam_channel::unbounded();
+        let mut next_chunk = 0;
+        let mut chunks = ReorderBuffer::new();
+
+        'scan: loop {
+            // Chunks done but waiting for an earlier one count as in flight too
+            while next_chunk < num_chunks && next_chunk - chunks.next_index() < window.max(1) {
+                let i = next_chunk;
+                let chunk_tx = chunk_tx.clone();
                 let start = i * chunk_size;
                 let end = std::cmp::min(start + chunk_size, len);
                 // Extend scan region to catch markers at chunk boundary
                 let scan_end = std::cmp::min(end + overlap, len);
                 let slice = &data[start..scan_end];
 
-                s.spawn(move |_| {
-                    let mut local_markers = Vec::new();
+                scope.spawn(move |_| {
+                    let mut scan = ChunkScan::default();
 
                     // Aho-Corasick finds all pattern matches in O(n) time
                     for mat in self.ac.find_iter(slice) {
@@ -191,16 +345,141 @@ impl Scanner {
                         let (magic, mtype, shift) = self.patterns_info[pattern_id];
                         let rel_bit_offset = (start + start_byte_rel) as u64 * 8 + shift as u64;
 
-                        if verify_magic(data, rel_bit_offset, magic) {
-                            local_markers.push((base_offset_bits + rel_bit_offset, mtype));
+                        if !verify_magic(data, rel_bit_offset, magic) {
+                            continue;
+                        }
+                        let level = match mtype {
+                            // Whatever follows is a new stream or garbage
+                            MarkerType::Eos => *scan.level.insert(DEFAULT_LEVEL),
+                            MarkerType::Block => match stream_level(data, rel_bit_offset) {
+                                Some(level) => *scan.level.insert(level),
+                                None => scan.level.unwrap_or(DEFAULT_LEVEL),
+                            },
+                        };
+                        if could_start_block(data, rel_bit_offset, mtype, level) {
+                            if scan.level.is_none() {
+                                scan.unresolved += 1;
+                            }
+                            scan.markers
+                                .push((base_offset_bits + rel_bit_offset, mtype));
                         }
                     }
 
-                    // Send results for this chunk (ignore errors if receiver dropped)
-                    let _ = sender.send((i, local_markers));
+                    let _ = chunk_tx.send((i, scan));
                 });
+                next_chunk += 1;
             }
-        });
+
+            if chunks.next_index() == next_chunk {
+                break;
+            }
+            // Every spawned task sends exactly once, and we hold a sender ourselves
+            let Ok((i, scan)) = chunk_rx.recv() else {
+                break;
+            };
+            let _ = chunks.insert(i, scan);
+
+            while let Some(mut scan) = chunks.pop() {
+                if level != DEFAULT_LEVEL {
+                    let mut checked = 0;
+                    scan.markers.retain(|&(pos, _)| {
+                        checked += 1;
+                        checked > scan.unresolved
+                            || block_confidence(data, pos - base_offset_bits, level).is_some()
+                    });
+                }
+                level = scan.level.unwrap_or(level);
+
+                // Send results for this chunk; stop scanning if the receiver dropped.
+                // The scope still waits for the chunks already in flight.
+                if sender
+                    .send((chunks.next_index() - 1, scan.markers))
+                    .is_err()
+                {
+                    break 'scan;
+                }
+            }
+        }
+    }
+
+    /// Scans `data` and returns its stream layout in file order.
+    ///
+    /// Markers are found in parallel as with [`Scanner::scan_stream`], then
+    /// grouped into streams: a stream starts at a `BZh` header directly in front of
+    /// a marker and ends after the combined CRC that follows its end-of-stream
+    /// marker, padded to the next byte. Everything else is reported as a
+    /// [`StreamMarker::Gap`]. A stream cut short has no [`StreamMarker::StreamEnd`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use parallel_bzip2::{Scanner, StreamMarker};
+    ///
+    /// let data = std::fs::read("file.bz2").unwrap();
+    /// for marker in Scanner::new().scan_layout(&data) {
+    ///     if let StreamMarker::StreamStart { byte_offset, level } = marker {
+    ///         println!("stream at byte {} with level {}", byte_offset, level);
+    ///     }
+    /// }
+    /// ```
+    pub fn scan_layout(&self, data: &[u8]) -> Vec<StreamMarker> {
+        let (sender, receiver) = crossbeam_channel::bounded(4);
+        std::thread::scope(|s| {
+            s.spawn(|| self.scan_stream(data, 0, DEFAULT_LEVEL, sender));
+
+            let mut layout = LayoutBuilder::new(data);
+            let mut chunks = ReorderBuffer::new();
+            for (idx, markers) in receiver {
+                chunks.insert(idx, markers);
+                while let Some(markers) = chunks.pop() {
+                    for (pos, mtype) in markers {
+                        layout.push(pos, mtype);
+                    }
+                }
+            }
+            layout.finish()
+        })
+    }
+
+    /// Finds the first marker starting at or after `from_bit`.
+    ///
+    /// This is a sequential counterpart to [`Scanner::scan_stream`] for looking a
+    /// short distance ahead, e.g. to find where the next candidate range ends.
+    /// `level` is the level of the stream `from_bit` is in, or [`DEFAULT_LEVEL`]
+    /// if unknown; block candidates are checked against it, unless they are the
+    /// first block of a stream.
+    ///
+    /// # Returns
+    ///
+    /// The bit position and type of the next verified marker, or `None` if there
+    /// are no more markers in `data`
+    pub fn next_marker(&self, data: &[u8], from_bit: u64, level: u8) -> Option<(u64, MarkerType)> {
+        // A marker starting in byte `b` is matched from byte `b + 1` onwards,
+        // so starting the search at this byte covers every position >= from_bit
+        let search_start = std::cmp::min((from_bit / 8) as usize, data.len());
+
+        for mat in self.ac.find_iter(&data[search_start..]) {
+            let match_start = search_start + mat.start();
+            // Same as scan_stream: we need the byte before the match for verification
+            if match_start == 0 {
+                continue;
+            }
+
+            let (magic, mtype, shift) = self.patterns_info[mat.pattern()];
+            let bit_offset = (match_start - 1) as u64 * 8 + shift as u64;
+            if bit_offset >= from_bit
+                && verify_magic(data, bit_offset, magic)
+                && could_start_block(
+                    data,
+                    bit_offset,
+                    mtype,
+                    stream_level(data, bit_offset).unwrap_or(level),
+                )
+            {
+                return Some((bit_offset, mtype));
+            }
+        }
+        None
     }
 }
 
@@ -210,10 +489,105 @@ impl Default for Scanner {
     }
 }
 
+/// Groups markers, in file order, into the streams of [`Scanner::scan_layout`].
+struct LayoutBuilder<'a> {
+    data: &'a [u8],
+    layout: Vec<StreamMarker>,
+    /// Whether the markers pushed so far end inside a stream
+    in_stream: bool,
+    /// Level of the current stream
+    level: u8,
+    /// Byte offset just past the last stream
+    stream_end: u64,
+}
+
+impl<'a> LayoutBuilder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            layout: Vec::new(),
+            in_stream: false,
+            level: crate::DEFAULT_LEVEL,
+            stream_end: 0,
+        }
+    }
+
+    fn push(&mut self, pos: u64, mtype: MarkerType) {
+        if !self.in_stream {
+            // Outside a stream, only a marker behind a header starts a new one
+            let Some(level) = stream_level(self.data, pos) else {
+                return;
+            };
+            let header = pos / 8 - 4;
+            if header < self.stream_end {
+                return;
+            }
+            self.push_gap(header);
+            self.layout.push(StreamMarker::StreamStart {
+                byte_offset: header,
+                level,
+            });
+            self.in_stream = true;
+            self.level = level;
+        }
+
+        let stored = read_u32_at(self.data, pos + 48);
+        match mtype {
+            MarkerType::Block => {
+                let level = self.level;
+                // A header that is invalid at the stream's own level is still
+                // reported, as the block that decoding will fail on
+                let confidence =
+                    block_confidence(self.data, pos, level).unwrap_or(Confidence::Unchecked);
+                self.layout.push(StreamMarker::Block {
+                    bit_offset: pos,
+                    stored_crc: stored,
+                    confidence,
+                })
+            }
+            MarkerType::Eos => {
+                self.layout.push(StreamMarker::StreamEnd {
+                    bit_offset: pos,
+                    combined_crc: stored,
+                });
+                self.in_stream = false;
+                self.stream_end = (pos + 48 + 32).div_ceil(8).min(self.data.len() as u64);
+            }
+        }
+    }
+
+    /// Reports the bytes from the end of the last stream up to `end` as a gap.
+    fn push_gap(&mut self, end: u64) {
+        if end <= self.stream_end {
+            return;
+        }
+        let bytes = &self.data[self.stream_end as usize..end as usize];
+        let kind = if bytes.iter().all(|&byte| byte == 0) {
+            GapKind::Padding
+        } else {
+            GapKind::Garbage
+        };
+        self.layout.push(StreamMarker::Gap {
+            start_byte: self.stream_end,
+            end_byte: end,
+            kind,
+        });
+        self.stream_end = end;
+    }
+
+    fn finish(mut self) -> Vec<StreamMarker> {
+        if !self.in_stream {
+            self.push_gap(self.data.len() as u64);
+        }
+        self.layout
+    }
+}
+
 /// Extracts a range of bits from a byte slice and appends them to the output buffer.
 ///
-/// This function handles bit-level extraction, which is necessary because bzip2 blocks
-/// can start and end at any bit position, not just byte boundaries.
+/// bzip2 blocks can start and end at any bit position, not just byte boundaries.
+/// The decoder reads them in place and never needs this; it is for callers that
+/// want a byte-aligned copy of a block, e.g. to store or send it on its own.
 ///
 /// # Arguments
 ///
@@ -357,7 +731,7 @@ pub fn extract_bits(data: &[u8], start_bit: u64, end_bit: u64, out: &mut Vec<u8>
 /// # Returns
 ///
 /// `true` if the magic number matches, `false` otherwise
-fn verify_magic(data: &[u8], bit_offset: u64, expected_magic: u64) -> bool {
+pub(crate) fn verify_magic(data: &[u8], bit_offset: u64, expected_magic: u64) -> bool {
     let byte_idx = (bit_offset / 8) as usize;
     let shift = (bit_offset % 8) as u8;
 
@@ -384,10 +758,202 @@ fn verify_magic(data: &[u8], bit_offset: u64, expected_magic: u64) -> bool {
     (val & mask) == expected
 }
 
+/// Checks whether a marker could start what its type says.
+///
+/// Block candidates must have a plausible header for `level`, the level of
+/// their stream; end-of-stream markers are only followed by a CRC and cannot be
+/// checked.
+fn could_start_block(data: &[u8], bit_offset: u64, mtype: MarkerType, level: u8) -> bool {
+    mtype == MarkerType::Eos || block_confidence(data, bit_offset, level).is_some()
+}
+
+/// Markers found in one chunk by [`Scanner::scan_chunks`].
+#[derive(Default)]
+struct ChunkScan {
+    markers: Vec<(u64, MarkerType)>,
+    /// Number of leading block markers checked before the stream level was known
+    unresolved: usize,
+    /// Level of the stream at the end of the chunk, if the chunk shows it
+    level: Option<u8>,
+}
+
+/// Checks the header following a block magic at `bit_offset`.
+///
+/// A block starts with the magic, its CRC, a randomised flag, the 24-bit origPtr,
+/// a two-level bitmap of the symbols used, the number of Huffman tables and the
+/// number of selectors. This parses those fields and rejects values that libbz2
+/// refuses to decode: an origPtr beyond the block size of `level`, no symbols
+/// used, fewer than 2 or more than 6 tables, or no selectors. It costs a few
+/// dozen bit reads, far less than a failed decode attempt.
+///
+/// # Returns
+///
+/// `None` if the magic cannot start a valid block, or how confident the header
+/// looks otherwise. Randomised blocks and empty bitmap groups are valid but
+/// never written by bzip2 1.0 and later, so they lower the confidence.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_bzip2::scanner::{block_confidence, Confidence};
+///
+/// let data = std::fs::read("tests/fixtures/fib.bz2").unwrap();
+/// assert_eq!(block_confidence(&data, 32, 9), Some(Confidence::High));
+/// // This fixture holds a randomised block
+/// let data = std::fs::read("tests/fixtures/rand.bz2").unwrap();
+/// assert_eq!(block_confidence(&data, 32, 9), Some(Confidence::Low));
+/// // Only magic and CRC: the rest of the header is missing
+/// assert_eq!(block_confidence(&data[..14], 32, 9), Some(Confidence::Unchecked));
+/// ```
+pub fn block_confidence(data: &[u8], bit_offset: u64, level: u8) -> Option<Confidence> {
+    let mut header = BitReader {
+        data,
+        pos: bit_offset + 48 + 32,
+    };
+    match check_block_header(&mut header, level) {
+        Ok(confidence) => confidence,
+        // Ran out of data: nothing to hold against the candidate yet
+        Err(()) => Some(Confidence::Unchecked),
+    }
+}
+
+/// Parses the block header fields after the CRC; `Err` if the data ends first.
+fn check_block_header(header: &mut BitReader, level: u8) -> Result<Option<Confidence>, ()> {
+    let randomised = header.bits(1)? == 1;
+    let orig_ptr = header.bits(24)?;
+    if orig_ptr >= 10 + 100_000 * u32::from(level) {
+        return Ok(None);
+    }
+
+    let in_use16 = header.bits(16)?;
+    if in_use16 == 0 {
+        return Ok(None);
+    }
+    let mut empty_group = false;
+    for group in 0..16 {
+        if in_use16 & (0x8000 >> group) != 0 && header.bits(16)? == 0 {
+            empty_group = true;
+        }
+    }
+
+    let tables = header.bits(3)?;
+    if !(2..=6).contains(&tables) {
+        return Ok(None);
+    }
+    if header.bits(15)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(if randomised || empty_group {
+        Confidence::Low
+    } else {
+        Confidence::High
+    }))
+}
+
+/// Reads big-endian bit fields at increasing bit offsets.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: u64,
+}
+
+impl BitReader<'_> {
+    /// Reads the next `count` bits (1-24).
+    fn bits(&mut self, count: u32) -> Result<u32, ()> {
+        let byte_idx = (self.pos / 8) as usize;
+        let shift = (self.pos % 8) as u32;
+        let needed = (shift + count).div_ceil(8) as usize;
+        let bytes = self.data.get(byte_idx..byte_idx + needed).ok_or(())?;
+
+        let mut val = 0u32;
+        for &byte in bytes {
+            val = (val << 8) | u32::from(byte);
+        }
+        self.pos += u64::from(count);
+        Ok((val >> (needed as u32 * 8 - shift - count)) & ((1 << count) - 1))
+    }
+}
+
+/// Reads the level from the stream header in front of a stream's first block.
+///
+/// A bzip2 stream starts with the byte-aligned header `BZh` followed by an ASCII
+/// digit `1`-`9`, immediately followed by the first block magic. This looks at the
+/// four bytes before `block_bit` for that header.
+///
+/// # Returns
+///
+/// The level (1-9), or `None` if the block is not preceded by a valid header
+///
+/// # Examples
+///
+/// ```
+/// # use parallel_bzip2::stream_level;
+/// let data = b"BZh61AY&SY";
+/// assert_eq!(stream_level(data, 32), Some(6));
+/// assert_eq!(stream_level(data, 24), None);
+/// ```
+pub fn stream_level(data: &[u8], block_bit: u64) -> Option<u8> {
+    if !block_bit.is_multiple_of(8) {
+        return None;
+    }
+    let block_byte = (block_bit / 8) as usize;
+    let header = data.get(block_byte.checked_sub(4)?..block_byte)?;
+
+    match header {
+        [b'B', b'Z', b'h', digit @ b'1'..=b'9'] => Some(digit - b'0'),
+        _ => None,
+    }
+}
+
+/// Checks whether the stream ending at an end-of-stream marker is followed by
+/// another stream header or by the end of the data.
+///
+/// A stream ends with the 48-bit end-of-stream magic, the 32-bit combined CRC and
+/// padding to the next byte boundary. Anything else found there is trailing
+/// garbage, which bzip2 ignores.
+pub(crate) fn stream_followed_cleanly(data: &[u8], eos_bit: u64) -> bool {
+    let stream_end = (eos_bit + 48 + 32).div_ceil(8) as usize;
+    matches!(
+        data.get(stream_end..),
+        Some([] | [b'B', b'Z', b'h', b'1'..=b'9', ..])
+    )
+}
+
+/// Reads a big-endian 32-bit value starting at an arbitrary bit offset.
+///
+/// This is used to pick up the CRCs that follow the block and end-of-stream
+/// magic numbers, which are not byte-aligned in general.
+///
+/// # Returns
+///
+/// `None` if the data ends before all 32 bits are available
+pub(crate) fn read_u32_at(data: &[u8], bit_offset: u64) -> Option<u32> {
+    let byte_idx = (bit_offset / 8) as usize;
+    let shift = (bit_offset % 8) as u32;
+
+    // 32 bits span 4 bytes when aligned, 5 otherwise
+    let needed = if shift == 0 { 4 } else { 5 };
+    if byte_idx.checked_add(needed)? > data.len() {
+        return None;
+    }
+
+    let mut buf = [0u8; 8];
+    buf[..needed].copy_from_slice(&data[byte_idx..byte_idx + needed]);
+    let val = u64::from_be_bytes(buf);
+
+    Some((val << shift >> 32) as u32)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A block CRC and the smallest valid block header after it: one used
+    /// bitmap group, two tables, one selector.
+    const HEADER: [u8; 14] = [
+        0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x00, 0x00, 0x40, 0x00, 0x7F, 0xFF, 0xA0, 0x00, 0x20,
+    ];
+
     fn scan_to_vec(data: &[u8]) -> Vec<(u64, MarkerType)> {
         let scanner = Scanner::new();
         let (tx, rx) = crossbeam_channel::bounded(100);
@@ -399,7 +965,7 @@ mod tests {
         // Run scan_stream in a scope
         std::thread::scope(|s| {
             s.spawn(|| {
-                scanner.scan_stream(data, 0, tx);
+                scanner.scan_stream(data, 0, DEFAULT_LEVEL, tx);
             });
         });
 
@@ -423,7 +989,7 @@ mod tests {
         // Block Magic: 0x314159265359
         let mut data = Vec::new();
         data.extend_from_slice(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59]); // PI
-        data.extend_from_slice(b"some data");
+        data.extend_from_slice(&HEADER);
 
         let markers = scan_to_vec(&data);
         assert_eq!(markers.len(), 1);
@@ -449,12 +1015,12 @@ mod tests {
 
         // Block 1 at 0
         data.extend_from_slice(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59]);
-        data.extend_from_slice(b"data1");
+        data.extend_from_slice(&HEADER);
 
-        // Block 2 at 6+5 = 11 bytes
+        // Block 2 at 6+14 = 20 bytes
         let pos2 = data.len() as u64 * 8;
         data.extend_from_slice(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59]);
-        data.extend_from_slice(b"data2");
+        data.extend_from_slice(&HEADER);
 
         // EOS
         let pos_eos = data.len() as u64 * 8;
@@ -492,6 +1058,161 @@ mod tests {
         assert!(matches!(markers[0].1, MarkerType::Block));
     }
 
+    #[test]
+    fn test_scan_layout() {
+        let mut data = b"junk".to_vec();
+        data.extend_from_slice(b"BZh5");
+        data.extend_from_slice(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59]);
+        data.extend_from_slice(&HEADER);
+        let eos = data.len() as u64 * 8;
+        data.extend_from_slice(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90, 0x12, 0x34, 0x56, 0x78]);
+        data.extend_from_slice(&[0, 0, 0]);
+        // An empty stream: header directly followed by the end-of-stream magic
+        data.extend_from_slice(b"BZh9");
+        data.extend_from_slice(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90, 0, 0, 0, 0]);
+
+        let layout = Scanner::new().scan_layout(&data);
+        assert_eq!(
+            layout,
+            vec![
+                StreamMarker::Gap {
+                    start_byte: 0,
+                    end_byte: 4,
+                    kind: GapKind::Garbage
+                },
+                StreamMarker::StreamStart {
+                    byte_offset: 4,
+                    level: 5
+                },
+                StreamMarker::Block {
+                    bit_offset: 64,
+                    stored_crc: Some(0xDEAD_BEEF),
+                    confidence: Confidence::High
+                },
+                StreamMarker::StreamEnd {
+                    bit_offset: eos,
+                    combined_crc: Some(0x1234_5678)
+                },
+                StreamMarker::Gap {
+                    start_byte: 38,
+                    end_byte: 41,
+                    kind: GapKind::Padding
+                },
+                StreamMarker::StreamStart {
+                    byte_offset: 41,
+                    level: 9
+                },
+                StreamMarker::StreamEnd {
+                    bit_offset: 45 * 8,
+                    combined_crc: Some(0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_confidence() {
+        let mut data = vec![0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+        data.extend_from_slice(&HEADER);
+        assert_eq!(block_confidence(&data, 0, 1), Some(Confidence::High));
+
+        // Randomised blocks are valid, but not written by current bzip2
+        let mut randomised = data.clone();
+        randomised[10] |= 0x80;
+        assert_eq!(block_confidence(&randomised, 0, 1), Some(Confidence::Low));
+
+        // origPtr past the end of a level-1 block
+        let mut orig_ptr = data.clone();
+        orig_ptr[11] = 0xFF;
+        assert_eq!(block_confidence(&orig_ptr, 0, 1), None);
+        assert_eq!(block_confidence(&orig_ptr, 0, 9), Some(Confidence::High));
+
+        // No symbols in use
+        let mut unused = data.clone();
+        unused[13] = 0;
+        assert_eq!(block_confidence(&unused, 0, 9), None);
+
+        // Seven Huffman tables
+        let mut tables = data.clone();
+        tables[17] = 0xF0;
+        assert_eq!(block_confidence(&tables, 0, 9), None);
+
+        // No selectors
+        let mut selectors = data.clone();
+        selectors[19] = 0;
+        assert_eq!(block_confidence(&selectors, 0, 9), None);
+
+        // A candidate cut off before its header is kept
+        assert_eq!(
+            block_confidence(&data[..12], 0, 9),
+            Some(Confidence::Unchecked)
+        );
+        assert!(scan_to_vec(&tables).is_empty());
+    }
+
+    #[test]
+    fn test_candidates_checked_against_stream_level() {
+        let magic = [0x31, 0x41, 0x59, 0x26, 0x53, 0x59];
+        let mut block = magic.to_vec();
+        block.extend_from_slice(&HEADER);
+        // origPtr past the end of a level-1 block, but not of a level-9 one
+        let mut big_orig_ptr = block.clone();
+        big_orig_ptr[11] = 0xFF;
+
+        for level in [b'1', b'9'] {
+            let mut data = vec![b'B', b'Z', b'h', level];
+            data.extend_from_slice(&block);
+            let second = data.len() as u64 * 8;
+            data.extend_from_slice(&big_orig_ptr);
+            let eos = data.len() as u64 * 8;
+            data.extend_from_slice(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90]);
+
+            let mut expected = vec![(32, MarkerType::Block)];
+            if level == b'9' {
+                expected.push((second, MarkerType::Block));
+            }
+            expected.push((eos, MarkerType::Eos));
+
+            // With small chunks, the second candidate is in a chunk that does not
+            // see the stream header
+            for chunk_size in [16, DEFAULT_CHUNK_SIZE] {
+                let scanner = Scanner::with_chunk_size(chunk_size);
+                let (tx, rx) = crossbeam_channel::unbounded();
+                scanner.scan_stream(&data, 0, DEFAULT_LEVEL, tx);
+                let markers: Vec<_> = rx.iter().flat_map(|(_, markers)| markers).collect();
+                assert_eq!(
+                    markers, expected,
+                    "level {}, chunk size {}",
+                    level, chunk_size
+                );
+            }
+
+            let stream_level = level - b'0';
+            let next = Scanner::new().next_marker(&data, 33, stream_level);
+            assert_eq!(next, expected.get(1).copied());
+        }
+    }
+
+    #[test]
+    fn test_next_marker() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&[0x31, 0x41, 0x59, 0x26, 0x53, 0x59]);
+        data.extend_from_slice(&HEADER);
+        let pos_eos = data.len() as u64 * 8;
+        data.extend_from_slice(&[0x17, 0x72, 0x45, 0x38, 0x50, 0x90]);
+
+        let scanner = Scanner::new();
+        assert_eq!(
+            scanner.next_marker(&data, 0, DEFAULT_LEVEL),
+            Some((0, MarkerType::Block))
+        );
+        assert_eq!(
+            scanner.next_marker(&data, 1, DEFAULT_LEVEL),
+            Some((pos_eos, MarkerType::Eos))
+        );
+        assert_eq!(scanner.next_marker(&data, pos_eos + 1, DEFAULT_LEVEL), None);
+    }
+
     #[test]
     fn test_extract_bits_aligned() {
         let data = vec![0xAA, 0xBB, 0xCC];
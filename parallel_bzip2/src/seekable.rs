@@ -0,0 +1,591 @@
+//! Seek tables and random access for zstd files in the seekable format.
+//!
+//! A zstd file made of independent frames can be decoded from any frame, but
+//! nothing in the frames says where each one starts or how much it
+//! decompresses to. The [zstd seekable format] records this in a seek table,
+//! stored at the end of the file in a skippable frame that regular zstd
+//! decoders ignore. [`SeekTable`] builds, writes and parses such tables, and
+//! [`SeekableZstd`] uses them to read a file at arbitrary decompressed offsets.
+//!
+//! Only available with the `seekable` feature.
+//!
+//! # Seek table format
+//!
+//! All integers are little-endian:
+//!
+//! | Offset | Size | Field |
+//! |-------:|-----:|-------|
+//! | 0      | 4    | Skippable frame magic `0x184D2A5E` |
+//! | 4      | 4    | Size of the rest of the frame |
+//! | 8      | 8 or 12 × n | Entries |
+//! | end-9  | 4    | Number of frames |
+//! | end-5  | 1    | Descriptor: bit 7 checksums present, bits 2-6 reserved, zero |
+//! | end-4  | 4    | Seekable magic `0x8F92EAB1` |
+//!
+//! Each entry describes one frame, in file order:
+//!
+//! | Offset | Size | Field |
+//! |-------:|-----:|-------|
+//! | 0      | 4    | [`SeekEntry::compressed_size`] |
+//! | 4      | 4    | [`SeekEntry::decompressed_size`] |
+//! | 8      | 4    | [`SeekEntry::checksum`], if the descriptor says so |
+//!
+//! [zstd seekable format]: https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use zstd::zstd_safe;
+
+use crate::index::Fields;
+use crate::indexed::{CacheStats, CachedReader, Unit};
+use crate::{Error, Result};
+
+/// Magic number of a zstd frame.
+const ZSTD_MAGIC: u32 = 0xFD2F_B528;
+
+/// Magic number of the skippable frame holding the seek table.
+pub const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+
+/// Magic number at the very end of a seekable file.
+pub const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+
+const SKIPPABLE_HEADER_LEN: usize = 8;
+const FOOTER_LEN: usize = 9;
+
+const FLAG_CHECKSUMS: u8 = 1 << 7;
+const RESERVED_FLAGS: u8 = 0x7C;
+
+/// Content checksum flag in the descriptor of a zstd frame header.
+const FRAME_FLAG_CHECKSUM: u8 = 1 << 2;
+
+/// Location and size of one frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekEntry {
+    /// Index of the frame in the file
+    pub index: usize,
+    /// Offset of the frame's first byte within the file
+    pub compressed_offset: u64,
+    /// Length of the frame in bytes
+    pub compressed_size: u32,
+    /// Offset of the frame's first byte within the decompressed output
+    pub decompressed_offset: u64,
+    /// Number of bytes the frame decompresses to
+    pub decompressed_size: u32,
+    /// Low 32 bits of the XXH64 of the decompressed frame, if the table has checksums
+    pub checksum: Option<u32>,
+}
+
+impl SeekEntry {
+    /// Returns the offset just past the frame's last byte in the file.
+    pub fn compressed_end(&self) -> u64 {
+        self.compressed_offset + u64::from(self.compressed_size)
+    }
+
+    /// Returns the offset just past the frame's last byte in the decompressed output.
+    pub fn decompressed_end(&self) -> u64 {
+        self.decompressed_offset + u64::from(self.decompressed_size)
+    }
+}
+
+/// Seek table of a zstd file, listing its frames in order.
+///
+/// # Examples
+///
+/// Writing one frame per chunk, then the table:
+///
+/// ```no_run
+/// use parallel_bzip2::SeekTable;
+/// use std::io::Write;
+///
+/// let mut out = std::fs::File::create("file.zst").unwrap();
+/// let mut table = SeekTable::new(true);
+/// for chunk in [&b"hello "[..], b"world"] {
+///     let mut compressor = zstd::bulk::Compressor::new(3).unwrap();
+///     compressor.include_checksum(true).unwrap();
+///     let frame = compressor.compress(chunk).unwrap();
+///     table.push_frame(&frame).unwrap();
+///     out.write_all(&frame).unwrap();
+/// }
+/// table.write_to(&mut out).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeekTable {
+    entries: Vec<SeekEntry>,
+    checksums: bool,
+}
+
+impl SeekTable {
+    /// Creates an empty table, with a checksum for every frame if `checksums` is set.
+    pub fn new(checksums: bool) -> Self {
+        Self {
+            entries: Vec::new(),
+            checksums,
+        }
+    }
+
+    /// Returns `true` if the table records a checksum for every frame.
+    pub fn has_checksums(&self) -> bool {
+        self.checksums
+    }
+
+    /// Returns the frames in file order.
+    pub fn entries(&self) -> &[SeekEntry] {
+        &self.entries
+    }
+
+    /// Returns the number of frames.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the table lists no frames.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total length of the frames, without the table itself.
+    pub fn compressed_len(&self) -> u64 {
+        self.entries.last().map_or(0, SeekEntry::compressed_end)
+    }
+
+    /// Returns the total decompressed length of the frames.
+    pub fn decompressed_len(&self) -> u64 {
+        self.entries.last().map_or(0, SeekEntry::decompressed_end)
+    }
+
+    /// Returns the length of the table once written, skippable frame included.
+    pub fn encoded_len(&self) -> usize {
+        SKIPPABLE_HEADER_LEN + self.entry_len() * self.entries.len() + FOOTER_LEN
+    }
+
+    /// Finds the frame holding the byte at `offset` of the decompressed output.
+    pub fn find(&self, offset: u64) -> Option<&SeekEntry> {
+        let pos = self
+            .entries
+            .partition_point(|entry| entry.decompressed_end() <= offset);
+        self.entries
+            .get(pos)
+            .filter(|entry| entry.decompressed_offset <= offset)
+    }
+
+    /// Appends a frame of the given sizes.
+    ///
+    /// `checksum` is the low 32 bits of the XXH64 of the decompressed frame. It is
+    /// required if the table has checksums and ignored otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSeekTable`] if a size does not fit in 32 bits, as
+    /// the format requires, or if the checksum is missing.
+    pub fn push(
+        &mut self,
+        compressed_size: usize,
+        decompressed_size: usize,
+        checksum: Option<u32>,
+    ) -> Result<()> {
+        let index = self.entries.len();
+        let (Ok(compressed_size), Ok(decompressed_size)) = (
+            u32::try_from(compressed_size),
+            u32::try_from(decompressed_size),
+        ) else {
+            return Err(invalid(format!("frame {} is larger than 4GiB", index)));
+        };
+        let checksum = match (self.checksums, checksum) {
+            (true, None) => return Err(invalid(format!("frame {} has no checksum", index))),
+            (true, checksum) => checksum,
+            (false, _) => None,
+        };
+        self.entries.push(SeekEntry {
+            index,
+            compressed_offset: self.compressed_len(),
+            compressed_size,
+            decompressed_offset: self.decompressed_len(),
+            decompressed_size,
+            checksum,
+        });
+        Ok(())
+    }
+
+    /// Appends a complete zstd frame, reading its sizes from its header.
+    ///
+    /// The frame must record its content size, as frames from
+    /// `zstd::bulk::Compressor` do, and if the table has checksums, its content
+    /// checksum too, which zstd computes the same way as seek table checksums.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ZstdFrame`] if `frame` is not a single zstd frame with the
+    /// needed fields, or the errors of [`push`](Self::push).
+    pub fn push_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let index = self.entries.len();
+        let bad_frame = |reason: &str| Error::ZstdFrame {
+            frame_index: index,
+            reason: reason.to_string(),
+        };
+        if frame.len() < 5 || frame[..4] != ZSTD_MAGIC.to_le_bytes() {
+            return Err(bad_frame("not a zstd frame"));
+        }
+        if zstd_safe::find_frame_compressed_size(frame) != Ok(frame.len()) {
+            return Err(bad_frame("not exactly one zstd frame"));
+        }
+        let decompressed_size = match zstd_safe::get_frame_content_size(frame) {
+            Ok(Some(size)) => usize::try_from(size).unwrap_or(usize::MAX),
+            _ => return Err(bad_frame("frame header has no content size")),
+        };
+        let checksum = if frame[4] & FRAME_FLAG_CHECKSUM != 0 {
+            let tail = &frame[frame.len() - 4..];
+            Some(u32::from_le_bytes(tail.try_into().expect("4 bytes")))
+        } else if self.checksums {
+            return Err(bad_frame("frame has no content checksum"));
+        } else {
+            None
+        };
+        self.push(frame.len(), decompressed_size, checksum)
+    }
+
+    /// Writes the table as a skippable frame, in the format described in the
+    /// [module documentation](self).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if writing fails.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut out = Vec::with_capacity(self.encoded_len());
+        let frame_size = (self.encoded_len() - SKIPPABLE_HEADER_LEN) as u32;
+        out.extend_from_slice(&SKIPPABLE_MAGIC.to_le_bytes());
+        out.extend_from_slice(&frame_size.to_le_bytes());
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.compressed_size.to_le_bytes());
+            out.extend_from_slice(&entry.decompressed_size.to_le_bytes());
+            if self.checksums {
+                out.extend_from_slice(&entry.checksum.unwrap_or(0).to_le_bytes());
+            }
+        }
+        out.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        out.push(if self.checksums { FLAG_CHECKSUMS } else { 0 });
+        out.extend_from_slice(&SEEKABLE_MAGIC.to_le_bytes());
+        writer.write_all(&out)?;
+        Ok(())
+    }
+
+    /// Reads the seek table at the end of `data`, the contents of a seekable file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSeekTable`] if `data` does not end with a valid
+    /// seek table, or if the frames it lists do not fit in front of it.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < SKIPPABLE_HEADER_LEN + FOOTER_LEN {
+            return Err(invalid("file is too short for a seek table"));
+        }
+        let mut footer = Fields(&data[data.len() - FOOTER_LEN..]);
+        let count = footer.u32() as usize;
+        let descriptor = footer.u8();
+        if footer.u32() != SEEKABLE_MAGIC {
+            return Err(invalid("file does not end with a seek table"));
+        }
+        if descriptor & RESERVED_FLAGS != 0 {
+            return Err(invalid("reserved descriptor bits are set"));
+        }
+
+        let mut table = SeekTable::new(descriptor & FLAG_CHECKSUMS != 0);
+        let table_len = count
+            .checked_mul(table.entry_len())
+            .and_then(|len| len.checked_add(SKIPPABLE_HEADER_LEN + FOOTER_LEN))
+            .filter(|&len| len <= data.len())
+            .ok_or_else(|| invalid("seek table is longer than the file"))?;
+        let mut fields = Fields(&data[data.len() - table_len..data.len() - FOOTER_LEN]);
+        let magic = fields.u32();
+        let frame_size = fields.u32() as usize;
+        if magic != SKIPPABLE_MAGIC || frame_size != table_len - SKIPPABLE_HEADER_LEN {
+            return Err(invalid("seek table frame header does not match its footer"));
+        }
+
+        table.entries.reserve(count);
+        for _ in 0..count {
+            let compressed_size = fields.u32() as usize;
+            let decompressed_size = fields.u32() as usize;
+            let checksum = table.checksums.then(|| fields.u32());
+            table.push(compressed_size, decompressed_size, checksum)?;
+        }
+        if table.compressed_len() > (data.len() - table_len) as u64 {
+            return Err(invalid("frames extend past the seek table"));
+        }
+        Ok(table)
+    }
+
+    fn entry_len(&self) -> usize {
+        if self.checksums {
+            12
+        } else {
+            8
+        }
+    }
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::InvalidSeekTable {
+        reason: reason.into(),
+    }
+}
+
+/// A zstd file in the seekable format that can be read at arbitrary
+/// decompressed offsets.
+///
+/// Works like [`crate::IndexedBz2`], with the seek table in place of a block
+/// index: all methods take `&self`, frames are decoded on the calling thread,
+/// and recently used frames are kept in an LRU cache. Decoded frames are checked
+/// against their size and, if the table has them, their checksum.
+///
+/// # Examples
+///
+/// ```no_run
+/// use parallel_bzip2::SeekableZstd;
+///
+/// let file = SeekableZstd::open("file.zst").unwrap();
+/// let mut buf = vec![0; 4096];
+/// let read = file.read_at(1 << 20, &mut buf).unwrap();
+/// println!("read {} bytes", read);
+/// ```
+pub struct SeekableZstd {
+    reader: CachedReader,
+    table: SeekTable,
+}
+
+impl SeekableZstd {
+    /// Opens a seekable zstd file with memory-mapped I/O.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Io`] if the file cannot be opened, or the errors of
+    /// [`new`](Self::new).
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let mmap = unsafe { memmap2::MmapOptions::new().map(&file)? };
+        Self::new(Arc::new(mmap))
+    }
+
+    /// Creates a reader for in-memory data in the seekable format.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidSeekTable`] if `data` does not end with a seek
+    /// table, or if its frames do not take up everything in front of it.
+    pub fn new<T>(data: Arc<T>) -> Result<Self>
+    where
+        T: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let bytes = data.as_ref().as_ref();
+        let table = SeekTable::parse(bytes)?;
+        if table.compressed_len() + table.encoded_len() as u64 != bytes.len() as u64 {
+            return Err(invalid("frames do not cover the file up to the seek table"));
+        }
+        Ok(Self {
+            reader: CachedReader::new(data),
+            table,
+        })
+    }
+
+    /// Sets how many decoded frames are cached,
+    /// [`DEFAULT_CACHE_BLOCKS`](crate::indexed::DEFAULT_CACHE_BLOCKS) by default.
+    ///
+    /// Zero disables the cache.
+    pub fn cache_capacity(self, frames: usize) -> Self {
+        self.reader.set_capacity(frames);
+        self
+    }
+
+    /// Returns the decompressed length of the file.
+    pub fn len(&self) -> u64 {
+        self.table.decompressed_len()
+    }
+
+    /// Returns `true` if the file decompresses to nothing.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the seek table of the file.
+    pub fn table(&self) -> &SeekTable {
+        &self.table
+    }
+
+    /// Returns how many frames were served from the cache and how many were decoded.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.reader.stats()
+    }
+
+    /// Reads decompressed data starting at `offset` into `buf`.
+    ///
+    /// Returns the number of bytes read, which is less than `buf.len()` only when
+    /// the end of the data is reached, and zero for offsets at or past the end.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ZstdFrame`] if an overlapping frame cannot be decoded or
+    /// does not match its seek table entry.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize> {
+        self.reader.read_at(
+            offset,
+            buf,
+            self.len(),
+            |pos| *self.table.find(pos).expect("seek table covers the data"),
+            decode_frame,
+        )
+    }
+}
+
+/// Decodes a frame and checks it against its checksum, if the table has one.
+fn decode_frame(data: &[u8], entry: &SeekEntry) -> Result<Vec<u8>> {
+    let compressed = &data[entry.compressed_offset as usize..entry.compressed_end() as usize];
+    let out = zstd::bulk::decompress(compressed, entry.decompressed_size as usize)
+        .map_err(|e| bad_frame(entry, e.to_string()))?;
+    if let Some(checksum) = entry.checksum {
+        let computed = xxh64(&out) as u32;
+        if computed != checksum {
+            return Err(bad_frame(
+                entry,
+                format!(
+                    "checksum mismatch: stored {:#010x}, computed {:#010x}",
+                    checksum, computed
+                ),
+            ));
+        }
+    }
+    Ok(out)
+}
+
+fn bad_frame(entry: &SeekEntry, reason: String) -> Error {
+    Error::ZstdFrame {
+        frame_index: entry.index,
+        reason,
+    }
+}
+
+impl Unit for SeekEntry {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn decompressed_offset(&self) -> u64 {
+        self.decompressed_offset
+    }
+
+    fn decompressed_len(&self) -> u64 {
+        u64::from(self.decompressed_size)
+    }
+
+    fn length_mismatch(&self, len: usize) -> Error {
+        bad_frame(
+            self,
+            format!(
+                "decompressed to {} bytes, the seek table says {}",
+                len, self.decompressed_size
+            ),
+        )
+    }
+}
+
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+/// Computes the XXH64 hash of `data` with seed 0, as used for zstd checksums.
+///
+/// Seek tables written by other tools may hold checksums for frames that have
+/// none of their own, so these are checked here rather than by zstd.
+fn xxh64(data: &[u8]) -> u64 {
+    fn round(acc: u64, lane: u64) -> u64 {
+        acc.wrapping_add(lane.wrapping_mul(PRIME64_2))
+            .rotate_left(31)
+            .wrapping_mul(PRIME64_1)
+    }
+    fn merge(acc: u64, lane: u64) -> u64 {
+        (acc ^ round(0, lane))
+            .wrapping_mul(PRIME64_1)
+            .wrapping_add(PRIME64_4)
+    }
+    fn u64_at(bytes: &[u8]) -> u64 {
+        u64::from_le_bytes(bytes[..8].try_into().expect("8 bytes"))
+    }
+
+    let mut rest = data;
+    let mut hash = if data.len() >= 32 {
+        let mut acc = [
+            PRIME64_1.wrapping_add(PRIME64_2),
+            PRIME64_2,
+            0,
+            0u64.wrapping_sub(PRIME64_1),
+        ];
+        while rest.len() >= 32 {
+            for (i, acc) in acc.iter_mut().enumerate() {
+                *acc = round(*acc, u64_at(&rest[i * 8..]));
+            }
+            rest = &rest[32..];
+        }
+        let hash = acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18));
+        acc.iter().fold(hash, |hash, &acc| merge(hash, acc))
+    } else {
+        PRIME64_5
+    };
+    hash = hash.wrapping_add(data.len() as u64);
+
+    while rest.len() >= 8 {
+        hash = (hash ^ round(0, u64_at(rest)))
+            .rotate_left(27)
+            .wrapping_mul(PRIME64_1)
+            .wrapping_add(PRIME64_4);
+        rest = &rest[8..];
+    }
+    if rest.len() >= 4 {
+        let lane = u32::from_le_bytes(rest[..4].try_into().expect("4 bytes"));
+        hash = (hash ^ u64::from(lane).wrapping_mul(PRIME64_1))
+            .rotate_left(23)
+            .wrapping_mul(PRIME64_2)
+            .wrapping_add(PRIME64_3);
+        rest = &rest[4..];
+    }
+    for &byte in rest {
+        hash = (hash ^ u64::from(byte).wrapping_mul(PRIME64_5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME64_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME64_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME64_3);
+    hash ^ (hash >> 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xxh64_reference_values() {
+        assert_eq!(xxh64(b""), 0xEF46_DB37_51D8_E999);
+        assert_eq!(xxh64(b"abc"), 0x44BC_2CF5_AD77_0999);
+    }
+
+    #[test]
+    fn test_xxh64_matches_zstd_checksum() {
+        // Long enough for the stripe loop and every tail case
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 + i / 13) as u8).collect();
+        for len in [0, 3, 4, 8, 31, 32, 45, 1000] {
+            let mut compressor = zstd::bulk::Compressor::new(1).unwrap();
+            compressor.include_checksum(true).unwrap();
+            let frame = compressor.compress(&data[..len]).unwrap();
+            let stored = u32::from_le_bytes(frame[frame.len() - 4..].try_into().unwrap());
+            assert_eq!(xxh64(&data[..len]) as u32, stored, "length {}", len);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Sliding-window block segmentation for input that cannot be mapped.
+//!
+//! [`crate::Bz2Decoder::from_reader`] reads its input as it arrives, so the
+//! scanner cannot see the whole file up front. The [`Segmenter`] reads the input
+//! in chunks and scans each one for markers. Once the marker that ends a candidate
+//! range is seen, it copies that range into a shared segment for the decoder and
+//! drops the input before it. Only the blocks being decoded and the one being
+//! scanned are held in memory, however long the input is.
+
+use std::collections::VecDeque;
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::decoder::Job;
+use crate::scanner::{self, MarkerType, MAGIC_EOS};
+use crate::{BlockRange, Error, Result, Scanner, DEFAULT_LEVEL, MAX_MERGED_CANDIDATES};
+
+/// Longest candidate range accepted before giving up on finding its end.
+///
+/// Twice the largest block bzip2 produces, so that a stream without an end
+/// marker fails instead of being buffered in full.
+const MAX_CANDIDATE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// Bytes kept after the end marker of a range.
+///
+/// Covers the marker, the combined CRC after an end-of-stream marker, and the
+/// header of the next stream, so that trailing garbage is detected as it would be
+/// in the full data.
+const END_MARKER_SLACK: u64 = 16;
+
+/// Splits a reader into candidate ranges, each with a segment of data holding it.
+///
+/// Yields the same ranges as [`crate::scan_blocks`] would on the whole input, in
+/// order. A range that ends at a block magic is only handed out once the ranges
+/// after it are complete too, so that a block split by false-positive magics can
+/// still be merged with as many following candidates as in the mapped case.
+pub(crate) struct Segmenter<R> {
+    reader: R,
+    scanner: Scanner,
+    /// Input that is still needed; `buf[0]` is byte `buf_start` of the input
+    buf: Vec<u8>,
+    buf_start: u64,
+    /// Bit offset where scanning resumes
+    scan_from: u64,
+    /// Start of the candidate range being scanned, if inside a stream
+    current_start: Option<u64>,
+    /// Level of the stream being scanned
+    level: u8,
+    /// Number of candidate ranges found so far
+    found: usize,
+    /// Complete ranges that have not been handed out yet
+    ranges: VecDeque<BlockRange>,
+    /// Ranges copied into a segment, ready to be handed out
+    ready: VecDeque<Job>,
+    /// Set once the input has ended or failed
+    eof: bool,
+    /// Error to report once the ranges found before it have been handed out
+    error: Option<Error>,
+}
+
+impl<R: Read> Segmenter<R> {
+    /// Creates a segmenter reading `reader` in chunks of the scanner's chunk size.
+    pub(crate) fn new(reader: R, scanner: Scanner) -> Self {
+        Self {
+            reader,
+            scanner,
+            buf: Vec::new(),
+            buf_start: 0,
+            scan_from: 0,
+            current_start: None,
+            level: DEFAULT_LEVEL,
+            found: 0,
+            ranges: VecDeque::new(),
+            ready: VecDeque::new(),
+            eof: false,
+            error: None,
+        }
+    }
+
+    /// Reads the next chunk, scans it and hands out the ranges it completes.
+    fn fill(&mut self) {
+        let chunk_size = self.scanner.chunk_size() as u64;
+        match (&mut self.reader)
+            .take(chunk_size)
+            .read_to_end(&mut self.buf)
+        {
+            Ok(read) => {
+                // A short read means the reader is exhausted
+                self.eof = (read as u64) < chunk_size;
+                self.scan();
+                if let Err(err) = self.check_candidate_size() {
+                    self.error = Some(err);
+                    self.eof = true;
+                }
+            }
+            Err(err) => {
+                self.error = Some(err.into());
+                self.eof = true;
+            }
+        }
+        self.cut_segment();
+    }
+
+    /// Turns the markers in the unscanned part of the buffer into ranges.
+    fn scan(&mut self) {
+        let base = self.buf_start * 8;
+        let len = self.buf.len() as u64;
+        // A marker this close to the end may continue in data not read yet, and
+        // its block header cannot be checked yet
+        let complete_below = if self.eof {
+            u64::MAX
+        } else {
+            len.saturating_sub(scanner::BLOCK_HEADER_BYTES) * 8
+        };
+
+        loop {
+            let from = self.scan_from - base;
+            // Outside a stream, the next block is a new stream's or garbage
+            let level = match self.current_start {
+                Some(_) => self.level,
+                None => DEFAULT_LEVEL,
+            };
+            let Some((pos, mtype)) = self.scanner.next_marker(&self.buf, from, level) else {
+                break;
+            };
+            if pos >= complete_below {
+                break;
+            }
+            self.scan_from = base + pos + 48;
+            let marker = base + pos;
+
+            match mtype {
+                MarkerType::Block => {
+                    if let Some(start) = self.current_start {
+                        self.push_range(start, marker);
+                    } else {
+                        // First block of a stream: pick up the level from its header
+                        let level = scanner::stream_level(&self.buf, pos);
+                        self.level = level.unwrap_or(DEFAULT_LEVEL);
+                    }
+                    self.current_start = Some(marker);
+                }
+                MarkerType::Eos => {
+                    if let Some(start) = self.current_start.take() {
+                        self.push_range(start, marker);
+                    }
+                }
+            }
+        }
+
+        if !self.eof {
+            self.scan_from = self.scan_from.max(base + complete_below);
+        } else if self.error.is_none() {
+            // Block without EOS marker (truncated input)
+            if let Some(start) = self.current_start.take() {
+                self.push_range(start, base + len * 8);
+            }
+        }
+    }
+
+    fn push_range(&mut self, start_bit: u64, end_bit: u64) {
+        self.ranges
+            .push_back(BlockRange::new(start_bit, end_bit, self.level));
+        self.found += 1;
+    }
+
+    /// Fails if the range being scanned has grown past any valid block.
+    fn check_candidate_size(&self) -> Result<()> {
+        let Some(start_bit) = self.current_start else {
+            return Ok(());
+        };
+        let end_bit = (self.buf_start + self.buf.len() as u64) * 8;
+        if end_bit - start_bit <= MAX_CANDIDATE_BYTES * 8 {
+            return Ok(());
+        }
+        Err(Error::BlockDecode {
+            block_index: Some(self.found),
+            start_bit,
+            end_bit,
+            source: format!("no block end marker within {} bytes", MAX_CANDIDATE_BYTES).into(),
+        })
+    }
+
+    /// Copies the ranges that can be handed out into one segment.
+    fn cut_segment(&mut self) {
+        let buf_end = self.buf_start + self.buf.len() as u64;
+
+        // Find how many ranges are complete, and the data they need
+        let mut count = 0;
+        let mut segment_end = 0;
+        for i in 0..self.ranges.len() {
+            let needed_end = if self.eof {
+                buf_end
+            } else {
+                match self.merge_end(i) {
+                    Some(end_bit) => end_bit / 8 + END_MARKER_SLACK,
+                    None => break,
+                }
+            };
+            if needed_end > buf_end {
+                break;
+            }
+            count += 1;
+            segment_end = segment_end.max(needed_end);
+        }
+
+        if count > 0 {
+            let segment_start = self.ranges[0].start_bit / 8;
+            let offset_bits = segment_start * 8;
+            let data: Arc<Vec<u8>> = Arc::new(
+                self.buf[(segment_start - self.buf_start) as usize
+                    ..(segment_end - self.buf_start) as usize]
+                    .to_vec(),
+            );
+            for range in self.ranges.drain(..count) {
+                self.ready.push_back(Job {
+                    data: data.clone(),
+                    offset_bits,
+                    block: BlockRange::new(
+                        range.start_bit - offset_bits,
+                        range.end_bit - offset_bits,
+                        range.level,
+                    ),
+                    absorbed: 0,
+                });
+            }
+        }
+
+        self.drop_consumed();
+    }
+
+    /// End of the last range that range `i` may have to be merged with.
+    ///
+    /// Merging stops at an end-of-stream marker, after [`MAX_MERGED_CANDIDATES`]
+    /// more ranges, or once the merged range is longer than any valid block.
+    /// Returns `None` if the ranges up to there have not all been found yet.
+    fn merge_end(&self, i: usize) -> Option<u64> {
+        let base = self.buf_start * 8;
+        let start_bit = self.ranges[i].start_bit;
+        let mut merged = 0;
+        loop {
+            let end_bit = self.ranges.get(i + merged)?.end_bit;
+            if merged == MAX_MERGED_CANDIDATES
+                || end_bit - start_bit > MAX_CANDIDATE_BYTES * 8
+                || scanner::verify_magic(&self.buf, end_bit - base, MAGIC_EOS)
+            {
+                return Some(end_bit);
+            }
+            merged += 1;
+        }
+    }
+
+    /// Drops input that no future range can refer to.
+    fn drop_consumed(&mut self) {
+        // The next stream's header sits just before its first block magic
+        let mut keep = (self.scan_from / 8).saturating_sub(4);
+        if let Some(range) = self.ranges.front() {
+            keep = keep.min(range.start_bit / 8);
+        }
+        if let Some(start) = self.current_start {
+            keep = keep.min(start / 8);
+        }
+        let consumed = keep.saturating_sub(self.buf_start) as usize;
+
+        // Only shift the buffer once that frees a good part of it
+        if consumed > 0 && (self.eof || consumed >= self.buf.len() / 2) {
+            self.buf.drain(..consumed.min(self.buf.len()));
+            self.buf_start += consumed as u64;
+        }
+    }
+}
+
+impl<R: Read> Iterator for Segmenter<R> {
+    type Item = Result<Job>;
+
+    fn next(&mut self) -> Option<Result<Job>> {
+        loop {
+            if let Some(job) = self.ready.pop_front() {
+                return Some(Ok(job));
+            }
+            if self.eof {
+                return self.error.take().map(Err);
+            }
+            self.fill();
+        }
+    }
+}
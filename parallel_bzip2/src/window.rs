@@ -0,0 +1,219 @@
+//! Ordered scheduling window for parallel block processing.
+//!
+//! Blocks are decoded in parallel but emitted in file order, so every block that
+//! finishes ahead of a slow one has to be buffered. A [`ReorderBuffer`] holds them
+//! until their turn comes, and a [`ReorderWindow`] bounds that buffer: work on
+//! block `i` only starts once `i` is within a fixed distance of the next block to
+//! be emitted, so memory use depends on the window size rather than on how far the
+//! fastest workers could otherwise run ahead.
+
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::CancellationToken;
+
+/// Admits block indices that are within a fixed distance of the next one to emit.
+///
+/// The producer calls [`acquire`](Self::acquire) before dispatching each block and
+/// the consumer calls [`advance`](Self::advance) as it emits blocks in order.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_bzip2::{CancellationToken, ReorderWindow};
+///
+/// let window = ReorderWindow::new(2);
+/// let cancel = CancellationToken::new();
+/// assert!(window.acquire(0, &cancel));
+/// assert!(window.acquire(1, &cancel));
+///
+/// // Block 2 may only start once block 0 has been emitted
+/// window.advance(1);
+/// assert!(window.acquire(2, &cancel));
+/// ```
+#[derive(Debug)]
+pub struct ReorderWindow {
+    size: usize,
+    next: Mutex<usize>,
+    advanced: Condvar,
+}
+
+impl ReorderWindow {
+    /// Creates a window admitting `size` blocks from the next one to emit (at least one).
+    pub fn new(size: usize) -> Self {
+        Self {
+            size: size.max(1),
+            next: Mutex::new(0),
+            advanced: Condvar::new(),
+        }
+    }
+
+    /// Returns the number of blocks admitted at once.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Waits until block `index` is within the window.
+    ///
+    /// The next block to emit is always admitted, so a consumer waiting for it can
+    /// never deadlock with the producer. Returns `false` if `cancel` fires while
+    /// waiting.
+    pub fn acquire(&self, index: usize, cancel: &CancellationToken) -> bool {
+        let mut next = self.next.lock().unwrap();
+        loop {
+            if cancel.is_cancelled() {
+                return false;
+            }
+            if index < *next + self.size {
+                return true;
+            }
+            // Wake up now and then to notice cancellation
+            next = self
+                .advanced
+                .wait_timeout(next, Duration::from_millis(50))
+                .unwrap()
+                .0;
+        }
+    }
+
+    /// Records that every block before `next` has been emitted.
+    pub fn advance(&self, next: usize) {
+        let mut current = self.next.lock().unwrap();
+        if next > *current {
+            *current = next;
+            self.advanced.notify_all();
+        }
+    }
+}
+
+/// Holds results that arrive out of order until they can be emitted in order.
+///
+/// Results are indexed from zero. Each one is released once every result before
+/// it has been taken, or skipped because it was merged into an earlier one.
+///
+/// # Examples
+///
+/// ```
+/// use parallel_bzip2::ReorderBuffer;
+///
+/// let mut buffer = ReorderBuffer::new();
+/// buffer.insert(1, "b");
+/// assert_eq!(buffer.pop(), None);
+///
+/// buffer.insert(0, "a");
+/// assert_eq!(buffer.pop(), Some("a"));
+/// assert_eq!(buffer.pop(), Some("b"));
+/// assert_eq!(buffer.next_index(), 2);
+/// ```
+#[derive(Debug)]
+pub struct ReorderBuffer<T> {
+    next: usize,
+    pending: HashMap<usize, T>,
+}
+
+impl<T> ReorderBuffer<T> {
+    /// Creates an empty buffer waiting for index 0.
+    pub fn new() -> Self {
+        Self {
+            next: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Returns the index of the next result to emit.
+    pub fn next_index(&self) -> usize {
+        self.next
+    }
+
+    /// Returns the number of results waiting for an earlier one.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns `true` if no results are waiting.
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Buffers the result with the given index.
+    ///
+    /// Results for indices that were already emitted or skipped are handed back.
+    pub fn insert(&mut self, index: usize, item: T) -> Option<T> {
+        if index < self.next {
+            return Some(item);
+        }
+        self.pending.insert(index, item);
+        None
+    }
+
+    /// Takes the next result in order, if it has arrived.
+    pub fn pop(&mut self) -> Option<T> {
+        let item = self.pending.remove(&self.next)?;
+        self.next += 1;
+        Some(item)
+    }
+
+    /// Skips the next `count` indices, returning the results already buffered for them.
+    ///
+    /// Results for these indices that arrive later are handed back by
+    /// [`insert`](Self::insert).
+    pub fn skip(&mut self, count: usize) -> Vec<T> {
+        let skipped = (self.next..self.next + count)
+            .filter_map(|index| self.pending.remove(&index))
+            .collect();
+        self.next += count;
+        skipped
+    }
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_acquire_waits_for_advance() {
+        let window = Arc::new(ReorderWindow::new(2));
+        let cancel = CancellationToken::new();
+
+        let waiter = {
+            let window = window.clone();
+            let cancel = cancel.clone();
+            std::thread::spawn(move || window.acquire(3, &cancel))
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!waiter.is_finished());
+
+        window.advance(2);
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn test_reorder_buffer_skip() {
+        let mut buffer = ReorderBuffer::new();
+        buffer.insert(2, 'c');
+        buffer.insert(0, 'a');
+        assert_eq!(buffer.pop(), Some('a'));
+
+        // Index 1 was merged into 0: skipping it releases index 2 and drops late arrivals
+        assert_eq!(buffer.skip(1), Vec::<char>::new());
+        assert_eq!(buffer.insert(1, 'b'), Some('b'));
+        assert_eq!(buffer.pop(), Some('c'));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_acquire_stops_on_cancel() {
+        let window = ReorderWindow::new(1);
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(!window.acquire(5, &cancel));
+    }
+}
@@ -0,0 +1,151 @@
+use parallel_bzip2::crc::block_crc;
+use parallel_bzip2::{
+    decompress_block, scan_blocks, scan_blocks_scoped, scan_blocks_shared, scan_blocks_shared_in,
+    BlockDecoder, BlockError, BlockRange, Bz2Decoder, DecodedBlock, Error,
+};
+use std::io::Cursor;
+use std::sync::Arc;
+
+mod common;
+
+const TEST_DIR: &str = "tests/fixtures";
+
+fn collect_blocks(decoder: Bz2Decoder) -> Vec<DecodedBlock> {
+    decoder.into_blocks().map(|block| block.unwrap()).collect()
+}
+
+#[test]
+fn test_blocks_carry_metadata() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let ranges: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+    let blocks = collect_blocks(Bz2Decoder::new(Arc::new(data)));
+    assert_eq!(blocks.len(), ranges.len());
+
+    let mut offset = 0;
+    for (i, (block, range)) in blocks.iter().zip(&ranges).enumerate() {
+        assert_eq!(block.index, i);
+        assert_eq!(block.stream_index, 0);
+        assert_eq!(
+            (block.start_bit, block.end_bit),
+            (range.start_bit, range.end_bit)
+        );
+        assert_eq!(block.stored_crc, block_crc(&block.data));
+        assert_eq!(block.decompressed_offset, offset);
+        offset += block.data.len() as u64;
+    }
+
+    let joined: Vec<u8> = blocks.into_iter().flat_map(|block| block.data).collect();
+    assert_eq!(joined, original);
+}
+
+#[test]
+fn test_blocks_from_reader_match_mapped_input() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    let mapped = collect_blocks(Bz2Decoder::new(Arc::new(data.clone())));
+    let streamed = collect_blocks(
+        Bz2Decoder::builder()
+            .scan_chunk_size(4096)
+            .build_reader(Cursor::new(data))
+            .unwrap(),
+    );
+    assert_eq!(streamed, mapped);
+}
+
+#[test]
+fn test_blocks_count_streams() {
+    let blocks = collect_blocks(Bz2Decoder::open(format!("{}/concat.bz2", TEST_DIR)).unwrap());
+    let streams: Vec<usize> = blocks.iter().map(|block| block.stream_index).collect();
+    assert!(streams.len() >= 2);
+    assert!(streams.windows(2).all(|pair| pair[1] == pair[0] + 1));
+}
+
+#[test]
+fn test_blocks_stop_after_error() {
+    let mut blocks = Bz2Decoder::open(format!("{}/crc2.bz2", TEST_DIR))
+        .unwrap()
+        .into_blocks();
+    assert!(blocks.next().unwrap().is_err());
+    assert!(blocks.next().is_none());
+}
+
+#[test]
+fn test_scan_variants_agree() {
+    let (_, generated) = common::multi_block_bz2(600_000, 1);
+    let mut inputs = vec![generated];
+    for name in ["concat", "trash", "gap", "empty"] {
+        inputs.push(std::fs::read(format!("{}/{}.bz2", TEST_DIR, name)).unwrap());
+    }
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap(),
+    );
+
+    for data in inputs {
+        let copied: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+        let scoped: Vec<BlockRange> =
+            std::thread::scope(|s| scan_blocks_scoped(s, &data).iter().collect());
+        let data = Arc::new(data);
+        let shared: Vec<BlockRange> = scan_blocks_shared(data.clone()).iter().collect();
+        let pooled: Vec<BlockRange> = scan_blocks_shared_in(pool.clone(), data).iter().collect();
+        assert_eq!(scoped, copied);
+        assert_eq!(shared, copied);
+        assert_eq!(pooled, copied);
+    }
+}
+
+#[test]
+fn test_scoped_scan_stops_when_dropped() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    // Returning from the scope joins the scanning threads, so this must not hang
+    let first = std::thread::scope(|s| scan_blocks_scoped(s, &data).recv().unwrap());
+    assert_eq!(first.start_bit, 32);
+}
+
+#[test]
+fn test_block_decoder_reads_unaligned_blocks_in_place() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let blocks: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+    assert!(blocks.iter().any(|block| block.start_bit % 8 != 0));
+
+    let mut decoder = BlockDecoder::new();
+    let mut out = Vec::new();
+    for block in &blocks {
+        let end = decoder
+            .decode(&data, block.start_bit, block.end_bit, block.level, &mut out)
+            .unwrap();
+        // Each block ends right where the next marker starts
+        assert_eq!(end, block.end_bit);
+    }
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_block_errors_are_structured() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    let block = scan_blocks(&data).recv().unwrap();
+
+    // A block cut short by a range that ends too early
+    let short = BlockRange::new(block.start_bit, block.end_bit - 100, block.level);
+    let mut out = Vec::new();
+    let err = BlockDecoder::new()
+        .decode(&data, short.start_bit, short.end_bit, short.level, &mut out)
+        .unwrap_err();
+    assert_eq!(err, BlockError::UnexpectedEnd);
+
+    let Err(Error::BlockDecode { source, .. }) = decompress_block(&data, short) else {
+        panic!("expected a decode error");
+    };
+    assert_eq!(
+        source.downcast_ref::<BlockError>(),
+        Some(&BlockError::UnexpectedEnd)
+    );
+
+    // Not at a block magic
+    let shifted = BlockRange::new(block.start_bit + 1, block.end_bit, block.level);
+    assert!(matches!(
+        decompress_block(&data, shifted),
+        Err(Error::BlockDecode { .. })
+    ));
+}
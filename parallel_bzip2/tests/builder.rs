@@ -0,0 +1,53 @@
+use parallel_bzip2::{Bz2Decoder, Bz2DecoderBuilder};
+use std::io::Read;
+use std::sync::Arc;
+
+mod common;
+
+fn decode_all(builder: Bz2DecoderBuilder, data: Vec<u8>) -> Vec<u8> {
+    let mut decoder = builder.build(Arc::new(data)).unwrap();
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_caller_thread_pool() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap(),
+    );
+    let out = decode_all(Bz2Decoder::builder().thread_pool(pool), data);
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_single_thread_with_tiny_budget() {
+    // One pool thread shared by scanning and decoding, and a budget smaller than
+    // a block: the decoder must still make progress one block at a time
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let builder = Bz2Decoder::builder()
+        .num_threads(1)
+        .max_in_flight_bytes(1)
+        .scan_chunk_size(4096);
+    assert_eq!(decode_all(builder, data), original);
+}
+
+#[test]
+fn test_small_scan_chunks() {
+    // Many chunk boundaries, some of which fall inside block magics
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let out = decode_all(Bz2Decoder::builder().scan_chunk_size(7), data);
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_minimal_reorder_window() {
+    // With a window of one block, decoding proceeds strictly in order
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let out = decode_all(Bz2Decoder::builder().reorder_window(1), data);
+    assert_eq!(out, original);
+}
@@ -0,0 +1,51 @@
+use parallel_bzip2::{Bz2Decoder, Error};
+use std::io::{self, Read};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod common;
+
+/// Waits until only the test holds the data, i.e. every background thread has exited.
+fn wait_for_release(data: &Arc<Vec<u8>>) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if Arc::strong_count(data) == 1 {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    false
+}
+
+#[test]
+fn test_drop_stops_background_threads() {
+    let data = Arc::new(common::multi_block_bz2(2_000_000, 1).1);
+    let mut decoder = Bz2Decoder::new(data.clone());
+
+    let mut head = [0u8; 1024];
+    decoder.read_exact(&mut head).unwrap();
+    drop(decoder);
+
+    assert!(wait_for_release(&data));
+}
+
+#[test]
+fn test_cancel_fails_later_reads() {
+    let data = Arc::new(common::multi_block_bz2(2_000_000, 1).1);
+    let mut decoder = Bz2Decoder::new(data.clone());
+
+    let mut head = [0u8; 1024];
+    decoder.read_exact(&mut head).unwrap();
+
+    // Cancel from another thread through a token
+    let token = decoder.cancellation_token();
+    std::thread::spawn(move || token.cancel()).join().unwrap();
+
+    let err = decoder.read(&mut head).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Other);
+    assert!(matches!(Error::from(err), Error::Cancelled));
+    assert!(decoder.read(&mut head).is_err());
+
+    drop(decoder);
+    assert!(wait_for_release(&data));
+}
@@ -0,0 +1,71 @@
+//! Fixtures shared by the integration tests.
+
+#![allow(dead_code)]
+
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use std::io::Write;
+
+/// Pseudo-random data, the same on every call, so that it barely compresses.
+pub fn pseudo_random(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_F491u32;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+/// Compresses `len` bytes of pseudo-random data at `level`, so that it spans
+/// several blocks when `len` exceeds `level * 100_000`.
+///
+/// Returns the original data and the bzip2 stream.
+pub fn multi_block_bz2(len: usize, level: u32) -> (Vec<u8>, Vec<u8>) {
+    let original = pseudo_random(len);
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::new(level));
+    encoder.write_all(&original).unwrap();
+    (original, encoder.finish().unwrap())
+}
+
+/// Compresses data into one level-9 block that the scanner splits into three
+/// candidate ranges, at two false-positive block magics.
+///
+/// A block header maps the byte values used in the block, one bit per value, so
+/// picking the values that occur writes arbitrary bits into the compressed data.
+/// The map holds a fake block magic at bit 0 and a second one at bit 71, inside the
+/// first one's CRC and origPtr. Both fake headers pass the scanner's checks.
+///
+/// Returns the original data and the bzip2 stream.
+pub fn split_block_bz2() -> (Vec<u8>, Vec<u8>) {
+    const MAGIC: u64 = 0x3141_5926_5359;
+    fn set(map: &mut [bool; 256], at: usize, len: usize, value: u64) {
+        for i in 0..len {
+            map[at + i] = (value >> (len - 1 - i)) & 1 == 1;
+        }
+    }
+
+    // Every 16-value group must stay in use, so that the map is written in full
+    let mut map = [true; 256];
+    // First fake: the second magic provides its origPtr and most of its group
+    // map, which two clear bits limit to seven groups; then tables and selectors
+    set(&mut map, 0, 48, MAGIC);
+    set(&mut map, 71, 48, MAGIC);
+    set(&mut map, 119, 2, 0);
+    set(&mut map, 233, 3, 2);
+    set(&mut map, 236, 15, 1);
+    // Second fake: origPtr below 2^16, a single group, the tables and the selectors
+    set(&mut map, 152, 8, 0);
+    set(&mut map, 176, 16, 0x8000);
+    set(&mut map, 208, 3, 2);
+    set(&mut map, 211, 15, 1);
+
+    let values: Vec<u8> = (0..=255).filter(|&value| map[value as usize]).collect();
+    let original: Vec<u8> = pseudo_random(200_000)
+        .into_iter()
+        .map(|byte| values[byte as usize % values.len()])
+        .collect();
+    let mut encoder = BzEncoder::new(Vec::new(), Compression::new(9));
+    encoder.write_all(&original).unwrap();
+    (original, encoder.finish().unwrap())
+}
@@ -0,0 +1,64 @@
+use parallel_bzip2::crc::{block_crc, stored_block_crc};
+use parallel_bzip2::{decompress_block, parallel_bzip2_cat, scan_blocks, CrcMismatch, Error};
+
+const TEST_DIR: &str = "tests/fixtures";
+
+fn fixture(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/{}", TEST_DIR, name)).expect("Failed to read fixture")
+}
+
+#[test]
+fn test_valid_file_passes_crc_checks() {
+    let data = parallel_bzip2_cat(format!("{}/concat.bz2", TEST_DIR)).unwrap();
+    assert_eq!(data.len(), 5);
+}
+
+#[test]
+fn test_block_crc_mismatch() {
+    // crc2.bz2 has a corrupted block CRC
+    let data = fixture("crc2.bz2");
+    let block = scan_blocks(&data).recv().unwrap();
+    assert!(decompress_block(&data, block).is_err());
+}
+
+#[test]
+fn test_truncated_block_is_rejected() {
+    // Cutting a block short makes the decompressor stop with UnexpectedEof,
+    // which is only caught by comparing against the stored block CRC. Since the
+    // block runs into the end of the data, it is reported as truncated.
+    let mut data = fixture("rand.bz2");
+    data.truncate(30);
+    let block = scan_blocks(&data).recv().unwrap();
+
+    let err = decompress_block(&data, block).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::Truncated {
+            block_index: None,
+            bit_offset: 32,
+        }
+    ));
+}
+
+#[test]
+fn test_stream_crc_mismatch() {
+    // crc1.bz2 has valid blocks but a corrupted combined CRC
+    let err = parallel_bzip2_cat(format!("{}/crc1.bz2", TEST_DIR)).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::CrcMismatch(CrcMismatch::Stream { block_index: 0, .. })
+    ));
+}
+
+#[test]
+fn test_stored_block_crc() {
+    let data = fixture("rand.bz2");
+    let block = scan_blocks(&data).recv().unwrap();
+    let decoded = decompress_block(&data, block).unwrap();
+    assert_eq!(
+        stored_block_crc(&data, block.start_bit),
+        Some(block_crc(&decoded))
+    );
+    // The CRC follows the 48-bit magic, so there is none in a cut-off header
+    assert_eq!(stored_block_crc(&data[..8], block.start_bit), None);
+}
@@ -0,0 +1,116 @@
+use bzip2::read::{BzDecoder, MultiBzDecoder};
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use parallel_bzip2::{Bz2Decoder, Bz2EncoderBuilder, Scanner, StreamMarker};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// Data spanning several level-1 blocks, with both noise and long runs.
+fn sample_data() -> Vec<u8> {
+    let mut state = 0x2545_F491u32;
+    let mut data = Vec::new();
+    for i in 0..350_000u32 {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        data.push((state >> 24) as u8);
+        if i % 1000 == 0 {
+            data.extend(std::iter::repeat_n(b'x', 300));
+        }
+    }
+    data
+}
+
+fn encode(data: &[u8], level: u8, multi_stream: bool) -> Vec<u8> {
+    let mut encoder = Bz2EncoderBuilder::new()
+        .level(level)
+        .multi_stream(multi_stream)
+        .num_threads(4)
+        .build(Vec::new())
+        .unwrap();
+    // Odd-sized writes, so that blocks are cut in the middle of a write
+    for piece in data.chunks(77_777) {
+        encoder.write_all(piece).unwrap();
+    }
+    encoder.finish().unwrap()
+}
+
+fn stream_count(data: &[u8]) -> usize {
+    Scanner::new()
+        .scan_layout(data)
+        .iter()
+        .filter(|marker| matches!(marker, StreamMarker::StreamStart { .. }))
+        .count()
+}
+
+#[test]
+fn test_single_stream_round_trip() {
+    let original = sample_data();
+    let compressed = encode(&original, 1, false);
+    assert!(compressed.starts_with(b"BZh1"));
+    assert_eq!(stream_count(&compressed), 1);
+
+    // A single-stream decoder must read all of it, combined CRC included
+    let mut out = Vec::new();
+    BzDecoder::new(&compressed[..])
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, original);
+
+    let mut out = Vec::new();
+    Bz2Decoder::new(Arc::new(compressed))
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_multi_stream_round_trip() {
+    let original = sample_data();
+    let compressed = encode(&original, 1, true);
+    let blocks = parallel_bzip2::scan_blocks(&compressed).iter().count();
+    assert!(blocks > 1);
+    assert_eq!(stream_count(&compressed), blocks);
+
+    let mut out = Vec::new();
+    MultiBzDecoder::new(&compressed[..])
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_matches_libbz2_within_one_block() {
+    for input in [&b""[..], b"hello world"] {
+        let mut reference = BzEncoder::new(Vec::new(), Compression::new(9));
+        reference.write_all(input).unwrap();
+        let reference = reference.finish().unwrap();
+
+        assert_eq!(encode(input, 9, false), reference);
+        assert_eq!(encode(input, 9, true), reference);
+    }
+}
+
+#[test]
+fn test_flush_and_drop() {
+    let original = sample_data();
+    let mut compressed = Vec::new();
+    {
+        let mut encoder = Bz2EncoderBuilder::new()
+            .level(1)
+            .max_in_flight_blocks(1)
+            .build(&mut compressed)
+            .unwrap();
+        let (head, tail) = original.split_at(1000);
+        encoder.write_all(head).unwrap();
+        // Flushing cuts a short block but keeps a single valid stream
+        encoder.flush().unwrap();
+        encoder.write_all(tail).unwrap();
+        // Dropping finishes the stream
+    }
+    assert_eq!(stream_count(&compressed), 1);
+
+    let mut out = Vec::new();
+    BzDecoder::new(&compressed[..])
+        .read_to_end(&mut out)
+        .unwrap();
+    assert_eq!(out, original);
+}
@@ -0,0 +1,109 @@
+use parallel_bzip2::{parallel_bzip2_cat, Bz2Decoder, CrcMismatch, Error};
+use std::io::{self, Read};
+
+const TEST_DIR: &str = "tests/fixtures";
+
+#[test]
+fn test_missing_file_is_io_error() {
+    let err = parallel_bzip2_cat(format!("{}/does-not-exist.bz2", TEST_DIR)).unwrap_err();
+    assert!(matches!(err, Error::Io(ref e) if e.kind() == io::ErrorKind::NotFound));
+}
+
+#[test]
+fn test_empty_file_is_not_bzip2() {
+    let err = parallel_bzip2_cat(format!("{}/void.bz2", TEST_DIR)).unwrap_err();
+    assert!(matches!(err, Error::NotBzip2 { byte_offset: 0 }));
+}
+
+#[test]
+fn test_non_bzip2_file_is_rejected() {
+    let err = parallel_bzip2_cat("Cargo.toml").unwrap_err();
+    assert!(matches!(err, Error::NotBzip2 { byte_offset: 0 }));
+}
+
+#[test]
+fn test_read_error_carries_typed_error() {
+    let mut decoder = Bz2Decoder::open(format!("{}/crc1.bz2", TEST_DIR)).unwrap();
+    let mut out = Vec::new();
+    let io_err = decoder.read_to_end(&mut out).unwrap_err();
+    assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+
+    let err = Error::from(io_err);
+    assert!(matches!(
+        err,
+        Error::CrcMismatch(CrcMismatch::Stream { .. })
+    ));
+    assert_eq!(err.block_index(), Some(0));
+
+    // Later reads repeat the same typed error
+    let again = Error::from(decoder.read(&mut [0; 16]).unwrap_err());
+    assert!(matches!(
+        again,
+        Error::CrcMismatch(CrcMismatch::Stream { .. })
+    ));
+    assert_eq!(again.to_string(), err.to_string());
+}
+
+#[test]
+fn test_io_error_round_trip() {
+    let original = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+    let err = Error::from(original);
+    assert!(matches!(err, Error::Io(_)));
+
+    let back = io::Error::from(err);
+    assert_eq!(back.kind(), io::ErrorKind::PermissionDenied);
+
+    let cancelled = io::Error::from(Error::Cancelled);
+    assert!(matches!(Error::from(cancelled), Error::Cancelled));
+}
+
+#[test]
+fn test_corrupt_block_is_not_reported_as_eof() {
+    // crc2.bz2 has a single corrupted block, so no data may come out before the error
+    let mut decoder = Bz2Decoder::open(format!("{}/crc2.bz2", TEST_DIR)).unwrap();
+    let mut buf = [0u8; 64];
+    let err = decoder.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    // The block itself is well-formed; only its CRC gives the corruption away
+    assert!(matches!(
+        Error::from(err),
+        Error::CrcMismatch(CrcMismatch::Block {
+            block_index: Some(0),
+            ..
+        })
+    ));
+
+    // The failure is sticky rather than turning into EOF
+    assert!(decoder.read(&mut buf).is_err());
+}
+
+#[test]
+fn test_truncated_stream_is_reported() {
+    let mut data = std::fs::read(format!("{}/rand.bz2", TEST_DIR)).unwrap();
+    data.truncate(30);
+    let mut decoder = Bz2Decoder::new(std::sync::Arc::new(data));
+    let mut out = Vec::new();
+    let err = decoder.read_to_end(&mut out).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(matches!(
+        Error::from(err),
+        Error::Truncated {
+            block_index: Some(0),
+            bit_offset: 32,
+        }
+    ));
+    assert!(out.is_empty());
+}
+
+#[test]
+fn test_trailing_garbage_is_ignored() {
+    // A complete stream followed by garbage and a truncated stream: like bzip2,
+    // decode the first stream and ignore the rest
+    let data = parallel_bzip2_cat(format!("{}/trash.bz2", TEST_DIR)).unwrap();
+    assert_eq!(data, b"TEST\n");
+
+    // Valid streams after the garbage are still decoded
+    let data = parallel_bzip2_cat(format!("{}/gap.bz2", TEST_DIR)).unwrap();
+    assert!(data.starts_with(b"TEST\n"));
+    assert_eq!(data.len(), 11);
+}
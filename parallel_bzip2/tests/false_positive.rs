@@ -0,0 +1,82 @@
+use parallel_bzip2::{
+    decompress_block, decompress_block_merging, scan_blocks, BlockRange, BlockSpan,
+};
+
+mod common;
+
+#[test]
+fn test_merging_is_noop_for_valid_block() {
+    let (_, data) = common::multi_block_bz2(250_000, 1);
+    let block = scan_blocks(&data).recv().unwrap();
+    assert_eq!(block.level, 1);
+
+    let mut out = Vec::new();
+    let span = decompress_block_merging(&data, block, &mut out).unwrap();
+
+    assert_eq!(
+        span,
+        BlockSpan {
+            start_bit: block.start_bit,
+            end_bit: block.end_bit,
+            merged: 0
+        }
+    );
+    assert_eq!(out, decompress_block(&data, block).unwrap());
+}
+
+#[test]
+fn test_merging_recovers_split_block() {
+    let (original, data) = common::multi_block_bz2(250_000, 1);
+    let blocks: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+    assert!(blocks.len() >= 2);
+    let block = blocks[0];
+
+    // Pretend the scanner found a block magic in the middle of the first block:
+    // the first half cannot decode on its own
+    let split = BlockRange {
+        end_bit: block.start_bit + (block.end_bit - block.start_bit) / 2,
+        ..block
+    };
+    assert!(decompress_block(&data, split).is_err());
+
+    let mut out = Vec::new();
+    let span = decompress_block_merging(&data, split, &mut out).unwrap();
+
+    assert_eq!(
+        span,
+        BlockSpan {
+            start_bit: block.start_bit,
+            end_bit: block.end_bit,
+            merged: 1
+        }
+    );
+    assert!(original.starts_with(&out));
+}
+
+#[test]
+fn test_merging_recovers_block_split_twice() {
+    let (original, data) = common::split_block_bz2();
+    let blocks: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+    assert_eq!(blocks.len(), 3);
+    assert!(decompress_block(&data, blocks[0]).is_err());
+
+    let mut out = Vec::new();
+    let span = decompress_block_merging(&data, blocks[0], &mut out).unwrap();
+
+    assert_eq!(span.end_bit, blocks[2].end_bit);
+    assert_eq!(span.merged, 2);
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_merging_gives_up_on_corrupt_block() {
+    let (_, mut data) = common::multi_block_bz2(250_000, 1);
+    let block = scan_blocks(&data).recv().unwrap();
+
+    // Corrupt the payload of the first block
+    let mid_byte = ((block.start_bit + block.end_bit) / 2 / 8) as usize;
+    data[mid_byte] ^= 0xFF;
+
+    let mut out = Vec::new();
+    assert!(decompress_block_merging(&data, block, &mut out).is_err());
+}
@@ -0,0 +1,123 @@
+use parallel_bzip2::{index_path, BlockIndex, Bz2Decoder, Error};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+mod common;
+
+const TEST_DIR: &str = "tests/fixtures";
+
+/// Writes `data` to a file in the temporary directory, unique to the test.
+fn temp_file(name: &str, data: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("parallel_bzip2_{}.bz2", name));
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn test_index_round_trip() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let path = temp_file("index_round_trip", &data);
+
+    let index = BlockIndex::build(&path).unwrap();
+    assert!(index.is_complete());
+    assert!(index.entries().len() > 1);
+    assert_eq!(index.decompressed_len(), Some(original.len() as u64));
+    assert_eq!(index.source().unwrap().len, data.len() as u64);
+
+    let index_file = index_path(&path);
+    assert_eq!(index_file.extension().unwrap(), "bz2idx");
+    index.save(&index_file).unwrap();
+    let loaded = BlockIndex::load(&index_file).unwrap();
+    assert_eq!(loaded, index);
+
+    // Decoding from the index gives the same blocks as scanning
+    let mut decoder = Bz2Decoder::builder()
+        .block_index(loaded)
+        .open(&path)
+        .unwrap();
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, original);
+    assert_eq!(decoder.index(), &index);
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&index_file).unwrap();
+}
+
+#[test]
+fn test_index_seeks_without_decoding_ahead() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let path = temp_file("index_seek", &data);
+    let index = BlockIndex::build(&path).unwrap();
+
+    let mut decoder = Bz2Decoder::builder()
+        .block_index(index)
+        .open(&path)
+        .unwrap();
+    assert_eq!(decoder.seek(SeekFrom::End(-10)).unwrap(), 599_990);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, original[599_990..]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_index_fixtures_match_scan() {
+    for name in ["concat", "rand", "fib", "trash", "gap", "empty"] {
+        let path = format!("{}/{}.bz2", TEST_DIR, name);
+        let expected = parallel_bzip2::parallel_bzip2_cat(&path).unwrap();
+        let index = BlockIndex::build(&path).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let mut decoder = Bz2Decoder::builder()
+            .block_index(index)
+            .build(Arc::new(data))
+            .unwrap();
+        let mut out = Vec::new();
+        decoder.read_to_end(&mut out).unwrap();
+        assert_eq!(out, expected, "{}", name);
+    }
+}
+
+#[test]
+fn test_index_rejects_other_input() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    let path = temp_file("index_other_input", &data);
+    let index = BlockIndex::build(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // Same length, different content
+    let mut changed = data.clone();
+    changed[100] ^= 0xFF;
+    let err = Bz2Decoder::builder()
+        .block_index(index.clone())
+        .build(Arc::new(changed))
+        .err();
+    assert!(matches!(err, Some(Error::InvalidIndex { .. })));
+
+    let err = Bz2Decoder::builder()
+        .block_index(index)
+        .build(Arc::new(data[..data.len() - 1].to_vec()))
+        .err();
+    assert!(matches!(err, Some(Error::InvalidIndex { .. })));
+}
+
+#[test]
+fn test_index_rejects_corrupt_file() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    let path = temp_file("index_corrupt", &data);
+    let index = BlockIndex::build(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let mut bytes = Vec::new();
+    index.write_to(&mut bytes).unwrap();
+    assert_eq!(BlockIndex::read_from(&bytes[..]).unwrap(), index);
+
+    bytes[60] ^= 1;
+    let err = BlockIndex::read_from(&bytes[..]).unwrap_err();
+    assert!(matches!(err, Error::InvalidIndex { .. }));
+    let err = BlockIndex::read_from(&b"BZh91AY&SY"[..]).unwrap_err();
+    assert!(matches!(err, Error::InvalidIndex { .. }));
+}
@@ -0,0 +1,135 @@
+use parallel_bzip2::crc::block_crc;
+use parallel_bzip2::{index_path, BlockIndex, Bz2Decoder, Error, IndexedBz2};
+use std::io::{Seek, SeekFrom};
+use std::sync::Arc;
+
+mod common;
+
+/// Builds a complete index for in-memory data by decoding it once.
+fn index_of(data: &[u8]) -> BlockIndex {
+    let mut decoder = Bz2Decoder::new(Arc::new(data.to_vec()));
+    decoder.seek(SeekFrom::End(0)).unwrap();
+    decoder.index().clone()
+}
+
+#[test]
+fn test_read_at_matches_original() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let index = index_of(&data);
+    let file = IndexedBz2::new(Arc::new(data), index).unwrap();
+    assert_eq!(file.len(), original.len() as u64);
+
+    // Within a block, across block boundaries, and running into the end
+    for (offset, len) in [
+        (0, 100),
+        (99_000, 5_000),
+        (150_000, 300_000),
+        (599_000, 4096),
+    ] {
+        let mut buf = vec![0; len];
+        let read = file.read_at(offset as u64, &mut buf).unwrap();
+        let expected = &original[offset..original.len().min(offset + len)];
+        assert_eq!(&buf[..read], expected);
+    }
+    assert_eq!(file.read_at(600_000, &mut [0; 16]).unwrap(), 0);
+    assert_eq!(file.read_at(u64::MAX, &mut [0; 16]).unwrap(), 0);
+}
+
+#[test]
+fn test_read_at_uses_cache() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let index = index_of(&data);
+    let file = IndexedBz2::new(Arc::new(data), index).unwrap();
+
+    let mut buf = [0; 10];
+    file.read_at(10, &mut buf).unwrap();
+    file.read_at(20, &mut buf).unwrap();
+    assert_eq!(buf, original[20..30]);
+    let stats = file.cache_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+
+    let file = file.cache_capacity(0);
+    file.read_at(10, &mut buf).unwrap();
+    file.read_at(10, &mut buf).unwrap();
+    assert_eq!(file.cache_stats().misses, 3);
+}
+
+#[test]
+fn test_read_at_from_many_threads() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let index = index_of(&data);
+    let file = Arc::new(
+        IndexedBz2::new(Arc::new(data), index)
+            .unwrap()
+            .cache_capacity(2),
+    );
+    let original = Arc::new(original);
+
+    let handles: Vec<_> = (0..8u64)
+        .map(|thread| {
+            let file = file.clone();
+            let original = original.clone();
+            std::thread::spawn(move || {
+                for i in 0..20u64 {
+                    let offset = ((thread * 7919 + i * 31_337) % 590_000) as usize;
+                    let mut buf = vec![0; 10_000];
+                    let read = file.read_at(offset as u64, &mut buf).unwrap();
+                    assert_eq!(&buf[..read], &original[offset..offset + read]);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+#[test]
+fn test_open_uses_or_rebuilds_index_file() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let path = std::env::temp_dir().join("parallel_bzip2_indexed_open.bz2");
+    std::fs::write(&path, &data).unwrap();
+
+    // Without an index file the index is built
+    let file = IndexedBz2::open(&path).unwrap();
+    assert!(file.index().is_complete());
+    file.index().save(index_path(&path)).unwrap();
+
+    let file = IndexedBz2::open(&path).unwrap();
+    let mut buf = vec![0; 1000];
+    file.read_at(300_000, &mut buf).unwrap();
+    assert_eq!(buf, original[300_000..301_000]);
+
+    std::fs::remove_file(index_path(&path)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_new_rejects_incomplete_index() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    let err = IndexedBz2::new(Arc::new(data), BlockIndex::new()).err();
+    assert!(matches!(err, Some(Error::InvalidIndex { .. })));
+}
+
+#[test]
+fn test_read_at_rejects_wrong_block_length() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let mut file = Vec::new();
+    index_of(&data).write_to(&mut file).unwrap();
+
+    // Lengthen the last block by 1000 bytes, with a valid checksum
+    let len_field = file.len() - 4 - 60 + 40;
+    let len = u64::from_le_bytes(file[len_field..len_field + 8].try_into().unwrap());
+    file[len_field..len_field + 8].copy_from_slice(&(len + 1000).to_le_bytes());
+    let checksum = block_crc(&file[..file.len() - 4]);
+    let checksum_field = file.len() - 4;
+    file[checksum_field..].copy_from_slice(&checksum.to_le_bytes());
+
+    let index = BlockIndex::read_from(&file[..]).unwrap();
+    let file = IndexedBz2::new(Arc::new(data), index).unwrap();
+    let mut buf = vec![0; 2000];
+    assert!(matches!(
+        file.read_at(original.len() as u64 - 1000, &mut buf),
+        Err(Error::InvalidIndex { .. })
+    ));
+}
@@ -0,0 +1,80 @@
+use parallel_bzip2::crc::combine;
+use parallel_bzip2::{GapKind, Scanner, StreamMarker};
+
+const TEST_DIR: &str = "tests/fixtures";
+
+fn layout_of(name: &str) -> (Vec<u8>, Vec<StreamMarker>) {
+    let data = std::fs::read(format!("{}/{}.bz2", TEST_DIR, name)).unwrap();
+    let layout = Scanner::new().scan_layout(&data);
+    (data, layout)
+}
+
+#[test]
+fn test_layout_combined_crcs_match_blocks() {
+    let (_, layout) = layout_of("concat");
+    let mut streams = 0;
+    let mut combined = 0;
+    for marker in layout {
+        match marker {
+            StreamMarker::StreamStart { level, .. } => {
+                assert_eq!(level, 9);
+                streams += 1;
+                combined = 0;
+            }
+            StreamMarker::Block { stored_crc, .. } => {
+                combined = combine(combined, stored_crc.unwrap());
+            }
+            StreamMarker::StreamEnd { combined_crc, .. } => {
+                assert_eq!(combined_crc, Some(combined));
+            }
+            StreamMarker::Gap { .. } => panic!("unexpected gap in {:?}", marker),
+        }
+    }
+    assert_eq!(streams, 2);
+}
+
+#[test]
+fn test_layout_reports_garbage_between_streams() {
+    let (data, layout) = layout_of("trash");
+    let gaps: Vec<_> = layout
+        .iter()
+        .filter_map(|marker| match *marker {
+            StreamMarker::Gap {
+                start_byte,
+                end_byte,
+                kind,
+            } => Some((start_byte, end_byte, kind)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(gaps.len(), 1);
+    let (start, end, kind) = gaps[0];
+    assert_eq!(kind, GapKind::Garbage);
+    assert_eq!(&data[start as usize..end as usize], b"TRASH\n");
+
+    // The stream after the garbage is still found
+    let starts = layout
+        .iter()
+        .filter(|marker| matches!(marker, StreamMarker::StreamStart { .. }))
+        .count();
+    assert_eq!(starts, 2);
+}
+
+#[test]
+fn test_layout_empty_stream() {
+    let (_, layout) = layout_of("empty");
+    assert_eq!(
+        layout,
+        vec![
+            StreamMarker::StreamStart {
+                byte_offset: 0,
+                level: 9
+            },
+            StreamMarker::StreamEnd {
+                bit_offset: 32,
+                combined_crc: Some(0)
+            },
+        ]
+    );
+    assert!(Scanner::new().scan_layout(&[]).is_empty());
+}
@@ -0,0 +1,41 @@
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use parallel_bzip2::{decompress_block, parallel_bzip2_cat, scan_blocks, BlockRange};
+use std::io::Write;
+
+const TEST_DIR: &str = "tests/fixtures";
+
+#[test]
+fn test_scan_reports_stream_level() {
+    for level in [1, 5, 9] {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::new(level));
+        encoder.write_all(b"level test").unwrap();
+        let data = encoder.finish().unwrap();
+
+        let blocks: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].level, level as u8);
+        assert_eq!(decompress_block(&data, blocks[0]).unwrap(), b"level test");
+    }
+}
+
+#[test]
+fn test_block_larger_than_level_is_rejected() {
+    // overrun.bz2 declares BZh1 but holds a block larger than 100k
+    let path = format!("{}/overrun.bz2", TEST_DIR);
+    let data = std::fs::read(&path).unwrap();
+    let block = scan_blocks(&data).recv().unwrap();
+    assert_eq!(block.level, 1);
+
+    assert!(decompress_block(&data, block).is_err());
+    assert!(parallel_bzip2_cat(&path).is_err());
+}
+
+#[test]
+fn test_invalid_level_is_rejected() {
+    let path = format!("{}/rand.bz2", TEST_DIR);
+    let data = std::fs::read(path).unwrap();
+    let block = scan_blocks(&data).recv().unwrap();
+
+    assert!(decompress_block(&data, BlockRange { level: 0, ..block }).is_err());
+}
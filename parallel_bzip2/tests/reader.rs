@@ -0,0 +1,118 @@
+use parallel_bzip2::{scan_blocks, Bz2Decoder, Error};
+use std::io::{self, Read};
+
+mod common;
+
+const TEST_DIR: &str = "tests/fixtures";
+
+/// Hands out data in small pieces like a pipe, then optionally fails.
+struct Trickle {
+    data: Vec<u8>,
+    pos: usize,
+    piece: usize,
+    fail_at_end: bool,
+}
+
+impl Trickle {
+    fn new(data: Vec<u8>, piece: usize) -> Self {
+        Self {
+            data,
+            pos: 0,
+            piece,
+            fail_at_end: false,
+        }
+    }
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.data.len() && self.fail_at_end {
+            return Err(io::Error::new(io::ErrorKind::ConnectionReset, "gone"));
+        }
+        let len = buf.len().min(self.piece).min(self.data.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+fn decode_reader<R: Read + Send + 'static>(reader: R) -> io::Result<Vec<u8>> {
+    let mut decoder = Bz2Decoder::builder()
+        .scan_chunk_size(4096)
+        .build_reader(reader)
+        .unwrap();
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+#[test]
+fn test_reader_matches_original() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let out = decode_reader(Trickle::new(data, 1000)).unwrap();
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_reader_merges_block_split_twice() {
+    let (original, data) = common::split_block_bz2();
+    assert_eq!(scan_blocks(&data).iter().count(), 3);
+    let out = decode_reader(Trickle::new(data, 1000)).unwrap();
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_reader_default_chunk_size() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let mut decoder = Bz2Decoder::from_reader(io::Cursor::new(data)).unwrap();
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_reader_fixtures_match_mmap() {
+    for name in ["concat", "rand", "fib", "trash", "gap", "empty"] {
+        let path = format!("{}/{}.bz2", TEST_DIR, name);
+        let expected = parallel_bzip2::parallel_bzip2_cat(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        let out = decode_reader(Trickle::new(data, 7)).unwrap();
+        assert_eq!(out, expected, "{}", name);
+    }
+}
+
+#[test]
+fn test_reader_rejects_non_bzip2() {
+    let err = Bz2Decoder::from_reader(io::Cursor::new(b"hello world".to_vec())).err();
+    assert!(matches!(err, Some(Error::NotBzip2 { byte_offset: 0 })));
+
+    let err = Bz2Decoder::from_reader(io::empty()).err();
+    assert!(matches!(err, Some(Error::NotBzip2 { byte_offset: 0 })));
+}
+
+#[test]
+fn test_reader_truncated_input() {
+    let (_, data) = common::multi_block_bz2(600_000, 1);
+    let cut = data[..data.len() / 2].to_vec();
+    let err = decode_reader(Trickle::new(cut, 1000)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    assert!(matches!(Error::from(err), Error::Truncated { .. }));
+}
+
+#[test]
+fn test_reader_error_after_decoded_data() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let mut reader = Trickle::new(data, 1000);
+    reader.fail_at_end = true;
+
+    let mut decoder = Bz2Decoder::builder()
+        .scan_chunk_size(4096)
+        .build_reader(reader)
+        .unwrap();
+    let mut out = Vec::new();
+    let err = decoder.read_to_end(&mut out).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    // Blocks completed before the failure are still returned
+    assert!(!out.is_empty());
+    assert!(original.starts_with(&out));
+}
@@ -0,0 +1,160 @@
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use parallel_bzip2::{
+    decompress_block, recover, scan_blocks, BlockRange, FillMode, RecoveryBuilder,
+};
+use std::io::Write;
+
+mod common;
+
+const TEST_DIR: &str = "tests/fixtures";
+
+/// Pseudo-random data compressed at level 1 into four blocks, and those blocks.
+fn multi_block_bz2() -> (Vec<u8>, Vec<u8>, Vec<BlockRange>) {
+    let (original, data) = common::multi_block_bz2(350_000, 1);
+    let blocks: Vec<BlockRange> = scan_blocks(&data).iter().collect();
+    assert_eq!(blocks.len(), 4);
+    (original, data, blocks)
+}
+
+/// Decompressed contents of the given blocks, concatenated.
+fn blocks_data(data: &[u8], blocks: &[BlockRange]) -> Vec<u8> {
+    blocks
+        .iter()
+        .flat_map(|block| decompress_block(data, *block).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_recover_intact_data() {
+    let (original, data, _) = multi_block_bz2();
+    let mut out = Vec::new();
+    let report = recover(&data, &mut out).unwrap();
+
+    assert!(report.is_complete());
+    assert_eq!(report.recovered_blocks, 4);
+    assert_eq!(report.recovered_bytes, original.len() as u64);
+    assert_eq!(report.output_len, original.len() as u64);
+    assert_eq!(out, original);
+}
+
+#[test]
+fn test_recover_fixtures() {
+    for name in ["concat", "rand", "fib", "gap", "empty", "32767", "repet"] {
+        let path = format!("{}/{}.bz2", TEST_DIR, name);
+        let expected = parallel_bzip2::parallel_bzip2_cat(&path).unwrap();
+        let data = std::fs::read(&path).unwrap();
+
+        let mut out = Vec::new();
+        let report = recover(&data, &mut out).unwrap();
+        assert!(report.is_complete(), "{}: {:?}", name, report.lost);
+        assert_eq!(out, expected, "{}", name);
+    }
+}
+
+#[test]
+fn test_recover_skips_corrupt_block() {
+    let (original, mut data, blocks) = multi_block_bz2();
+    // Damage the middle of the second block
+    let middle = ((blocks[1].start_bit + blocks[1].end_bit) / 16) as usize;
+    for byte in &mut data[middle..middle + 64] {
+        *byte ^= 0x5A;
+    }
+
+    let mut out = Vec::new();
+    let report = RecoveryBuilder::new()
+        .num_threads(2)
+        .recover(&data, &mut out)
+        .unwrap();
+
+    assert_eq!(report.recovered_blocks, 3);
+    assert_eq!(report.lost.len(), 1);
+    let lost = &report.lost[0];
+    assert_eq!(lost.block_index, 1);
+    assert_eq!(
+        (lost.start_bit, lost.end_bit),
+        (blocks[1].start_bit, blocks[1].end_bit)
+    );
+
+    let first_len = decompress_block(&data, blocks[0]).unwrap().len() as u64;
+    assert_eq!(lost.output_offset, first_len);
+    assert_eq!(lost.estimated_offset, first_len);
+    // Random data compresses evenly, so the estimate is close to the real size
+    let real_len = (original.len() as u64 - report.recovered_bytes) as f64;
+    assert!((lost.estimated_len as f64 - real_len).abs() < real_len * 0.05);
+
+    let mut expected = blocks_data(&data, &blocks[..1]);
+    expected.extend(blocks_data(&data, &blocks[2..]));
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn test_recover_zero_fills_lost_data() {
+    let (_, mut data, blocks) = multi_block_bz2();
+    let middle = ((blocks[2].start_bit + blocks[2].end_bit) / 16) as usize;
+    data[middle] ^= 0xFF;
+
+    let mut out = Vec::new();
+    let report = RecoveryBuilder::new()
+        .fill(FillMode::Zeros)
+        .recover(&data, &mut out)
+        .unwrap();
+
+    assert_eq!(report.lost.len(), 1);
+    let lost = &report.lost[0];
+    assert_eq!(
+        report.output_len,
+        report.recovered_bytes + report.estimated_lost_bytes()
+    );
+    assert_eq!(out.len() as u64, report.output_len);
+
+    let start = lost.output_offset as usize;
+    let end = start + lost.estimated_len as usize;
+    assert_eq!(out[..start], blocks_data(&data, &blocks[..2])[..]);
+    assert!(out[start..end].iter().all(|&byte| byte == 0));
+    assert_eq!(out[end..], blocks_data(&data, &blocks[3..])[..]);
+}
+
+#[test]
+fn test_recover_reports_block_with_damaged_magic() {
+    let (_, mut data, blocks) = multi_block_bz2();
+    // Without its magic, the third block is scanned as part of the second one,
+    // which still decodes on its own
+    let magic = blocks[2].start_bit;
+    let byte = (magic / 8) as usize + 2;
+    data[byte] ^= 0xFF;
+
+    let mut out = Vec::new();
+    let report = recover(&data, &mut out).unwrap();
+
+    assert_eq!(report.recovered_blocks, 3);
+    assert_eq!(report.lost.len(), 1);
+    let lost = &report.lost[0];
+    assert_eq!(lost.block_index, 1);
+    assert_eq!((lost.start_bit, lost.end_bit), (magic, blocks[2].end_bit));
+
+    let mut expected = blocks_data(&data, &blocks[..2]);
+    expected.extend(blocks_data(&data, &blocks[3..]));
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn test_recover_ignores_damaged_stream_trailer() {
+    // Two streams; the end-of-stream marker of the first is damaged, so its last
+    // block runs into the second stream
+    let mut data = Vec::new();
+    let mut original = Vec::new();
+    for part in [&b"first stream "[..], b"second stream"] {
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::new(9));
+        encoder.write_all(part).unwrap();
+        data.extend(encoder.finish().unwrap());
+        original.extend_from_slice(part);
+    }
+    let eos = scan_blocks(&data).recv().unwrap().end_bit;
+    data[(eos / 8) as usize + 1] ^= 0xFF;
+
+    let mut out = Vec::new();
+    let report = recover(&data, &mut out).unwrap();
+    assert!(report.is_complete());
+    assert_eq!(out, original);
+}
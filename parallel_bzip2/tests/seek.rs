@@ -0,0 +1,88 @@
+use parallel_bzip2::Bz2Decoder;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::Arc;
+
+mod common;
+
+fn read_exactly(decoder: &mut Bz2Decoder, len: usize) -> Vec<u8> {
+    let mut out = vec![0; len];
+    decoder.read_exact(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_seek_matches_original() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let mut decoder = Bz2Decoder::new(Arc::new(data));
+
+    // Forward past blocks that have not been decoded yet, then back into indexed ones
+    for offset in [250_000, 10, 599_990, 123_456, 0, 400_000] {
+        assert_eq!(decoder.seek(SeekFrom::Start(offset)).unwrap(), offset);
+        let len = 10.min(original.len() - offset as usize);
+        let offset = offset as usize;
+        assert_eq!(
+            read_exactly(&mut decoder, len),
+            original[offset..offset + len]
+        );
+    }
+
+    assert_eq!(decoder.seek(SeekFrom::Current(-20)).unwrap(), 399_990);
+    assert_eq!(read_exactly(&mut decoder, 20), original[399_990..400_010]);
+
+    let mut rest = Vec::new();
+    decoder.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, original[400_010..]);
+}
+
+#[test]
+fn test_seek_from_end_completes_index() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let mut decoder = Bz2Decoder::new(Arc::new(data));
+
+    assert_eq!(decoder.seek(SeekFrom::End(-5)).unwrap(), 599_995);
+    assert_eq!(read_exactly(&mut decoder, 5), original[599_995..]);
+
+    let index = decoder.index();
+    assert!(index.is_complete());
+    assert!(index.entries().len() > 1);
+    assert_eq!(index.decompressed_len(), Some(original.len() as u64));
+
+    // Past the end is clamped, and reads there return nothing
+    assert_eq!(decoder.seek(SeekFrom::Start(1 << 40)).unwrap(), 600_000);
+    assert_eq!(decoder.read(&mut [0; 16]).unwrap(), 0);
+    assert_eq!(
+        decoder
+            .seek(SeekFrom::Current(-700_000))
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn test_seek_across_streams() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let concatenated = [data.clone(), data].concat();
+    let mut decoder = Bz2Decoder::new(Arc::new(concatenated));
+
+    decoder.seek(SeekFrom::Start(900_000)).unwrap();
+    assert_eq!(read_exactly(&mut decoder, 100), original[300_000..300_100]);
+
+    // Back into the first stream and straight through the stream boundary
+    decoder.seek(SeekFrom::Start(300_000)).unwrap();
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out).unwrap();
+    assert_eq!(out, [&original[300_000..], &original[..]].concat());
+}
+
+#[test]
+fn test_seek_reader_source() {
+    let (original, data) = common::multi_block_bz2(600_000, 1);
+    let mut decoder = Bz2Decoder::from_reader(io::Cursor::new(data)).unwrap();
+
+    decoder.seek(SeekFrom::Start(300_000)).unwrap();
+    assert_eq!(read_exactly(&mut decoder, 10), original[300_000..300_010]);
+
+    let err = decoder.seek(SeekFrom::Start(0)).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+}
@@ -0,0 +1,141 @@
+#![cfg(feature = "seekable")]
+
+use parallel_bzip2::{Error, SeekTable, SeekableZstd};
+use std::sync::Arc;
+use zstd::bulk::Compressor;
+
+/// Pseudo-random data, so that frames do not compress to almost nothing.
+fn original() -> Vec<u8> {
+    let mut state = 0x2545_F491u32;
+    (0..300_000)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 28) as u8
+        })
+        .collect()
+}
+
+/// Compresses `data` in chunks of `chunk` bytes, one frame each, followed by a
+/// seek table.
+fn seekable_zstd(data: &[u8], chunk: usize, checksums: bool) -> Vec<u8> {
+    let mut compressor = Compressor::new(3).unwrap();
+    compressor.include_checksum(checksums).unwrap();
+    let mut out = Vec::new();
+    let mut table = SeekTable::new(checksums);
+    for chunk in data.chunks(chunk) {
+        let frame = compressor.compress(chunk).unwrap();
+        table.push_frame(&frame).unwrap();
+        out.extend_from_slice(&frame);
+    }
+    table.write_to(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn test_seek_table_roundtrip() {
+    let original = original();
+    for checksums in [false, true] {
+        let data = seekable_zstd(&original, 70_000, checksums);
+        let table = SeekTable::parse(&data).unwrap();
+        assert_eq!(table.len(), 5);
+        assert_eq!(table.has_checksums(), checksums);
+        assert_eq!(table.decompressed_len(), original.len() as u64);
+        assert_eq!(
+            table.compressed_len() + table.encoded_len() as u64,
+            data.len() as u64
+        );
+        assert_eq!(table.find(69_999).unwrap().index, 0);
+        assert_eq!(table.find(70_000).unwrap().index, 1);
+        assert!(table.find(original.len() as u64).is_none());
+        assert_eq!(
+            table.entries()[4].checksum.is_some(),
+            checksums,
+            "checksums are recorded only when asked for"
+        );
+    }
+}
+
+#[test]
+fn test_plain_zstd_decoders_skip_the_table() {
+    let original = original();
+    let data = seekable_zstd(&original, 70_000, true);
+    assert_eq!(zstd::decode_all(&data[..]).unwrap(), original);
+}
+
+#[test]
+fn test_read_at_matches_original() {
+    let original = original();
+    for checksums in [false, true] {
+        let file = SeekableZstd::new(Arc::new(seekable_zstd(&original, 70_000, checksums)))
+            .unwrap()
+            .cache_capacity(2);
+        assert_eq!(file.len(), original.len() as u64);
+
+        // Within a frame, across frame boundaries, and running into the end
+        for (offset, len) in [
+            (0, 100),
+            (69_000, 5_000),
+            (100_000, 150_000),
+            (290_000, 20_000),
+        ] {
+            let mut buf = vec![0; len];
+            let read = file.read_at(offset as u64, &mut buf).unwrap();
+            let expected = &original[offset..original.len().min(offset + len)];
+            assert_eq!(&buf[..read], expected);
+        }
+        assert_eq!(
+            file.read_at(original.len() as u64, &mut [0; 10]).unwrap(),
+            0
+        );
+        assert!(file.cache_stats().hits > 0);
+    }
+}
+
+#[test]
+fn test_empty_table() {
+    let data = seekable_zstd(&[], 1, true);
+    let file = SeekableZstd::new(Arc::new(data)).unwrap();
+    assert!(file.is_empty());
+    assert!(file.table().is_empty());
+}
+
+#[test]
+fn test_corrupted_frame_is_reported() {
+    let original = original();
+    let mut data = seekable_zstd(&original, 70_000, true);
+    let table = SeekTable::parse(&data).unwrap();
+
+    // Changing the table's checksum of frame 2 leaves the frame itself valid
+    let entry_offset = data.len() - table.encoded_len() + 8 + 2 * 12 + 8;
+    data[entry_offset] ^= 1;
+    let file = SeekableZstd::new(Arc::new(data)).unwrap();
+    let mut buf = vec![0; 10];
+    file.read_at(0, &mut buf).unwrap();
+    match file.read_at(150_000, &mut buf) {
+        Err(Error::ZstdFrame { frame_index, .. }) => assert_eq!(frame_index, 2),
+        other => panic!("expected a frame error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_invalid_tables_are_rejected() {
+    let original = original();
+    let data = seekable_zstd(&original, 70_000, false);
+
+    // Plain zstd, a truncated file, and frames missing in front of the table
+    let mut compressor = Compressor::new(3).unwrap();
+    let plain = compressor.compress(&original).unwrap();
+    let first_frame = SeekTable::parse(&data).unwrap().entries()[0].compressed_size as usize;
+    for bad in [&plain[..], &data[..data.len() - 1], &data[first_frame..]] {
+        assert!(matches!(
+            SeekableZstd::new(Arc::new(bad.to_vec())),
+            Err(Error::InvalidSeekTable { .. })
+        ));
+    }
+
+    // Checksums need frames that carry them
+    assert!(matches!(
+        SeekTable::new(true).push_frame(&plain),
+        Err(Error::ZstdFrame { frame_index: 0, .. })
+    ));
+}